@@ -0,0 +1,127 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::BTreeMap,
+    env::args,
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write as _},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures_util::StreamExt;
+use nl_wireguard::{WireguardDeviceStats, WireguardWatcher};
+
+const INTERVAL: Duration = Duration::from_secs(15);
+const LISTEN_ADDR: &str = "127.0.0.1:9586";
+
+// Serve latest statistics of given interfaces in Prometheus text format at
+// http://127.0.0.1:9586/metrics
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let argv: Vec<String> = args().collect();
+    if argv.len() < 2 {
+        eprintln!("Usage: exporter <ifname>...");
+        return;
+    }
+
+    let (connection, handle, _) = nl_wireguard::new_connection().unwrap();
+    tokio::spawn(connection);
+
+    let watcher = WireguardWatcher::new(handle, INTERVAL);
+    for iface_name in &argv[1..] {
+        watcher.add(iface_name).unwrap();
+    }
+
+    let latest: Arc<Mutex<BTreeMap<String, WireguardDeviceStats>>> =
+        Arc::default();
+    let latest_clone = latest.clone();
+    std::thread::spawn(move || serve(latest_clone));
+
+    let mut stream = std::pin::pin!(watcher.stream());
+    while let Some((iface_name, result)) = stream.next().await {
+        match result {
+            Ok(stats) => {
+                latest.lock().unwrap().insert(iface_name, stats);
+            }
+            Err(e) => {
+                log::warn!("Failed to query {iface_name}: {e}");
+                latest.lock().unwrap().remove(&iface_name);
+            }
+        }
+    }
+}
+
+fn serve(latest: Arc<Mutex<BTreeMap<String, WireguardDeviceStats>>>) {
+    let listener = TcpListener::bind(LISTEN_ADDR).unwrap();
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        let mut request_line = String::new();
+        if BufReader::new(&stream)
+            .read_line(&mut request_line)
+            .is_err()
+        {
+            continue;
+        }
+        let response = if request_line.starts_with("GET /metrics ") {
+            let body = render(&latest.lock().unwrap());
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; \
+                 version=0.0.4\r\nContent-Length: {}\r\nConnection: \
+                 close\r\n\r\n{body}",
+                body.len()
+            )
+        } else {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: \
+             close\r\n\r\n"
+                .to_string()
+        };
+        stream.write_all(response.as_bytes()).ok();
+    }
+}
+
+fn render(latest: &BTreeMap<String, WireguardDeviceStats>) -> String {
+    let mut out = String::new();
+    let metrics: [(&str, &str, &str); 3] = [
+        (
+            "wireguard_received_bytes_total",
+            "counter",
+            "Bytes received",
+        ),
+        ("wireguard_sent_bytes_total", "counter", "Bytes sent"),
+        (
+            "wireguard_latest_handshake_seconds",
+            "gauge",
+            "UNIX time of latest handshake, 0 if never",
+        ),
+    ];
+    for (index, (name, kind, help)) in metrics.iter().enumerate() {
+        writeln!(out, "# HELP {name} {help}").ok();
+        writeln!(out, "# TYPE {name} {kind}").ok();
+        for (iface_name, stats) in latest {
+            for peer in &stats.peers {
+                let value = match index {
+                    0 => peer.rx_bytes,
+                    1 => peer.tx_bytes,
+                    _ => peer
+                        .last_handshake
+                        .map(|t| t.as_secs())
+                        .unwrap_or_default(),
+                };
+                writeln!(
+                    out,
+                    "{name}{{interface=\"{iface_name}\",public_key=\"{}\"}} \
+                     {value}",
+                    peer.public_key
+                )
+                .ok();
+            }
+        }
+    }
+    out
+}
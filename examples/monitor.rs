@@ -0,0 +1,72 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    env::args,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::StreamExt;
+use nl_wireguard::{WireguardHandshakeMonitor, WireguardWatcher};
+
+const INTERVAL: Duration = Duration::from_secs(2);
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(180);
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let argv: Vec<String> = args().collect();
+    if argv.len() < 2 {
+        eprintln!("Usage: monitor <ifname>");
+        return;
+    }
+    let iface_name = argv[1].as_str();
+
+    let (connection, handle, _) = nl_wireguard::new_connection().unwrap();
+    tokio::spawn(connection);
+
+    let watcher = WireguardWatcher::new(handle, INTERVAL).with_history(16);
+    watcher.add(iface_name).unwrap();
+
+    let mut alerts = Box::pin(watcher.handshake_alerts(
+        WireguardHandshakeMonitor::new().watch_all(HANDSHAKE_TIMEOUT),
+    ));
+    tokio::spawn(async move {
+        while let Some(alert) = alerts.next().await {
+            println!("ALERT {alert:?}");
+        }
+    });
+
+    let mut stream = std::pin::pin!(watcher.stream());
+    while let Some((iface_name, result)) = stream.next().await {
+        let stats = match result {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("{iface_name}: {e}");
+                continue;
+            }
+        };
+        let history = watcher.history(&iface_name).unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        println!("{iface_name}:");
+        for peer in &stats.peers {
+            let handshake = match peer.last_handshake {
+                Some(t) => format!("{}s ago", now.saturating_sub(t).as_secs()),
+                None => "never".to_string(),
+            };
+            let rate = history
+                .as_ref()
+                .and_then(|h| h.rate_over(&peer.public_key, RATE_WINDOW));
+            let (rx, tx) = rate
+                .map(|r| (r.rx_bytes_per_sec, r.tx_bytes_per_sec))
+                .unwrap_or_default();
+            println!(
+                "  {} handshake: {handshake}, rx: {rx:.0} B/s, tx: {tx:.0} B/s",
+                peer.public_key
+            );
+        }
+    }
+}
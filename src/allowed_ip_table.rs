@@ -0,0 +1,218 @@
+// SPDX-License-Identifier: MIT
+
+use std::{collections::BTreeMap, net::IpAddr};
+
+use crate::{peer_parsed::u128_to_ip, WireguardIpAddress, WireguardParsed};
+
+/// Longest prefix match table of allowed IPs to peer public key, answering
+/// which peer handles a destination like the cryptokey routing of kernel.
+///
+/// Lookup costs `O(log n)` for each distinct prefix length in use.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct WireguardAllowedIpTable {
+    // Indexed by (is_ipv4, prefix_length), holding network address to
+    // base64 encoded public key of peer.
+    prefixes: BTreeMap<(bool, u8), BTreeMap<u128, String>>,
+}
+
+impl From<&WireguardParsed> for WireguardAllowedIpTable {
+    /// Like kernel, the allowed IP listed in multiple peers is owned by the
+    /// last one. Peers without public key are ignored.
+    fn from(config: &WireguardParsed) -> Self {
+        let mut ret = Self::default();
+        for (peer, ip) in config.iter_allowed_ips() {
+            if let Some(public_key) = peer.public_key.as_deref() {
+                ret.insert(ip, public_key);
+            }
+        }
+        ret
+    }
+}
+
+impl WireguardAllowedIpTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Route `ip` to peer of specified public key, return the public key of
+    /// previous owner.
+    pub fn insert(
+        &mut self,
+        ip: &WireguardIpAddress,
+        public_key: &str,
+    ) -> Option<String> {
+        self.prefixes
+            .entry((ip.ip_addr.is_ipv4(), ip.prefix_length))
+            .or_default()
+            .insert(ip.range().0, public_key.to_string())
+    }
+
+    /// Return the public key of peer owning removed `ip`.
+    pub fn remove(&mut self, ip: &WireguardIpAddress) -> Option<String> {
+        let index = (ip.ip_addr.is_ipv4(), ip.prefix_length);
+        let nets = self.prefixes.get_mut(&index)?;
+        let ret = nets.remove(&ip.range().0);
+        if nets.is_empty() {
+            self.prefixes.remove(&index);
+        }
+        ret
+    }
+
+    /// Public key of peer with longest prefix matching `ip`
+    pub fn lookup(&self, ip: IpAddr) -> Option<&str> {
+        let is_ipv4 = ip.is_ipv4();
+        self.prefixes
+            .range((is_ipv4, 0)..=(is_ipv4, u8::MAX))
+            .rev()
+            .find_map(|((_, prefix_length), nets)| {
+                let net = WireguardIpAddress {
+                    prefix_length: *prefix_length,
+                    ip_addr: ip,
+                    flags: None,
+                };
+                nets.get(&net.range().0).map(String::as_str)
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.prefixes.values().map(BTreeMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+
+    /// All allowed IPs and the public key of their peers, IPv6 first,
+    /// shorter prefix first.
+    pub fn iter(&self) -> impl Iterator<Item = (WireguardIpAddress, &str)> {
+        self.prefixes
+            .iter()
+            .flat_map(|((is_ipv4, prefix_length), nets)| {
+                nets.iter().map(move |(addr, public_key)| {
+                    (
+                        WireguardIpAddress {
+                            prefix_length: *prefix_length,
+                            ip_addr: u128_to_ip(*addr, *is_ipv4),
+                            flags: None,
+                        },
+                        public_key.as_str(),
+                    )
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WireguardPeerParsed;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn table(entries: &[(&str, &str)]) -> WireguardAllowedIpTable {
+        let mut ret = WireguardAllowedIpTable::new();
+        for (net, public_key) in entries {
+            ret.insert(&net.parse().unwrap(), public_key);
+        }
+        ret
+    }
+
+    #[test]
+    fn lookup_longest_prefix() {
+        let table = table(&[
+            ("0.0.0.0/0", "default"),
+            ("::/0", "default6"),
+            ("10.0.0.0/8", "site"),
+            ("10.1.0.0/16", "subnet"),
+            ("10.1.0.1/32", "host"),
+            ("fd00::/16", "site6"),
+            ("fd00::1/128", "host6"),
+        ]);
+        let cases = [
+            ("192.0.2.1", "default"),
+            ("10.2.0.1", "site"),
+            ("10.1.255.255", "subnet"),
+            ("10.1.0.1", "host"),
+            ("2001:db8::1", "default6"),
+            ("fd00::2", "site6"),
+            ("fd00::1", "host6"),
+        ];
+        for (addr, expected) in cases {
+            assert_eq!(table.lookup(ip(addr)), Some(expected), "{addr}");
+        }
+    }
+
+    #[test]
+    fn lookup_family_mismatch() {
+        let table = table(&[("0.0.0.0/0", "v4")]);
+        assert_eq!(table.lookup(ip("::")), None);
+        assert_eq!(table.lookup(ip("::ffff:10.0.0.1")), None);
+        let table = self::table(&[("::/0", "v6")]);
+        assert_eq!(table.lookup(ip("0.0.0.0")), None);
+        assert_eq!(WireguardAllowedIpTable::new().lookup(ip("10.0.0.1")), None);
+    }
+
+    #[test]
+    fn insert_same_network_replaces_owner() {
+        let mut table = table(&[("10.0.0.0/8", "first")]);
+        // Host bits are ignored
+        assert_eq!(
+            table.insert(&"10.1.2.3/8".parse().unwrap(), "second"),
+            Some("first".to_string())
+        );
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.lookup(ip("10.0.0.1")), Some("second"));
+    }
+
+    #[test]
+    fn remove_falls_back_to_shorter_prefix() {
+        let mut table =
+            table(&[("10.0.0.0/8", "site"), ("10.1.0.0/16", "subnet")]);
+        assert_eq!(
+            table.remove(&"10.1.0.0/16".parse().unwrap()),
+            Some("subnet".to_string())
+        );
+        assert_eq!(table.lookup(ip("10.1.0.1")), Some("site"));
+        assert_eq!(table.remove(&"10.1.0.0/16".parse().unwrap()), None);
+        table.remove(&"10.0.0.0/8".parse().unwrap());
+        assert!(table.is_empty());
+        assert_eq!(table.len(), 0);
+    }
+
+    #[test]
+    fn from_config_last_peer_wins() {
+        let peer = |public_key: &str, net: &str| WireguardPeerParsed {
+            public_key: Some(public_key.to_string()),
+            allowed_ips: Some(vec![net.parse().unwrap()]),
+            ..Default::default()
+        };
+        let config = WireguardParsed::new("wg0").with_peers([
+            peer("first", "10.0.0.0/8"),
+            peer("last", "10.0.0.0/8"),
+            WireguardPeerParsed {
+                public_key: None,
+                ..peer("", "10.1.0.0/16")
+            },
+        ]);
+        let table = WireguardAllowedIpTable::from(&config);
+        // Peer without public key is ignored
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.lookup(ip("10.1.0.1")), Some("last"));
+    }
+
+    #[test]
+    fn iter_order() {
+        let table = table(&[
+            ("10.1.0.0/16", "b"),
+            ("10.0.0.0/8", "a"),
+            ("fd00::/16", "c"),
+        ]);
+        let entries: Vec<String> = table
+            .iter()
+            .map(|(ip, public_key)| format!("{ip} {public_key}"))
+            .collect();
+        assert_eq!(entries, ["fd00::/16 c", "10.0.0.0/8 a", "10.1.0.0/16 b"]);
+    }
+}
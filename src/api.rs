@@ -0,0 +1,55 @@
+// SPDX-License-Identifier: MIT
+
+use std::{future::Future, pin::Pin};
+
+use crate::{WireguardError, WireguardHandle, WireguardParsed};
+
+/// Boxed future returned by [DynWireguardApi].
+pub type WireguardBoxFuture<'a, T> =
+    Pin<Box<dyn Future<Output = Result<T, WireguardError>> + Send + 'a>>;
+
+/// Object safe form of the core operations of [WireguardHandle], so
+/// applications can hold `Box<dyn DynWireguardApi>` and inject mock
+/// implementation or wrap the handle with decorators (e.g. caching,
+/// metrics).
+///
+/// ```no_run
+/// use nl_wireguard::DynWireguardApi;
+///
+/// async fn listen_port(
+///     api: &mut dyn DynWireguardApi,
+///     iface_name: &str,
+/// ) -> Result<Option<u16>, nl_wireguard::WireguardError> {
+///     Ok(api.get_by_name(iface_name).await?.listen_port)
+/// }
+/// ```
+pub trait DynWireguardApi: Send {
+    /// See [WireguardHandle::get_by_name()].
+    fn get_by_name<'a>(
+        &'a mut self,
+        iface_name: &'a str,
+    ) -> WireguardBoxFuture<'a, WireguardParsed>;
+
+    /// See [WireguardHandle::set()].
+    fn set(&mut self, config: WireguardParsed) -> WireguardBoxFuture<'_, ()>;
+
+    /// See [WireguardHandle::list_all()].
+    fn list_all(&mut self) -> WireguardBoxFuture<'_, Vec<WireguardParsed>>;
+}
+
+impl DynWireguardApi for WireguardHandle {
+    fn get_by_name<'a>(
+        &'a mut self,
+        iface_name: &'a str,
+    ) -> WireguardBoxFuture<'a, WireguardParsed> {
+        Box::pin(WireguardHandle::get_by_name(self, iface_name))
+    }
+
+    fn set(&mut self, config: WireguardParsed) -> WireguardBoxFuture<'_, ()> {
+        Box::pin(WireguardHandle::set(self, config))
+    }
+
+    fn list_all(&mut self) -> WireguardBoxFuture<'_, Vec<WireguardParsed>> {
+        Box::pin(WireguardHandle::list_all(self))
+    }
+}
@@ -0,0 +1,240 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    DynWireguardApi, WireguardBoxFuture, WireguardHandle, WireguardParsed,
+};
+
+const DEFAULT_TTL: Duration = Duration::from_secs(1);
+
+/// Decorator of [DynWireguardApi] serving
+/// [DynWireguardApi::get_by_name()] from cache for a short time (default
+/// 1 second), cutting redundant kernel dumps when many components query
+/// the same interface.
+///
+/// Cache of an interface is dropped on any [DynWireguardApi::set()] to it
+/// through this wrapper and expired ones are dropped on every lookup, while
+/// changes made by others (e.g. `wg` command) and counters like `rx_bytes`
+/// might be stale up to TTL.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use nl_wireguard::{DynWireguardApi, WireguardCache};
+///
+/// async fn demo() -> Result<(), Box<dyn std::error::Error>> {
+///     let (conn, handle, _) = nl_wireguard::new_connection()?;
+///     tokio::spawn(conn);
+///     let mut api =
+///         WireguardCache::new(handle).ttl(Duration::from_millis(500));
+///     let first = api.get_by_name("wg0").await?;
+///     // Served from cache
+///     let second = api.get_by_name("wg0").await?;
+///     assert_eq!(first, second);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct WireguardCache<T = WireguardHandle> {
+    inner: T,
+    ttl: Duration,
+    entries: HashMap<String, (Instant, WireguardParsed)>,
+}
+
+impl<T: DynWireguardApi> WireguardCache<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            ttl: DEFAULT_TTL,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// How long a cached interface is served, default is 1 second.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Drop cache of specified interface.
+    pub fn invalidate(&mut self, iface_name: &str) {
+        self.entries.remove(iface_name);
+    }
+
+    /// Drop cache of all interfaces.
+    pub fn invalidate_all(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn evict_expired(&mut self) {
+        let ttl = self.ttl;
+        self.entries.retain(|_, (stored, _)| stored.elapsed() < ttl);
+    }
+
+    fn store(&mut self, config: &WireguardParsed) {
+        if let Some(iface_name) = config.iface_name.as_ref() {
+            self.entries
+                .insert(iface_name.clone(), (Instant::now(), config.clone()));
+        }
+    }
+}
+
+impl<T: DynWireguardApi> DynWireguardApi for WireguardCache<T> {
+    fn get_by_name<'a>(
+        &'a mut self,
+        iface_name: &'a str,
+    ) -> WireguardBoxFuture<'a, WireguardParsed> {
+        Box::pin(async move {
+            self.evict_expired();
+            if let Some((_, config)) = self.entries.get(iface_name) {
+                log::trace!("Serving {iface_name} from cache");
+                return Ok(config.clone());
+            }
+            let config = self.inner.get_by_name(iface_name).await?;
+            self.store(&config);
+            Ok(config)
+        })
+    }
+
+    fn set(&mut self, config: WireguardParsed) -> WireguardBoxFuture<'_, ()> {
+        // Kernel might have partially applied the config even on failure
+        match config.iface_name.as_deref() {
+            Some(iface_name) => self.invalidate(iface_name),
+            None => self.invalidate_all(),
+        }
+        self.inner.set(config)
+    }
+
+    fn list_all(&mut self) -> WireguardBoxFuture<'_, Vec<WireguardParsed>> {
+        Box::pin(async move {
+            let configs = self.inner.list_all().await?;
+            for config in &configs {
+                self.store(config);
+            }
+            Ok(configs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    const TTL: Duration = Duration::from_millis(50);
+
+    // Count queries reaching the kernel
+    #[derive(Clone, Default)]
+    struct MockApi {
+        queries: Arc<AtomicUsize>,
+    }
+
+    impl MockApi {
+        fn queries(&self) -> usize {
+            self.queries.load(Ordering::SeqCst)
+        }
+    }
+
+    impl DynWireguardApi for MockApi {
+        fn get_by_name<'a>(
+            &'a mut self,
+            iface_name: &'a str,
+        ) -> WireguardBoxFuture<'a, WireguardParsed> {
+            self.queries.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move { Ok(WireguardParsed::new(iface_name)) })
+        }
+
+        fn set(
+            &mut self,
+            _config: WireguardParsed,
+        ) -> WireguardBoxFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn list_all(&mut self) -> WireguardBoxFuture<'_, Vec<WireguardParsed>> {
+            self.queries.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async {
+                Ok(vec![
+                    WireguardParsed::new("wg0"),
+                    WireguardParsed::new("wg1"),
+                ])
+            })
+        }
+    }
+
+    fn cached_ifaces(cache: &WireguardCache<MockApi>) -> Vec<&str> {
+        let mut ret: Vec<&str> =
+            cache.entries.keys().map(String::as_str).collect();
+        ret.sort_unstable();
+        ret
+    }
+
+    #[tokio::test]
+    async fn serve_within_ttl() {
+        let api = MockApi::default();
+        let mut cache = WireguardCache::new(api.clone()).ttl(TTL);
+        let config = cache.get_by_name("wg0").await.unwrap();
+        assert_eq!(config, WireguardParsed::new("wg0"));
+        assert_eq!(cache.get_by_name("wg0").await.unwrap(), config);
+        assert_eq!(api.queries(), 1);
+
+        cache.get_by_name("wg1").await.unwrap();
+        assert_eq!(api.queries(), 2);
+        assert_eq!(cached_ifaces(&cache), ["wg0", "wg1"]);
+    }
+
+    #[tokio::test]
+    async fn evict_expired() {
+        let api = MockApi::default();
+        let mut cache = WireguardCache::new(api.clone()).ttl(TTL);
+        cache.get_by_name("wg0").await.unwrap();
+        std::thread::sleep(TTL * 2);
+
+        // Looking up other interface drops the expired one
+        cache.get_by_name("wg1").await.unwrap();
+        assert_eq!(cached_ifaces(&cache), ["wg1"]);
+        cache.get_by_name("wg0").await.unwrap();
+        assert_eq!(api.queries(), 3);
+        assert_eq!(cached_ifaces(&cache), ["wg0", "wg1"]);
+    }
+
+    #[tokio::test]
+    async fn list_all_fills_cache() {
+        let api = MockApi::default();
+        let mut cache = WireguardCache::new(api.clone()).ttl(TTL);
+        cache.list_all().await.unwrap();
+        cache.get_by_name("wg0").await.unwrap();
+        cache.get_by_name("wg1").await.unwrap();
+        assert_eq!(api.queries(), 1);
+    }
+
+    #[tokio::test]
+    async fn set_invalidates() {
+        let api = MockApi::default();
+        let mut cache = WireguardCache::new(api.clone()).ttl(TTL);
+        cache.list_all().await.unwrap();
+
+        cache.set(WireguardParsed::new("wg0")).await.unwrap();
+        assert_eq!(cached_ifaces(&cache), ["wg1"]);
+        cache.set(WireguardParsed::default()).await.unwrap();
+        assert!(cached_ifaces(&cache).is_empty());
+
+        cache.list_all().await.unwrap();
+        cache.invalidate("wg1");
+        assert_eq!(cached_ifaces(&cache), ["wg0"]);
+        cache.invalidate_all();
+        assert!(cached_ifaces(&cache).is_empty());
+    }
+}
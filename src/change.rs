@@ -0,0 +1,418 @@
+// SPDX-License-Identifier: MIT
+
+use crate::{
+    key::same_key, key_fingerprint, peer_parsed::normalize_endpoint,
+    WireguardIpAddress, WireguardParsed, WireguardParsedDeviceFlags,
+    WireguardParsedPeerFlags, WireguardPeerParsed,
+};
+
+const HIDDEN: &str = "(hidden)";
+const NONE: &str = "none";
+
+/// Change made to wireguard interface, secrets are never included.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum WireguardChange {
+    PeerAdded {
+        public_key: String,
+    },
+    PeerRemoved {
+        public_key: String,
+    },
+    /// Property of interface (`public_key` is `None`) or peer changed,
+    /// values are rendered as text, `None` means unset.
+    Property {
+        public_key: Option<String>,
+        name: &'static str,
+        old: Option<String>,
+        new: Option<String>,
+    },
+}
+
+impl std::fmt::Display for WireguardChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PeerAdded { public_key } => {
+                write!(f, "+ peer {}…", key_fingerprint(public_key))
+            }
+            Self::PeerRemoved { public_key } => {
+                write!(f, "- peer {}…", key_fingerprint(public_key))
+            }
+            Self::Property {
+                public_key,
+                name,
+                old,
+                new,
+            } => {
+                if let Some(public_key) = public_key {
+                    write!(f, "peer {}…: ", key_fingerprint(public_key))?;
+                }
+                write!(
+                    f,
+                    "{name} {} → {}",
+                    old.as_deref().unwrap_or(NONE),
+                    new.as_deref().unwrap_or(NONE)
+                )
+            }
+        }
+    }
+}
+
+impl WireguardChange {
+    /// Changes made by setting `applied` (e.g. the argument of
+    /// [crate::WireguardHandle::set()]) on interface currently configured
+    /// as `current`, following the kernel semantics of the device and peer
+    /// flags.
+    pub fn list(
+        current: &WireguardParsed,
+        applied: &WireguardParsed,
+    ) -> Vec<Self> {
+        let mut ret = Vec::new();
+        if applied.private_key.is_some()
+            && !same_key(
+                applied.private_key.as_deref(),
+                current.private_key.as_deref(),
+            )
+        {
+            ret.push(Self::Property {
+                public_key: None,
+                name: "private_key",
+                old: current.private_key.as_ref().map(|_| HIDDEN.to_string()),
+                new: Some(HIDDEN.to_string()),
+            });
+        }
+        if let Some(port) = applied
+            .listen_port
+            .filter(|p| Some(*p) != current.listen_port)
+        {
+            ret.push(Self::Property {
+                public_key: None,
+                name: "listen_port",
+                old: current.listen_port.map(|p| p.to_string()),
+                new: Some(port.to_string()),
+            });
+        }
+        if let Some(fwmark) = applied
+            .fwmark
+            .filter(|m| *m != current.fwmark.unwrap_or_default())
+        {
+            ret.push(Self::Property {
+                public_key: None,
+                name: "fwmark",
+                old: current.fwmark.map(|m| format!("{m:#x}")),
+                new: Some(format!("{fwmark:#x}")),
+            });
+        }
+
+        let replace_peers = applied
+            .flags
+            .as_deref()
+            .unwrap_or_default()
+            .contains(&WireguardParsedDeviceFlags::ReplacePeers);
+        if replace_peers {
+            for peer in current.peers_iter() {
+                if let Some(public_key) = peer.public_key.as_deref() {
+                    if !applied.peers_iter().any(|p| {
+                        same_key(p.public_key.as_deref(), Some(public_key))
+                    }) {
+                        ret.push(Self::PeerRemoved {
+                            public_key: public_key.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+
+        for peer in applied.peers_iter() {
+            let Some(public_key) = peer.public_key.as_deref() else {
+                continue;
+            };
+            let cur_peer = current
+                .peers_iter()
+                .find(|p| same_key(p.public_key.as_deref(), Some(public_key)));
+            let flags = peer.flags.as_deref().unwrap_or_default();
+            if flags.contains(&WireguardParsedPeerFlags::RemoveMe) {
+                if cur_peer.is_some() {
+                    ret.push(Self::PeerRemoved {
+                        public_key: public_key.to_string(),
+                    });
+                }
+                continue;
+            }
+            let empty_peer = WireguardPeerParsed::default();
+            let cur_peer = match cur_peer {
+                Some(p) => p,
+                None => {
+                    if flags.contains(&WireguardParsedPeerFlags::UpdateOnly) {
+                        continue;
+                    }
+                    ret.push(Self::PeerAdded {
+                        public_key: public_key.to_string(),
+                    });
+                    &empty_peer
+                }
+            };
+            // Peers are recreated by ReplacePeers, so are their allowed IPs
+            let replace_ips = replace_peers
+                || flags.contains(&WireguardParsedPeerFlags::ReplaceAllowedIps);
+            peer_changes(&mut ret, public_key, cur_peer, peer, replace_ips);
+        }
+        ret
+    }
+}
+
+fn peer_changes(
+    ret: &mut Vec<WireguardChange>,
+    public_key: &str,
+    current: &WireguardPeerParsed,
+    applied: &WireguardPeerParsed,
+    replace_ips: bool,
+) {
+    let mut push = |name, old: Option<String>, new: Option<String>| {
+        ret.push(WireguardChange::Property {
+            public_key: Some(public_key.to_string()),
+            name,
+            old,
+            new,
+        })
+    };
+    if let Some(endpoint) = applied.endpoint.map(normalize_endpoint) {
+        if Some(endpoint) != current.endpoint.map(normalize_endpoint) {
+            push(
+                "endpoint",
+                current.endpoint.map(|e| e.to_string()),
+                Some(endpoint.to_string()),
+            );
+        }
+    }
+    if applied.preshared_key.is_some()
+        && !same_key(
+            applied.preshared_key.as_deref(),
+            current.preshared_key.as_deref(),
+        )
+    {
+        push(
+            "preshared_key",
+            current.preshared_key.as_ref().map(|_| HIDDEN.to_string()),
+            Some(HIDDEN.to_string()),
+        );
+    }
+    if let Some(keepalive) = applied
+        .persistent_keepalive
+        .filter(|k| *k != current.persistent_keepalive.unwrap_or_default())
+    {
+        push(
+            "persistent_keepalive",
+            current.persistent_keepalive.map(|k| k.to_string()),
+            Some(keepalive.to_string()),
+        );
+    }
+    let cur_ips = current.allowed_ips.as_deref().unwrap_or_default();
+    let mut new_ips: Vec<&WireguardIpAddress> = if replace_ips {
+        Vec::new()
+    } else {
+        cur_ips.iter().collect()
+    };
+    for ip in applied.allowed_ips.as_deref().unwrap_or_default() {
+        if !new_ips.iter().any(|i| same_prefix(i, ip)) {
+            new_ips.push(ip);
+        }
+    }
+    if new_ips.len() != cur_ips.len()
+        || !new_ips
+            .iter()
+            .all(|ip| cur_ips.iter().any(|i| same_prefix(i, ip)))
+    {
+        push(
+            "allowed_ips",
+            (!cur_ips.is_empty()).then(|| join(cur_ips.iter())),
+            (!new_ips.is_empty()).then(|| join(new_ips.into_iter())),
+        );
+    }
+}
+
+fn same_prefix(a: &WireguardIpAddress, b: &WireguardIpAddress) -> bool {
+    a.ip_addr == b.ip_addr && a.prefix_length == b.prefix_length
+}
+
+fn join<'a>(ips: impl Iterator<Item = &'a WireguardIpAddress>) -> String {
+    ips.map(|i| i.to_string()).collect::<Vec<_>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY1: &str = "uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM=";
+    const KEY2: &str = "WEAuaVuhdyscyTCXVfBDJR6nf9zxD75jmJzrfhkyE3Y=";
+    const KEY3: &str = "hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=";
+
+    fn peer(
+        public_key: &str,
+        ips: &[&str],
+        flags: &[WireguardParsedPeerFlags],
+    ) -> WireguardPeerParsed {
+        WireguardPeerParsed {
+            allowed_ips: Some(ips.iter().map(|i| i.parse().unwrap()).collect()),
+            flags: (!flags.is_empty()).then(|| flags.to_vec()),
+            ..WireguardPeerParsed::new(public_key)
+        }
+    }
+
+    fn current() -> WireguardParsed {
+        WireguardParsed::new("wg0").with_peers([
+            peer(KEY1, &["10.0.0.1/32"], &[]),
+            peer(KEY2, &["10.0.0.2/32"], &[]),
+        ])
+    }
+
+    fn applied(
+        flags: &[WireguardParsedDeviceFlags],
+        peers: Vec<WireguardPeerParsed>,
+    ) -> WireguardParsed {
+        WireguardParsed {
+            flags: (!flags.is_empty()).then(|| flags.to_vec()),
+            ..WireguardParsed::new("wg0")
+        }
+        .with_peers(peers)
+    }
+
+    fn lines(changes: Vec<WireguardChange>) -> Vec<String> {
+        changes.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn without_flags() {
+        let applied = applied(
+            &[],
+            vec![
+                peer(KEY1, &["10.0.0.3/32"], &[]),
+                peer(KEY3, &["10.0.0.4/32"], &[]),
+            ],
+        );
+        assert_eq!(
+            lines(WireguardChange::list(&current(), &applied)),
+            [
+                "peer uFmW/syc…: allowed_ips 10.0.0.1/32 → \
+                 10.0.0.1/32,10.0.0.3/32",
+                "+ peer hSDwCYkw…",
+                "peer hSDwCYkw…: allowed_ips none → 10.0.0.4/32",
+            ]
+        );
+    }
+
+    #[test]
+    fn replace_peers() {
+        let applied = applied(
+            &[WireguardParsedDeviceFlags::ReplacePeers],
+            vec![peer(KEY1, &["10.0.0.3/32"], &[])],
+        );
+        assert_eq!(
+            lines(WireguardChange::list(&current(), &applied)),
+            [
+                "- peer WEAuaVuh…",
+                "peer uFmW/syc…: allowed_ips 10.0.0.1/32 → 10.0.0.3/32",
+            ]
+        );
+        // Identical peer set is no change
+        let applied = WireguardParsed {
+            flags: Some(vec![WireguardParsedDeviceFlags::ReplacePeers]),
+            ..current()
+        };
+        assert!(WireguardChange::list(&current(), &applied).is_empty());
+    }
+
+    #[test]
+    fn remove_me() {
+        let applied = applied(
+            &[],
+            vec![
+                peer(KEY2, &[], &[WireguardParsedPeerFlags::RemoveMe]),
+                peer(KEY3, &[], &[WireguardParsedPeerFlags::RemoveMe]),
+            ],
+        );
+        assert_eq!(
+            WireguardChange::list(&current(), &applied),
+            [WireguardChange::PeerRemoved {
+                public_key: KEY2.to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn replace_allowed_ips() {
+        let applied = applied(
+            &[],
+            vec![
+                peer(
+                    KEY1,
+                    &["10.0.0.3/32"],
+                    &[WireguardParsedPeerFlags::ReplaceAllowedIps],
+                ),
+                peer(KEY2, &[], &[WireguardParsedPeerFlags::ReplaceAllowedIps]),
+            ],
+        );
+        assert_eq!(
+            lines(WireguardChange::list(&current(), &applied)),
+            [
+                "peer uFmW/syc…: allowed_ips 10.0.0.1/32 → 10.0.0.3/32",
+                "peer WEAuaVuh…: allowed_ips 10.0.0.2/32 → none",
+            ]
+        );
+    }
+
+    #[test]
+    fn update_only() {
+        let mut existing =
+            peer(KEY1, &[], &[WireguardParsedPeerFlags::UpdateOnly]);
+        existing.persistent_keepalive = Some(25);
+        let applied = applied(
+            &[],
+            vec![
+                existing,
+                peer(
+                    KEY3,
+                    &["10.0.0.4/32"],
+                    &[WireguardParsedPeerFlags::UpdateOnly],
+                ),
+            ],
+        );
+        assert_eq!(
+            WireguardChange::list(&current(), &applied),
+            [WireguardChange::Property {
+                public_key: Some(KEY1.to_string()),
+                name: "persistent_keepalive",
+                old: None,
+                new: Some("25".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn secrets_are_hidden() {
+        let mut current = current();
+        current.private_key = Some(KEY1.to_string());
+        let applied = WireguardParsed {
+            private_key: Some(KEY2.to_string()),
+            ..WireguardParsed::new("wg0")
+        }
+        .with_peers([WireguardPeerParsed {
+            preshared_key: Some(KEY3.to_string()),
+            ..WireguardPeerParsed::new(KEY1)
+        }]);
+        let changes = lines(WireguardChange::list(&current, &applied));
+        assert_eq!(
+            changes,
+            [
+                "private_key (hidden) → (hidden)",
+                "peer uFmW/syc…: preshared_key none → (hidden)",
+            ]
+        );
+        // Same key written differently is no change
+        let applied = WireguardParsed {
+            private_key: Some(KEY1.trim_end_matches('=').to_string()),
+            ..WireguardParsed::new("wg0")
+        };
+        assert!(WireguardChange::list(&current, &applied).is_empty());
+    }
+}
@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use crate::{ErrorKind, WireguardError};
+
+pub(crate) fn run_cmd(
+    program: &str,
+    args: &[&str],
+    stdin: Option<&str>,
+) -> Result<(), WireguardError> {
+    let cmd_str = format!("{program} {}", args.join(" "));
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            WireguardError::new(
+                ErrorKind::CommandFailed,
+                format!("Failed to execute {cmd_str}: {e}"),
+                None,
+            )
+        })?;
+    if let (Some(content), Some(mut fd)) = (stdin, child.stdin.take()) {
+        fd.write_all(content.as_bytes()).map_err(|e| {
+            WireguardError::new(
+                ErrorKind::CommandFailed,
+                format!("Failed to write stdin of {cmd_str}: {e}"),
+                None,
+            )
+        })?;
+    }
+    let output = child.wait_with_output().map_err(|e| {
+        WireguardError::new(
+            ErrorKind::CommandFailed,
+            format!("Failed to wait {cmd_str}: {e}"),
+            None,
+        )
+    })?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(WireguardError::new(
+            ErrorKind::CommandFailed,
+            format!(
+                "Command {cmd_str} failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            None,
+        ))
+    }
+}
@@ -0,0 +1,293 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+use futures_channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures_util::StreamExt;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{
+    ErrorKind, WireguardError, WireguardHandle, WireguardQuickConfig,
+    WireguardReconciler,
+};
+
+/// Watch a directory of wg-quick configuration files (`<iface>.conf`) and
+/// apply the changed file to its interface via [WireguardReconciler].
+///
+/// Editors and deployment tools often write a file in several steps, hence
+/// the events are debounced: a changed file is applied once after no more
+/// event arrived for 500 milliseconds, even if it changed many times.
+///
+/// Removing a file does not remove the interface or its peers.
+#[derive(Debug)]
+pub struct WireguardConfWatcher {
+    handle: WireguardHandle,
+    dir: PathBuf,
+    // Stop watching when dropped
+    _watcher: RecommendedWatcher,
+    changes: UnboundedReceiver<PathBuf>,
+}
+
+impl WireguardConfWatcher {
+    pub fn new(
+        handle: WireguardHandle,
+        dir: &Path,
+    ) -> Result<Self, WireguardError> {
+        let (event_tx, event_rx) = channel();
+        let (tx, changes) = unbounded();
+        let mut watcher =
+            notify::recommended_watcher(move |event| match event {
+                Ok(event) => {
+                    for path in changed_conf_files(&event) {
+                        event_tx.send(path).ok();
+                    }
+                }
+                Err(e) => log::warn!("Failed to watch config files: {e}"),
+            })
+            .map_err(|e| notify_error(dir, e))?;
+        // Stops when watcher is dropped as `event_tx` is dropped with it
+        std::thread::Builder::new()
+            .name("wg-conf-debounce".to_string())
+            .spawn(move || debounce(event_rx, tx, DEBOUNCE))
+            .map_err(|e| {
+                WireguardError::new(
+                    ErrorKind::IoError,
+                    format!("Failed to spawn debounce thread: {e}"),
+                    None,
+                )
+            })?;
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|e| notify_error(dir, e))?;
+        Ok(Self {
+            handle,
+            dir: dir.to_path_buf(),
+            _watcher: watcher,
+            changes,
+        })
+    }
+
+    /// Apply all configuration files in the watched directory, return the
+    /// interface name with result of [WireguardReconciler::reconcile()].
+    pub async fn apply_all(
+        &mut self,
+    ) -> Result<Vec<(String, Result<bool, WireguardError>)>, WireguardError>
+    {
+        let entries = std::fs::read_dir(&self.dir).map_err(|e| {
+            WireguardError::new(
+                ErrorKind::IoError,
+                format!("Failed to read dir {}: {e}", self.dir.display()),
+                None,
+            )
+        })?;
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| is_conf_file(p))
+            .collect();
+        paths.sort_unstable();
+
+        let mut ret = Vec::new();
+        for path in paths {
+            ret.push(self.apply(&path).await);
+        }
+        Ok(ret)
+    }
+
+    /// Apply configuration files whenever changed, never return.
+    pub async fn run(&mut self) {
+        while let Some(path) = self.changes.next().await {
+            let (iface_name, result) = self.apply(&path).await;
+            match result {
+                Ok(true) => log::info!(
+                    "Applied {} to interface {iface_name}",
+                    path.display()
+                ),
+                Ok(false) => (),
+                Err(e) => log::warn!(
+                    "Failed to apply {} to interface {iface_name}: {e}",
+                    path.display()
+                ),
+            }
+        }
+    }
+
+    async fn apply(
+        &self,
+        path: &Path,
+    ) -> (String, Result<bool, WireguardError>) {
+        let iface_name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let config = match WireguardQuickConfig::read(path) {
+            Ok(mut c) => c.resolve_endpoints_async().await.map(|_| c),
+            Err(e) => Err(e),
+        };
+        let result = match config {
+            Ok(config) => {
+                WireguardReconciler::new(self.handle.clone(), config.wireguard)
+                    .reconcile()
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        (iface_name, result)
+    }
+}
+
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+// Paths of configuration files created or modified by the event
+fn changed_conf_files(event: &notify::Event) -> Vec<PathBuf> {
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(_) => event
+            .paths
+            .iter()
+            .filter(|p| is_conf_file(p))
+            .cloned()
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn debounce(
+    events: Receiver<PathBuf>,
+    changes: UnboundedSender<PathBuf>,
+    delay: Duration,
+) {
+    while let Ok(path) = events.recv() {
+        let (paths, disconnected) = coalesce(&events, path, delay);
+        for path in paths {
+            if changes.unbounded_send(path).is_err() {
+                return;
+            }
+        }
+        if disconnected {
+            return;
+        }
+    }
+}
+
+// Collect `first` and following paths until no more arrives within `delay`,
+// also return whether the sender is gone.
+fn coalesce(
+    events: &Receiver<PathBuf>,
+    first: PathBuf,
+    delay: Duration,
+) -> (BTreeSet<PathBuf>, bool) {
+    let mut paths = BTreeSet::from([first]);
+    loop {
+        match events.recv_timeout(delay) {
+            Ok(path) => {
+                paths.insert(path);
+            }
+            Err(RecvTimeoutError::Timeout) => return (paths, false),
+            Err(RecvTimeoutError::Disconnected) => return (paths, true),
+        }
+    }
+}
+
+fn is_conf_file(path: &Path) -> bool {
+    path.extension().map(|e| e == "conf").unwrap_or_default()
+}
+
+fn notify_error(dir: &Path, e: notify::Error) -> WireguardError {
+    WireguardError::new(
+        ErrorKind::IoError,
+        format!("Failed to watch dir {}: {e}", dir.display()),
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{
+        AccessKind, CreateKind, DataChange, ModifyKind, RemoveKind,
+    };
+
+    use super::*;
+
+    fn event(kind: EventKind, paths: &[&str]) -> notify::Event {
+        paths
+            .iter()
+            .fold(notify::Event::new(kind), |e, p| e.add_path(p.into()))
+    }
+
+    #[test]
+    fn changed_conf_files_by_kind() {
+        let paths = ["/etc/wireguard/wg0.conf", "/etc/wireguard/wg0.conf~"];
+        let expected = vec![PathBuf::from("/etc/wireguard/wg0.conf")];
+        for kind in [
+            EventKind::Create(CreateKind::File),
+            EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+            EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::To)),
+        ] {
+            assert_eq!(
+                changed_conf_files(&event(kind, &paths)),
+                expected,
+                "{kind:?}"
+            );
+        }
+        for kind in [
+            EventKind::Remove(RemoveKind::File),
+            EventKind::Access(AccessKind::Any),
+            EventKind::Any,
+        ] {
+            assert!(
+                changed_conf_files(&event(kind, &paths)).is_empty(),
+                "{kind:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn coalesce_duplicated_paths() {
+        let (tx, rx) = channel();
+        for path in ["/a/wg1.conf", "/a/wg0.conf", "/a/wg1.conf"] {
+            tx.send(PathBuf::from(path)).unwrap();
+        }
+        let (paths, disconnected) = coalesce(
+            &rx,
+            PathBuf::from("/a/wg0.conf"),
+            Duration::from_millis(10),
+        );
+        assert_eq!(
+            paths.into_iter().collect::<Vec<_>>(),
+            vec![PathBuf::from("/a/wg0.conf"), PathBuf::from("/a/wg1.conf")]
+        );
+        assert!(!disconnected);
+
+        drop(tx);
+        let (paths, disconnected) = coalesce(
+            &rx,
+            PathBuf::from("/a/wg2.conf"),
+            Duration::from_secs(10),
+        );
+        assert_eq!(paths.len(), 1);
+        assert!(disconnected);
+    }
+
+    #[test]
+    fn debounce_forwards_each_path_once() {
+        let (tx, rx) = channel();
+        let (changes_tx, mut changes) = unbounded();
+        for path in ["/a/wg0.conf", "/a/wg0.conf", "/a/wg1.conf"] {
+            tx.send(PathBuf::from(path)).unwrap();
+        }
+        drop(tx);
+        debounce(rx, changes_tx, Duration::from_millis(10));
+        let mut forwarded = Vec::new();
+        while let Ok(path) = changes.try_recv() {
+            forwarded.push(path);
+        }
+        assert_eq!(
+            forwarded,
+            vec![PathBuf::from("/a/wg0.conf"), PathBuf::from("/a/wg1.conf")]
+        );
+    }
+}
@@ -1,8 +1,9 @@
 // SPDX-License-Identifier: MIT
 
-use std::io;
+use std::{future::Future, io};
 
-use futures_channel::mpsc::UnboundedReceiver;
+use futures_channel::{mpsc::UnboundedReceiver, oneshot};
+use futures_util::future::Either;
 use genetlink::message::RawGenlMessage;
 use netlink_packet_core::NetlinkMessage;
 use netlink_proto::Connection;
@@ -10,19 +11,59 @@ use netlink_sys::{AsyncSocket, SocketAddr};
 
 use crate::WireguardHandle;
 
+/// Netlink connection which implements [std::future::Future] and should be
+/// spawned on (or polled by) any executor to drive the requests of
+/// [WireguardHandle].
+pub type WireguardConnection<S> = Connection<RawGenlMessage, S>;
+
+/// Create connection using tokio socket.
 #[cfg(feature = "tokio_socket")]
 #[allow(clippy::type_complexity)]
 pub fn new_connection() -> io::Result<(
-    Connection<RawGenlMessage>,
+    WireguardConnection<netlink_sys::TokioSocket>,
     WireguardHandle,
     UnboundedReceiver<(NetlinkMessage<RawGenlMessage>, SocketAddr)>,
 )> {
     new_connection_with_socket()
 }
 
+/// Spawn connection on current tokio runtime, the returned guard stops the
+/// connection task when dropped.
+///
+/// Panics if not called from a tokio runtime, like `tokio::spawn()`.
+#[cfg(feature = "tokio_socket")]
+pub fn spawn_connection(
+) -> io::Result<(WireguardConnectionGuard, WireguardHandle)> {
+    let (conn, handle, _) = new_connection()?;
+    Ok((
+        WireguardConnectionGuard {
+            task: tokio::spawn(conn),
+        },
+        handle,
+    ))
+}
+
+/// Owner of connection task spawned by [spawn_connection()], aborting the
+/// task when dropped.
+#[cfg(feature = "tokio_socket")]
+#[derive(Debug)]
+pub struct WireguardConnectionGuard {
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(feature = "tokio_socket")]
+impl Drop for WireguardConnectionGuard {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Create connection using any [AsyncSocket] implementation, no async
+/// runtime feature is required. Implement [AsyncSocket] to integrate with
+/// custom executors or io_uring based runtimes.
 #[allow(clippy::type_complexity)]
 pub fn new_connection_with_socket<S>() -> io::Result<(
-    Connection<RawGenlMessage, S>,
+    WireguardConnection<S>,
     WireguardHandle,
     UnboundedReceiver<(NetlinkMessage<RawGenlMessage>, SocketAddr)>,
 )>
@@ -32,3 +73,43 @@ where
     let (conn, handle, messages) = genetlink::new_connection_with_socket()?;
     Ok((conn, WireguardHandle::new(handle), messages))
 }
+
+/// Handle for stopping the connection wrapped by
+/// [connection_with_shutdown()].
+#[derive(Debug)]
+pub struct WireguardShutdown {
+    sender: oneshot::Sender<()>,
+}
+
+impl WireguardShutdown {
+    /// Stop the connection, pending requests will fail.
+    pub fn shutdown(self) {
+        self.sender.send(()).ok();
+    }
+}
+
+/// Wrap `connection` into a future which completes when
+/// [WireguardShutdown::shutdown()] is invoked, so daemons can stop the
+/// netlink task deterministically instead of aborting it.
+/// Dropping [WireguardShutdown] without invoking `shutdown()` does not stop
+/// the connection, which ends anyway once all [WireguardHandle] are
+/// dropped.
+pub fn connection_with_shutdown<C>(
+    connection: C,
+) -> (impl Future<Output = ()>, WireguardShutdown)
+where
+    C: Future<Output = ()> + Unpin,
+{
+    let (sender, receiver) = oneshot::channel();
+    let fut = async move {
+        match futures_util::future::select(connection, receiver).await {
+            Either::Left(((), _)) => (),
+            Either::Right((Ok(()), _)) => {
+                log::debug!("Netlink connection shutdown requested");
+            }
+            // Shutdown handle dropped, keep serving
+            Either::Right((Err(_), connection)) => connection.await,
+        }
+    };
+    (fut, WireguardShutdown { sender })
+}
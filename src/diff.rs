@@ -0,0 +1,419 @@
+// SPDX-License-Identifier: MIT
+
+use std::collections::HashMap;
+
+use crate::{
+    key::{canonical_key, same_key},
+    peer_parsed::normalize_endpoint,
+    WireguardChange, WireguardIpAddress, WireguardParsed,
+    WireguardParsedPeerFlags, WireguardPeerParsed,
+};
+
+/// Generate the [WireguardParsed] to apply on top of `current` for reaching
+/// `desired`. Properties set to `None` in `desired` are not managed, while
+/// `desired.peers` set to `Some` means the peer list should be exactly the
+/// same, peers not in `desired` will be removed.
+/// Return `None` if nothing to change.
+pub(crate) fn correction(
+    current: &WireguardParsed,
+    desired: &WireguardParsed,
+) -> Option<WireguardParsed> {
+    let mut ret = WireguardParsed {
+        iface_name: desired.iface_name.clone(),
+        iface_index: if desired.iface_name.is_none() {
+            desired.iface_index.or(current.iface_index)
+        } else {
+            None
+        },
+        ..Default::default()
+    };
+    let mut changed = false;
+
+    if desired.private_key.is_some()
+        && !same_key(
+            desired.private_key.as_deref(),
+            current.private_key.as_deref(),
+        )
+    {
+        ret.private_key.clone_from(&desired.private_key);
+        changed = true;
+    }
+    if desired.listen_port.is_some()
+        && desired.listen_port != Some(0)
+        && desired.listen_port != current.listen_port
+    {
+        ret.listen_port = desired.listen_port;
+        changed = true;
+    }
+    if desired.fwmark.is_some()
+        && desired.fwmark.unwrap_or_default()
+            != current.fwmark.unwrap_or_default()
+    {
+        ret.fwmark = desired.fwmark;
+        changed = true;
+    }
+
+    if let Some(desired_peers) = desired.peers.as_ref() {
+        // Keys are matched in canonical form as the desired ones might be
+        // written differently from the base64 encoding of kernel.
+        let mut cur_peers: HashMap<String, &WireguardPeerParsed> =
+            HashMap::new();
+        for peer in current.peers.as_deref().unwrap_or_default() {
+            if let Some(key) = peer.public_key.as_deref() {
+                cur_peers.insert(canonical_key(key), peer);
+            }
+        }
+        let mut peers = Vec::new();
+        for desired_peer in desired_peers {
+            let cur_peer = desired_peer
+                .public_key
+                .as_deref()
+                .and_then(|k| cur_peers.remove(&canonical_key(k)));
+            if let Some(peer) = peer_correction(cur_peer, desired_peer) {
+                peers.push(peer);
+            }
+        }
+        for (_, peer) in cur_peers {
+            peers.push(WireguardPeerParsed {
+                public_key: peer.public_key.clone(),
+                flags: Some(vec![WireguardParsedPeerFlags::RemoveMe]),
+                ..Default::default()
+            });
+        }
+        if !peers.is_empty() {
+            ret.peers = Some(peers);
+            changed = true;
+        }
+    }
+
+    if changed {
+        Some(ret)
+    } else {
+        None
+    }
+}
+
+const ANSI_RED: &str = "\x1b[31m";
+const ANSI_GREEN: &str = "\x1b[32m";
+const ANSI_YELLOW: &str = "\x1b[33m";
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// Render the changes required for reaching `desired` from `current` as
+/// text, one [WireguardChange] per line, e.g. for dry-run output. Secrets
+/// are never shown. With `color`, added, removed and modified lines are
+/// colored green, red and yellow by ANSI escape sequence.
+/// Properties set to `None` in `desired` are not compared, `desired.peers`
+/// set to `Some` means the peer list should be exactly the same.
+pub fn render_diff(
+    current: &WireguardParsed,
+    desired: &WireguardParsed,
+    color: bool,
+) -> String {
+    let Some(fix) = correction(current, desired) else {
+        return String::new();
+    };
+    let mut ret = String::new();
+    for change in WireguardChange::list(current, &fix) {
+        let ansi = match change {
+            WireguardChange::PeerAdded { .. } => ANSI_GREEN,
+            WireguardChange::PeerRemoved { .. } => ANSI_RED,
+            _ => ANSI_YELLOW,
+        };
+        if color {
+            ret.push_str(&format!("{ansi}{change}{ANSI_RESET}\n"));
+        } else {
+            ret.push_str(&format!("{change}\n"));
+        }
+    }
+    ret
+}
+
+// For existing peer, only the changed properties are included along with
+// the public key, so unchanged allowed IPs are not sent again.
+fn peer_correction(
+    current: Option<&WireguardPeerParsed>,
+    desired: &WireguardPeerParsed,
+) -> Option<WireguardPeerParsed> {
+    let Some(current) = current else {
+        return Some(WireguardPeerParsed {
+            flags: desired
+                .allowed_ips
+                .is_some()
+                .then(|| vec![WireguardParsedPeerFlags::ReplaceAllowedIps]),
+            ..desired.without_runtime_fields()
+        });
+    };
+
+    let mut ret = WireguardPeerParsed {
+        public_key: desired.public_key.clone(),
+        ..Default::default()
+    };
+    let mut changed = false;
+    if desired.endpoint.is_some()
+        && desired.endpoint.map(normalize_endpoint)
+            != current.endpoint.map(normalize_endpoint)
+    {
+        ret.endpoint = desired.endpoint;
+        changed = true;
+    }
+    if desired.preshared_key.is_some()
+        && !same_key(
+            desired.preshared_key.as_deref(),
+            current.preshared_key.as_deref(),
+        )
+    {
+        ret.preshared_key.clone_from(&desired.preshared_key);
+        changed = true;
+    }
+    if desired.persistent_keepalive.is_some()
+        && desired.persistent_keepalive.unwrap_or_default()
+            != current.persistent_keepalive.unwrap_or_default()
+    {
+        ret.persistent_keepalive = desired.persistent_keepalive;
+        changed = true;
+    }
+    if desired.allowed_ips.is_some()
+        && sorted_ips(desired.allowed_ips.as_deref())
+            != sorted_ips(current.allowed_ips.as_deref())
+    {
+        ret.allowed_ips.clone_from(&desired.allowed_ips);
+        ret.flags = Some(vec![WireguardParsedPeerFlags::ReplaceAllowedIps]);
+        changed = true;
+    }
+    changed.then_some(ret)
+}
+
+fn sorted_ips(
+    ips: Option<&[WireguardIpAddress]>,
+) -> Vec<(std::net::IpAddr, u8)> {
+    let mut ret: Vec<(std::net::IpAddr, u8)> = ips
+        .unwrap_or_default()
+        .iter()
+        .map(|ip| (ip.ip_addr, ip.prefix_length))
+        .collect();
+    ret.sort_unstable();
+    ret.dedup();
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(public_key: &str, ips: &[&str]) -> WireguardPeerParsed {
+        WireguardPeerParsed {
+            public_key: Some(public_key.to_string()),
+            allowed_ips: Some(ips.iter().map(|i| i.parse().unwrap()).collect()),
+            ..Default::default()
+        }
+    }
+
+    fn config(peers: Vec<WireguardPeerParsed>) -> WireguardParsed {
+        WireguardParsed::new("wg0").with_peers(peers)
+    }
+
+    fn with_flags(
+        peer: WireguardPeerParsed,
+        flag: WireguardParsedPeerFlags,
+    ) -> WireguardPeerParsed {
+        WireguardPeerParsed {
+            flags: Some(vec![flag]),
+            ..peer
+        }
+    }
+
+    fn key_only(public_key: &str) -> WireguardPeerParsed {
+        WireguardPeerParsed {
+            public_key: Some(public_key.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn correction_of_peers() {
+        let a = peer("A", &["10.0.0.1/32"]);
+        let b = peer("B", &["10.0.0.2/32"]);
+        let b_moved = WireguardPeerParsed {
+            endpoint: Some("192.0.2.1:51820".parse().unwrap()),
+            persistent_keepalive: Some(25),
+            ..b.clone()
+        };
+        let cases = [
+            (
+                "unchanged",
+                config(vec![a.clone()]),
+                config(vec![a.clone()]),
+                None,
+            ),
+            (
+                "peer added",
+                config(vec![a.clone()]),
+                config(vec![a.clone(), b.clone()]),
+                Some(config(vec![with_flags(
+                    b.clone(),
+                    WireguardParsedPeerFlags::ReplaceAllowedIps,
+                )])),
+            ),
+            (
+                "peer removed",
+                config(vec![a.clone(), b.clone()]),
+                config(vec![a.clone()]),
+                Some(config(vec![with_flags(
+                    key_only("B"),
+                    WireguardParsedPeerFlags::RemoveMe,
+                )])),
+            ),
+            (
+                "all peers removed",
+                config(vec![a.clone()]),
+                config(vec![]),
+                Some(config(vec![with_flags(
+                    key_only("A"),
+                    WireguardParsedPeerFlags::RemoveMe,
+                )])),
+            ),
+            (
+                "peer modified",
+                config(vec![a.clone(), b.clone()]),
+                config(vec![a.clone(), b_moved.clone()]),
+                Some(config(vec![WireguardPeerParsed {
+                    endpoint: b_moved.endpoint,
+                    persistent_keepalive: Some(25),
+                    ..key_only("B")
+                }])),
+            ),
+            (
+                "peers not managed",
+                config(vec![a.clone()]),
+                WireguardParsed::new("wg0"),
+                None,
+            ),
+        ];
+        for (name, current, desired, expected) in cases {
+            assert_eq!(correction(&current, &desired), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn correction_of_allowed_ips() {
+        let cur = peer("A", &["10.0.0.1/32", "fd00::1/128"]);
+        let cases = [
+            ("same", peer("A", &["10.0.0.1/32", "fd00::1/128"]), None),
+            (
+                "reordered",
+                peer("A", &["fd00::1/128", "10.0.0.1/32"]),
+                None,
+            ),
+            (
+                "duplicated",
+                peer("A", &["10.0.0.1/32", "fd00::1/128", "10.0.0.1/32"]),
+                None,
+            ),
+            ("not managed", key_only("A"), None),
+            (
+                "ip added",
+                peer("A", &["10.0.0.1/32", "fd00::1/128", "10.1.0.0/16"]),
+                Some(peer("A", &["10.0.0.1/32", "fd00::1/128", "10.1.0.0/16"])),
+            ),
+            (
+                "ip removed",
+                peer("A", &["10.0.0.1/32"]),
+                Some(peer("A", &["10.0.0.1/32"])),
+            ),
+            (
+                "prefix length changed",
+                peer("A", &["10.0.0.1/24", "fd00::1/128"]),
+                Some(peer("A", &["10.0.0.1/24", "fd00::1/128"])),
+            ),
+            ("all removed", peer("A", &[]), Some(peer("A", &[]))),
+        ];
+        for (name, desired, expected) in cases {
+            let expected = expected.map(|p| {
+                config(vec![with_flags(
+                    p,
+                    WireguardParsedPeerFlags::ReplaceAllowedIps,
+                )])
+            });
+            assert_eq!(
+                correction(&config(vec![cur.clone()]), &config(vec![desired])),
+                expected,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn correction_of_listen_port() {
+        let port = |p: Option<u16>| WireguardParsed {
+            listen_port: p,
+            ..WireguardParsed::new("wg0")
+        };
+        let cases = [
+            ("same", Some(51820), Some(51820), None),
+            ("changed", Some(51820), Some(51821), Some(51821)),
+            ("set", None, Some(51820), Some(51820)),
+            // Zero means kernel chosen random port, never a change
+            ("zero", Some(51820), Some(0), None),
+            ("zero on unset", None, Some(0), None),
+            ("not managed", Some(51820), None, None),
+        ];
+        for (name, current, desired, expected) in cases {
+            assert_eq!(
+                correction(&port(current), &port(desired)),
+                expected.map(|p| port(Some(p))),
+                "{name}"
+            );
+        }
+    }
+
+    // Public key of Alice in RFC 7748 section 6.1
+    const KEY: &str = "hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=";
+    const PSK: &str = "GIUVCT6VL18i6GXO8wEucvi18LWYrAMJ1drM47cPz1I=";
+
+    #[test]
+    fn correction_matches_keys_in_canonical_form() {
+        let current = WireguardParsed {
+            private_key: Some(KEY.to_string()),
+            ..config(vec![WireguardPeerParsed {
+                preshared_key: Some(PSK.to_string()),
+                ..peer(KEY, &["10.0.0.1/32"])
+            }])
+        };
+        for written in [
+            KEY.trim_end_matches('='),
+            &format!(" {KEY}\n"),
+            &KEY.replace('/', "_"),
+        ] {
+            let desired = WireguardParsed {
+                private_key: Some(written.to_string()),
+                ..config(vec![WireguardPeerParsed {
+                    preshared_key: Some(PSK.trim_end_matches('=').to_string()),
+                    ..peer(written, &["10.0.0.1/32"])
+                }])
+            };
+            assert_eq!(correction(&current, &desired), None, "{written:?}");
+        }
+
+        // Changed peer keeps the desired key, removed one uses current key
+        let desired =
+            config(vec![peer(KEY.trim_end_matches('='), &["10.0.0.2/32"])]);
+        assert_eq!(
+            correction(&current, &desired),
+            Some(config(vec![with_flags(
+                peer(KEY.trim_end_matches('='), &["10.0.0.2/32"]),
+                WireguardParsedPeerFlags::ReplaceAllowedIps,
+            )]))
+        );
+        assert_eq!(
+            render_diff(&current, &desired, false),
+            "peer hSDwCYkw…: allowed_ips 10.0.0.1/32 → 10.0.0.2/32\n"
+        );
+        assert_eq!(
+            correction(&current, &config(Vec::new())),
+            Some(config(vec![with_flags(
+                key_only(KEY),
+                WireguardParsedPeerFlags::RemoveMe
+            )]))
+        );
+    }
+}
@@ -0,0 +1,111 @@
+// SPDX-License-Identifier: MIT
+
+use std::net::IpAddr;
+
+#[cfg(feature = "exec")]
+use crate::cmd::run_cmd;
+use crate::{ErrorKind, WireguardError, WireguardQuickConfig};
+
+/// Apply the DNS settings of [WireguardQuickConfig] to system resolver.
+pub trait WireguardDnsConfigurator {
+    fn apply(
+        &mut self,
+        iface_name: &str,
+        servers: &[IpAddr],
+        search: &[String],
+    ) -> Result<(), WireguardError>;
+
+    /// Remove the DNS settings applied to specified interface
+    fn revert(&mut self, iface_name: &str) -> Result<(), WireguardError>;
+}
+
+/// Configure DNS via `resolvconf` like `wg-quick` does.
+#[cfg(feature = "exec")]
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub struct WireguardResolvconf;
+
+#[cfg(feature = "exec")]
+impl WireguardDnsConfigurator for WireguardResolvconf {
+    fn apply(
+        &mut self,
+        iface_name: &str,
+        servers: &[IpAddr],
+        search: &[String],
+    ) -> Result<(), WireguardError> {
+        let mut content = String::new();
+        for server in servers {
+            content.push_str(&format!("nameserver {server}\n"));
+        }
+        if !search.is_empty() {
+            content.push_str(&format!("search {}\n", search.join(" ")));
+        }
+        let iface = format!("tun.{iface_name}");
+        run_cmd(
+            "resolvconf",
+            &["-a", &iface, "-m", "0", "-x"],
+            Some(&content),
+        )
+    }
+
+    fn revert(&mut self, iface_name: &str) -> Result<(), WireguardError> {
+        let iface = format!("tun.{iface_name}");
+        run_cmd("resolvconf", &["-d", &iface, "-f"], None)
+    }
+}
+
+/// Configure per-link DNS of systemd-resolved via `resolvectl`.
+#[cfg(feature = "exec")]
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub struct WireguardResolvectl;
+
+#[cfg(feature = "exec")]
+impl WireguardDnsConfigurator for WireguardResolvectl {
+    fn apply(
+        &mut self,
+        iface_name: &str,
+        servers: &[IpAddr],
+        search: &[String],
+    ) -> Result<(), WireguardError> {
+        let servers: Vec<String> =
+            servers.iter().map(|s| s.to_string()).collect();
+        let mut args = vec!["dns", iface_name];
+        args.extend(servers.iter().map(String::as_str));
+        run_cmd("resolvectl", &args, None)?;
+        if !search.is_empty() {
+            let mut args = vec!["domain", iface_name];
+            args.extend(search.iter().map(String::as_str));
+            run_cmd("resolvectl", &args, None)?;
+        }
+        Ok(())
+    }
+
+    fn revert(&mut self, iface_name: &str) -> Result<(), WireguardError> {
+        run_cmd("resolvectl", &["revert", iface_name], None)
+    }
+}
+
+impl WireguardQuickConfig {
+    /// Apply `dns` and `dns_search` via specified configurator. Do nothing
+    /// if neither is defined.
+    pub fn apply_dns(
+        &self,
+        configurator: &mut dyn WireguardDnsConfigurator,
+    ) -> Result<(), WireguardError> {
+        let servers = self.dns.as_deref().unwrap_or_default();
+        let search = self.dns_search.as_deref().unwrap_or_default();
+        if servers.is_empty() && search.is_empty() {
+            return Ok(());
+        }
+        let iface_name =
+            self.wireguard.iface_name.as_deref().ok_or_else(|| {
+                WireguardError::new(
+                    ErrorKind::InvalidConfig,
+                    "Cannot apply DNS without iface_name".to_string(),
+                    None,
+                )
+            })?;
+        configurator.apply(iface_name, servers, search)
+    }
+}
@@ -0,0 +1,213 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    str::FromStr,
+};
+
+use crate::{peer_parsed::normalize_endpoint, ErrorKind, WireguardError};
+
+/// Peer endpoint as written in configuration: `203.0.113.5:51820`,
+/// `[2001:db8::1]:51820` or `vpn.example.com:51820`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum WireguardEndpoint {
+    Addr(SocketAddr),
+    /// Host name to be resolved by [WireguardEndpoint::resolve()]
+    Host {
+        host: String,
+        port: u16,
+    },
+}
+
+impl From<SocketAddr> for WireguardEndpoint {
+    fn from(addr: SocketAddr) -> Self {
+        Self::Addr(normalize_endpoint(addr))
+    }
+}
+
+impl FromStr for WireguardEndpoint {
+    type Err = WireguardError;
+
+    fn from_str(s: &str) -> Result<Self, WireguardError> {
+        let s = s.trim();
+        let (host, port) = if let Some(rest) = s.strip_prefix('[') {
+            let (host, rest) = rest.split_once(']').ok_or_else(|| {
+                invalid_endpoint(s, "missing closing bracket")
+            })?;
+            let port = rest.strip_prefix(':').ok_or_else(|| {
+                invalid_endpoint(s, "missing port after bracketed address")
+            })?;
+            if IpAddr::from_str(host).map(|i| i.is_ipv6()) != Ok(true) {
+                return Err(invalid_endpoint(
+                    s,
+                    "only IPv6 address can be enclosed in brackets",
+                ));
+            }
+            (host, port)
+        } else {
+            let (host, port) = s
+                .rsplit_once(':')
+                .ok_or_else(|| invalid_endpoint(s, "missing port"))?;
+            if host.contains(':') {
+                return Err(invalid_endpoint(
+                    s,
+                    "IPv6 address should be enclosed in brackets",
+                ));
+            }
+            (host, port)
+        };
+        if host.is_empty() {
+            return Err(invalid_endpoint(s, "missing host"));
+        }
+        let port = u16::from_str(port).map_err(|e| {
+            invalid_endpoint(s, &format!("invalid port {port}: {e}"))
+        })?;
+        Ok(match IpAddr::from_str(host) {
+            Ok(ip) => Self::from(SocketAddr::new(ip, port)),
+            Err(_) => Self::Host {
+                host: host.to_string(),
+                port,
+            },
+        })
+    }
+}
+
+impl std::fmt::Display for WireguardEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Addr(addr) => write!(f, "{addr}"),
+            Self::Host { host, port } => write!(f, "{host}:{port}"),
+        }
+    }
+}
+
+impl WireguardEndpoint {
+    /// Socket address of endpoint, host name is resolved via system
+    /// resolver (blocking) and the first address is used.
+    pub fn resolve(&self) -> Result<SocketAddr, WireguardError> {
+        match self {
+            Self::Addr(addr) => Ok(*addr),
+            Self::Host { host, port } => (host.as_str(), *port)
+                .to_socket_addrs()
+                .map_err(|e| {
+                    WireguardError::new(
+                        ErrorKind::InvalidConfig,
+                        format!("Failed to resolve endpoint {self}: {e}"),
+                        None,
+                    )
+                })?
+                .next()
+                .map(normalize_endpoint)
+                .ok_or_else(|| {
+                    WireguardError::new(
+                        ErrorKind::InvalidConfig,
+                        format!("Endpoint {self} resolved to no address"),
+                        None,
+                    )
+                }),
+        }
+    }
+}
+
+fn invalid_endpoint(value: &str, reason: &str) -> WireguardError {
+    WireguardError::new(
+        ErrorKind::InvalidConfig,
+        format!("Invalid endpoint {value}: {reason}"),
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> WireguardEndpoint {
+        WireguardEndpoint::from_str(s).unwrap()
+    }
+
+    fn addr(s: &str) -> WireguardEndpoint {
+        WireguardEndpoint::Addr(s.parse().unwrap())
+    }
+
+    fn host(host: &str, port: u16) -> WireguardEndpoint {
+        WireguardEndpoint::Host {
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    #[test]
+    fn parse_literal() {
+        let cases = [
+            ("203.0.113.5:51820", addr("203.0.113.5:51820")),
+            (" 203.0.113.5:0\n", addr("203.0.113.5:0")),
+            ("[2001:db8::1]:51820", addr("[2001:db8::1]:51820")),
+            ("[::]:65535", addr("[::]:65535")),
+            // IPv4-mapped IPv6 address is stored as IPv4
+            ("[::ffff:192.0.2.1]:51820", addr("192.0.2.1:51820")),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(parse(s), expected, "{s}");
+        }
+    }
+
+    #[test]
+    fn parse_host() {
+        let cases = [
+            ("vpn.example.com:51820", host("vpn.example.com", 51820)),
+            ("localhost:1", host("localhost", 1)),
+            // Not a valid IPv4 address, left to resolver
+            ("192.0.2.256:51820", host("192.0.2.256", 51820)),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(parse(s), expected, "{s}");
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = [
+            ("", "missing port"),
+            ("203.0.113.5", "missing port"),
+            ("vpn.example.com", "missing port"),
+            ("[2001:db8::1]", "missing port after bracketed address"),
+            ("[2001:db8::1]51820", "missing port after bracketed address"),
+            ("[2001:db8::1:51820", "missing closing bracket"),
+            ("2001:db8::1:51820", "IPv6 address should be enclosed"),
+            ("[203.0.113.5]:51820", "only IPv6 address can be enclosed"),
+            (
+                "[vpn.example.com]:51820",
+                "only IPv6 address can be enclosed",
+            ),
+            (":51820", "missing host"),
+            ("203.0.113.5:", "invalid port"),
+            ("203.0.113.5:65536", "invalid port"),
+            ("[2001:db8::1]:port", "invalid port"),
+        ];
+        for (s, reason) in cases {
+            let e = WireguardEndpoint::from_str(s).unwrap_err();
+            assert_eq!(e.kind, ErrorKind::InvalidConfig, "{s}");
+            assert!(e.msg.contains(reason), "{s}: {e}");
+        }
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for s in [
+            "203.0.113.5:51820",
+            "[2001:db8::1]:51820",
+            "vpn.example.com:51820",
+        ] {
+            assert_eq!(parse(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn resolve_literal() {
+        assert_eq!(
+            parse("[::ffff:192.0.2.1]:51820").resolve().unwrap(),
+            "192.0.2.1:51820".parse().unwrap()
+        );
+    }
+}
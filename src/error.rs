@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: MIT
 
-use netlink_packet_core::NetlinkMessage;
+use netlink_packet_core::{DecodeError, ErrorMessage, NetlinkMessage};
 use netlink_packet_generic::GenlMessage;
 use netlink_packet_wireguard::WireguardMessage;
 
@@ -11,6 +11,20 @@ pub enum ErrorKind {
     DecodeError,
     /// Invalid key, should be base64 encoded of [u8; 32]
     InvalidKey,
+    /// Netlink attribute exceeds the size limit of kernel
+    TooLarge,
+    /// Invalid or incomplete configuration
+    InvalidConfig,
+    /// Failed to read or write file
+    IoError,
+    /// No unused address left in IP pool
+    IpPoolExhausted,
+    /// External command failed
+    CommandFailed,
+    /// Interface exists but is not a wireguard interface
+    NotWireguard,
+    /// Interface does not exist
+    NoSuchInterface,
 }
 
 impl std::fmt::Display for ErrorKind {
@@ -23,11 +37,20 @@ impl std::fmt::Display for ErrorKind {
                 Self::NetlinkError => "netlink_error",
                 Self::DecodeError => "decode_error",
                 Self::InvalidKey => "invalid_key",
+                Self::TooLarge => "too_large",
+                Self::InvalidConfig => "invalid_config",
+                Self::IoError => "io_error",
+                Self::IpPoolExhausted => "ip_pool_exhausted",
+                Self::CommandFailed => "command_failed",
+                Self::NotWireguard => "not_wireguard",
+                Self::NoSuchInterface => "no_such_interface",
             }
         )
     }
 }
 
+/// The alternate form of Display (`{:#}`) renders the netlink message as
+/// indented attribute tree with keys redacted.
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct WireguardError {
     pub kind: ErrorKind,
@@ -38,6 +61,15 @@ pub struct WireguardError {
 impl std::fmt::Display for WireguardError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(nl_msg) = self.netlink_msg.as_ref() {
+            if f.alternate() {
+                return write!(
+                    f,
+                    "{}: {}, netlink message:\n{}",
+                    self.kind,
+                    self.msg,
+                    crate::pretty::pretty_netlink_message(nl_msg)
+                );
+            }
             write!(
                 f,
                 "{}: {}, netlink message: {:?}",
@@ -64,3 +96,92 @@ impl WireguardError {
         }
     }
 }
+
+impl From<DecodeError> for WireguardError {
+    fn from(e: DecodeError) -> Self {
+        Self::new(
+            ErrorKind::DecodeError,
+            format!("netlink decode error: {e}"),
+            None,
+        )
+    }
+}
+
+/// Netlink error reply from kernel
+impl From<ErrorMessage> for WireguardError {
+    fn from(e: ErrorMessage) -> Self {
+        Self::new(
+            ErrorKind::NetlinkError,
+            match e.code {
+                Some(code) => {
+                    format!("netlink error: {}", errno_to_string(code.get()))
+                }
+                None => format!("netlink error: {e:?}"),
+            },
+            None,
+        )
+    }
+}
+
+#[cfg(feature = "connection")]
+impl From<genetlink::GenetlinkError> for WireguardError {
+    fn from(e: genetlink::GenetlinkError) -> Self {
+        match e {
+            genetlink::GenetlinkError::DecodeError(e) => Self::from(e),
+            genetlink::GenetlinkError::NetlinkError(e) => Self::new(
+                ErrorKind::NetlinkError,
+                match e.raw_os_error() {
+                    Some(errno) => {
+                        format!("netlink error: {}", errno_to_string(errno))
+                    }
+                    None => format!("netlink error: {e}"),
+                },
+                None,
+            ),
+            e => Self::new(
+                ErrorKind::NetlinkError,
+                format!("Netlink request failed: {e}"),
+                None,
+            ),
+        }
+    }
+}
+
+// Symbolic name and description of errno commonly seen in netlink replies
+const ERRNO_NAMES: [(i32, &str, &str); 25] = [
+    (1, "EPERM", "operation not permitted"),
+    (2, "ENOENT", "no such file or directory"),
+    (3, "ESRCH", "no such process"),
+    (4, "EINTR", "interrupted system call"),
+    (5, "EIO", "input/output error"),
+    (7, "E2BIG", "argument list too long"),
+    (9, "EBADF", "bad file descriptor"),
+    (11, "EAGAIN", "resource temporarily unavailable"),
+    (12, "ENOMEM", "cannot allocate memory"),
+    (13, "EACCES", "permission denied"),
+    (14, "EFAULT", "bad address"),
+    (16, "EBUSY", "device or resource busy"),
+    (17, "EEXIST", "file exists"),
+    (19, "ENODEV", "no such device"),
+    (22, "EINVAL", "invalid argument"),
+    (28, "ENOSPC", "no space left on device"),
+    (34, "ERANGE", "numerical result out of range"),
+    (90, "EMSGSIZE", "message too long"),
+    (93, "EPROTONOSUPPORT", "protocol not supported"),
+    (95, "EOPNOTSUPP", "operation not supported"),
+    (98, "EADDRINUSE", "address already in use"),
+    (99, "EADDRNOTAVAIL", "cannot assign requested address"),
+    (105, "ENOBUFS", "no buffer space available"),
+    (110, "ETIMEDOUT", "connection timed out"),
+    (524, "ENOTSUPP", "operation not supported"),
+];
+
+/// Render errno (sign ignored) like `ENODEV (no such device)`, falls back
+/// to `errno 1234` for unknown ones.
+pub(crate) fn errno_to_string(errno: i32) -> String {
+    let errno = errno.saturating_abs();
+    match ERRNO_NAMES.iter().find(|(n, _, _)| *n == errno) {
+        Some((_, name, desc)) => format!("{name} ({desc})"),
+        None => format!("errno {errno}"),
+    }
+}
@@ -0,0 +1,279 @@
+// SPDX-License-Identifier: MIT
+
+use std::{net::SocketAddr, time::Duration};
+
+use crate::{WireguardDeviceStats, WireguardParsed};
+
+/// Change of wireguard interface regardless of how it was detected.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum WireguardEvent {
+    DeviceAdded {
+        iface_name: String,
+    },
+    DeviceRemoved {
+        iface_name: String,
+    },
+    PeerAdded {
+        iface_name: String,
+        public_key: String,
+    },
+    PeerRemoved {
+        iface_name: String,
+        public_key: String,
+    },
+    EndpointChanged {
+        iface_name: String,
+        public_key: String,
+        endpoint: Option<SocketAddr>,
+    },
+    HandshakeCompleted {
+        iface_name: String,
+        public_key: String,
+        /// Handshake time since UNIX_EPOCH
+        last_handshake: Duration,
+    },
+    /// Public key of the interface changed
+    KeyChanged {
+        iface_name: String,
+        public_key: Option<String>,
+    },
+    StatsUpdated(WireguardDeviceStats),
+}
+
+impl WireguardEvent {
+    /// Events between two snapshots of the same interface, `None` means
+    /// the interface does not exist. [WireguardEvent::StatsUpdated] is
+    /// always the last event when `current` exists.
+    pub fn diff(
+        iface_name: &str,
+        previous: Option<&WireguardParsed>,
+        current: Option<&WireguardParsed>,
+    ) -> Vec<Self> {
+        let mut ret = Vec::new();
+        let current = match (previous, current) {
+            (None, None) => return ret,
+            (Some(_), None) => {
+                ret.push(Self::DeviceRemoved {
+                    iface_name: iface_name.to_string(),
+                });
+                return ret;
+            }
+            (None, Some(cur)) => {
+                ret.push(Self::DeviceAdded {
+                    iface_name: iface_name.to_string(),
+                });
+                cur
+            }
+            (Some(prev), Some(cur)) => {
+                if prev.public_key != cur.public_key {
+                    ret.push(Self::KeyChanged {
+                        iface_name: iface_name.to_string(),
+                        public_key: cur.public_key.clone(),
+                    });
+                }
+                cur
+            }
+        };
+
+        for peer in current.peers_iter() {
+            let Some(public_key) = peer.public_key.as_deref() else {
+                continue;
+            };
+            let prev_peer = previous.and_then(|p| {
+                p.peers_iter()
+                    .find(|p| p.public_key.as_deref() == Some(public_key))
+            });
+            let Some(prev_peer) = prev_peer else {
+                ret.push(Self::PeerAdded {
+                    iface_name: iface_name.to_string(),
+                    public_key: public_key.to_string(),
+                });
+                continue;
+            };
+            if prev_peer.endpoint != peer.endpoint {
+                ret.push(Self::EndpointChanged {
+                    iface_name: iface_name.to_string(),
+                    public_key: public_key.to_string(),
+                    endpoint: peer.endpoint,
+                });
+            }
+            if let Some(last_handshake) = peer.last_handshake {
+                if prev_peer.last_handshake != Some(last_handshake) {
+                    ret.push(Self::HandshakeCompleted {
+                        iface_name: iface_name.to_string(),
+                        public_key: public_key.to_string(),
+                        last_handshake,
+                    });
+                }
+            }
+        }
+
+        if let Some(previous) = previous {
+            for prev_peer in previous.peers_iter() {
+                if let Some(public_key) = prev_peer.public_key.as_deref() {
+                    if !current.contains_peer(public_key) {
+                        ret.push(Self::PeerRemoved {
+                            iface_name: iface_name.to_string(),
+                            public_key: public_key.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+
+        ret.push(Self::StatsUpdated(WireguardDeviceStats::from(current)));
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WireguardPeerParsed;
+
+    const KEY1: &str = "uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM=";
+    const KEY2: &str = "WEAuaVuhdyscyTCXVfBDJR6nf9zxD75jmJzrfhkyE3Y=";
+    const KEY3: &str = "hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=";
+
+    fn peer(
+        public_key: &str,
+        endpoint: Option<&str>,
+        last_handshake: Option<u64>,
+    ) -> WireguardPeerParsed {
+        WireguardPeerParsed {
+            endpoint: endpoint.map(|e| e.parse().unwrap()),
+            last_handshake: last_handshake.map(Duration::from_secs),
+            ..WireguardPeerParsed::new(public_key)
+        }
+    }
+
+    fn snapshot(peers: Vec<WireguardPeerParsed>) -> WireguardParsed {
+        WireguardParsed {
+            public_key: Some(KEY3.to_string()),
+            ..WireguardParsed::new("wg0")
+        }
+        .with_peers(peers)
+    }
+
+    // Events except the trailing StatsUpdated which is checked here
+    fn diff(
+        previous: Option<&WireguardParsed>,
+        current: Option<&WireguardParsed>,
+    ) -> Vec<WireguardEvent> {
+        let mut events = WireguardEvent::diff("wg0", previous, current);
+        if let Some(current) = current {
+            assert_eq!(
+                events.pop(),
+                Some(WireguardEvent::StatsUpdated(WireguardDeviceStats::from(
+                    current
+                )))
+            );
+        }
+        events
+    }
+
+    #[test]
+    fn device_added_and_removed() {
+        let cur = snapshot(vec![peer(KEY1, None, None)]);
+        assert_eq!(
+            diff(None, Some(&cur)),
+            [
+                WireguardEvent::DeviceAdded {
+                    iface_name: "wg0".to_string(),
+                },
+                WireguardEvent::PeerAdded {
+                    iface_name: "wg0".to_string(),
+                    public_key: KEY1.to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            diff(Some(&cur), None),
+            [WireguardEvent::DeviceRemoved {
+                iface_name: "wg0".to_string(),
+            }]
+        );
+        assert!(diff(None, None).is_empty());
+    }
+
+    #[test]
+    fn peer_added_and_removed() {
+        let prev = snapshot(vec![peer(KEY1, None, None)]);
+        let cur = snapshot(vec![peer(KEY2, None, None)]);
+        assert_eq!(
+            diff(Some(&prev), Some(&cur)),
+            [
+                WireguardEvent::PeerAdded {
+                    iface_name: "wg0".to_string(),
+                    public_key: KEY2.to_string(),
+                },
+                WireguardEvent::PeerRemoved {
+                    iface_name: "wg0".to_string(),
+                    public_key: KEY1.to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn endpoint_and_handshake_changed() {
+        let prev = snapshot(vec![
+            peer(KEY1, Some("192.0.2.1:51820"), Some(100)),
+            peer(KEY2, None, None),
+        ]);
+        let cur = snapshot(vec![
+            peer(KEY1, Some("192.0.2.2:51820"), Some(100)),
+            peer(KEY2, None, Some(200)),
+        ]);
+        assert_eq!(
+            diff(Some(&prev), Some(&cur)),
+            [
+                WireguardEvent::EndpointChanged {
+                    iface_name: "wg0".to_string(),
+                    public_key: KEY1.to_string(),
+                    endpoint: Some("192.0.2.2:51820".parse().unwrap()),
+                },
+                WireguardEvent::HandshakeCompleted {
+                    iface_name: "wg0".to_string(),
+                    public_key: KEY2.to_string(),
+                    last_handshake: Duration::from_secs(200),
+                },
+            ]
+        );
+
+        // Losing endpoint is reported, losing handshake time is not
+        let cur =
+            snapshot(vec![peer(KEY1, None, None), peer(KEY2, None, None)]);
+        assert_eq!(
+            diff(Some(&prev), Some(&cur)),
+            [WireguardEvent::EndpointChanged {
+                iface_name: "wg0".to_string(),
+                public_key: KEY1.to_string(),
+                endpoint: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn key_changed() {
+        let prev = snapshot(vec![peer(KEY1, None, None)]);
+        let cur = WireguardParsed {
+            public_key: Some(KEY2.to_string()),
+            ..prev.clone()
+        };
+        assert_eq!(
+            diff(Some(&prev), Some(&cur)),
+            [WireguardEvent::KeyChanged {
+                iface_name: "wg0".to_string(),
+                public_key: Some(KEY2.to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn unchanged() {
+        let prev = snapshot(vec![peer(KEY1, Some("192.0.2.1:51820"), Some(1))]);
+        assert!(diff(Some(&prev), Some(&prev.clone())).is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+// SPDX-License-Identifier: MIT
+
+use std::collections::BTreeMap;
+
+use futures_util::StreamExt;
+use netlink_packet_core::{NetlinkMessage, NetlinkPayload, NLM_F_REQUEST};
+use netlink_packet_generic::{
+    ctrl::{
+        nlas::{GenlCtrlAttrs, McastGrpAttrs, OpAttrs},
+        GenlCtrl, GenlCtrlCmd,
+    },
+    GenlFamily, GenlMessage,
+};
+use netlink_packet_wireguard::WireguardMessage;
+
+use crate::{ErrorKind, WireguardError, WireguardHandle};
+
+/// Generic netlink family of wireguard advertised by kernel
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[non_exhaustive]
+pub struct WireguardFamilyInfo {
+    pub name: String,
+    pub id: u16,
+    pub version: u32,
+    pub max_attr: u32,
+    /// Supported operations (commands)
+    pub operations: Vec<WireguardFamilyOperation>,
+    /// Multicast group name to ID
+    pub multicast_groups: BTreeMap<String, u32>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[non_exhaustive]
+pub struct WireguardFamilyOperation {
+    /// Command ID, e.g. 0 for `WG_CMD_GET_DEVICE`
+    pub id: u32,
+    /// `GENL_ADMIN_PERM`, `GENL_CMD_CAP_DO` and etc
+    pub flags: u32,
+}
+
+impl WireguardHandle {
+    /// Query the wireguard generic netlink family from kernel.
+    pub async fn family_info(
+        &mut self,
+    ) -> Result<WireguardFamilyInfo, WireguardError> {
+        let mut nl_msg =
+            NetlinkMessage::from(GenlMessage::from_payload(GenlCtrl {
+                cmd: GenlCtrlCmd::GetFamily,
+                nlas: vec![GenlCtrlAttrs::FamilyName(
+                    WireguardMessage::family_name().to_string(),
+                )],
+            }));
+        nl_msg.header.flags = NLM_F_REQUEST;
+        let mut replies = self.handle.request(nl_msg).await.map_err(|e| {
+            WireguardError::new(
+                ErrorKind::NetlinkError,
+                format!("Failed to query wireguard family: {e}"),
+                None,
+            )
+        })?;
+        let reply = match replies.next().await {
+            Some(Ok(reply)) => reply,
+            Some(Err(e)) => {
+                return Err(WireguardError::new(
+                    ErrorKind::DecodeError,
+                    format!("Failed to decode wireguard family: {e}"),
+                    None,
+                ));
+            }
+            None => {
+                return Err(WireguardError::new(
+                    ErrorKind::Bug,
+                    "Got no reply from kernel for family query".to_string(),
+                    None,
+                ));
+            }
+        };
+        match reply.payload {
+            NetlinkPayload::InnerMessage(genl_msg) => {
+                Ok(WireguardFamilyInfo::from(genl_msg.payload))
+            }
+            NetlinkPayload::Error(e) => Err(WireguardError::new(
+                ErrorKind::NetlinkError,
+                format!("Failed to query wireguard family: {e}"),
+                None,
+            )),
+            payload => Err(WireguardError::new(
+                ErrorKind::Bug,
+                format!("Unexpected NetlinkPayload type: {payload:?}"),
+                None,
+            )),
+        }
+    }
+}
+
+impl From<GenlCtrl> for WireguardFamilyInfo {
+    fn from(msg: GenlCtrl) -> Self {
+        let mut ret = Self::default();
+        for nla in msg.nlas {
+            match nla {
+                GenlCtrlAttrs::FamilyId(v) => ret.id = v,
+                GenlCtrlAttrs::FamilyName(v) => ret.name = v,
+                GenlCtrlAttrs::Version(v) => ret.version = v,
+                GenlCtrlAttrs::MaxAttr(v) => ret.max_attr = v,
+                GenlCtrlAttrs::Ops(ops) => {
+                    for op_attrs in ops {
+                        let mut op = WireguardFamilyOperation::default();
+                        for attr in op_attrs {
+                            match attr {
+                                OpAttrs::Id(v) => op.id = v,
+                                OpAttrs::Flags(v) => op.flags = v,
+                            }
+                        }
+                        ret.operations.push(op);
+                    }
+                }
+                GenlCtrlAttrs::McastGroups(groups) => {
+                    for grp_attrs in groups {
+                        let mut name = None;
+                        let mut id = None;
+                        for attr in grp_attrs {
+                            match attr {
+                                McastGrpAttrs::Name(v) => name = Some(v),
+                                McastGrpAttrs::Id(v) => id = Some(v),
+                            }
+                        }
+                        if let (Some(name), Some(id)) = (name, id) {
+                            ret.multicast_groups.insert(name, id);
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        ret
+    }
+}
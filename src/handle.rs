@@ -1,64 +1,445 @@
 // SPDX-License-Identifier: MIT
 
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use base64::{prelude::BASE64_STANDARD, Engine};
 use futures_util::{Stream, StreamExt};
 use genetlink::GenetlinkHandle;
 use netlink_packet_core::{
-    DecodeError, NetlinkMessage, NetlinkPayload, NLM_F_ACK, NLM_F_DUMP,
-    NLM_F_REQUEST,
+    DecodeError, Emitable, NetlinkHeader, NetlinkMessage, NetlinkPayload,
+    NLA_HEADER_SIZE, NLM_F_ACK, NLM_F_DUMP, NLM_F_REQUEST,
 };
 use netlink_packet_generic::GenlMessage;
-use netlink_packet_wireguard::{WireguardCmd, WireguardMessage};
+use netlink_packet_wireguard::{
+    WireguardAttribute, WireguardCmd, WireguardMessage,
+};
 
-use crate::{ErrorKind, WireguardError, WireguardParsed};
+use crate::{
+    parsed::NLA_MAX_LEN, ErrorKind, WireguardChange, WireguardDeviceStats,
+    WireguardError, WireguardJournal, WireguardJournalEntry, WireguardParsed,
+    WireguardParsedDeviceFlags, WireguardParsedPeerFlags, WireguardPeerParsed,
+};
 
 #[derive(Clone, Debug)]
 pub struct WireguardHandle {
-    handle: GenetlinkHandle,
+    pub(crate) handle: GenetlinkHandle,
+    verify_destructive: bool,
+    journal: Option<JournalSink>,
+    protocol_hook: Option<ProtocolHook>,
+    slow_threshold: Option<Duration>,
+    parse_warning_hook: Option<ParseWarningHook>,
+}
+
+type ParseWarningCallback = dyn Fn(&str, &WireguardError) + Send + Sync;
+
+#[derive(Clone)]
+struct ParseWarningHook(Arc<ParseWarningCallback>);
+
+impl std::fmt::Debug for ParseWarningHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ParseWarningHook").finish_non_exhaustive()
+    }
+}
+
+type ProtocolCallback = dyn Fn(&str, &WireguardPeerParsed) + Send + Sync;
+
+#[derive(Clone)]
+struct ProtocolHook {
+    expected: u32,
+    callback: Arc<ProtocolCallback>,
+}
+
+impl std::fmt::Debug for ProtocolHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProtocolHook")
+            .field("expected", &self.expected)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone)]
+struct JournalSink(Arc<Mutex<dyn WireguardJournal>>);
+
+impl std::fmt::Debug for JournalSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JournalSink").finish_non_exhaustive()
+    }
 }
 
 impl WireguardHandle {
     pub(crate) fn new(handle: GenetlinkHandle) -> Self {
-        WireguardHandle { handle }
+        WireguardHandle {
+            handle,
+            verify_destructive: false,
+            journal: None,
+            protocol_hook: None,
+            slow_threshold: None,
+            parse_warning_hook: None,
+        }
+    }
+
+    /// Record the changes made by every [WireguardHandle::set()] to
+    /// `journal`. The current configuration is queried before each set
+    /// for comparison, if that query fails, the set is not recorded.
+    /// Failure of recording is logged without failing the set. Cloned
+    /// handles share the same journal.
+    pub fn with_journal<J>(mut self, journal: J) -> Self
+    where
+        J: WireguardJournal + 'static,
+    {
+        self.journal = Some(JournalSink(Arc::new(Mutex::new(journal))));
+        self
+    }
+
+    /// Invoke `callback` with interface name and peer whenever
+    /// [WireguardHandle::get_by_name()] finds peer reporting protocol
+    /// version other than `expected` (usually
+    /// [WireguardPeerParsed::PROTOCOL_VERSION]), so incompatible or future
+    /// protocol peers are noticed during fleet upgrades. Cloned handles
+    /// share the same callback.
+    pub fn on_unexpected_protocol<F>(
+        mut self,
+        expected: u32,
+        callback: F,
+    ) -> Self
+    where
+        F: Fn(&str, &WireguardPeerParsed) + Send + Sync + 'static,
+    {
+        self.protocol_hook = Some(ProtocolHook {
+            expected,
+            callback: Arc::new(callback),
+        });
+        self
+    }
+
+    /// Invoke `callback` with interface name and the invalid attribute
+    /// ignored by [WireguardHandle::get_by_name()] (see
+    /// [WireguardParsed::parse_with_warnings()]) instead of logging it.
+    /// Cloned handles share the same callback.
+    pub fn on_parse_warning<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&str, &WireguardError) + Send + Sync + 'static,
+    {
+        self.parse_warning_hook = Some(ParseWarningHook(Arc::new(callback)));
+        self
+    }
+
+    /// Log warning with interface name and message size when a get or set
+    /// takes longer than `threshold`, to help diagnosing netlink latency
+    /// on overloaded systems. Disabled by default.
+    pub fn warn_slow_request(mut self, threshold: Option<Duration>) -> Self {
+        self.slow_threshold = threshold;
+        self
+    }
+
+    /// When enabled, [WireguardHandle::set()] verifies the target is a
+    /// wireguard interface via [WireguardHandle::verify_wireguard()] before
+    /// sending destructive changes (replacing peers or allowed IPs,
+    /// removing peers). Disabled by default.
+    pub fn verify_before_destructive(mut self, enabled: bool) -> Self {
+        self.verify_destructive = enabled;
+        self
+    }
+
+    /// Return [ErrorKind::NotWireguard] error if specified interface is not
+    /// a wireguard interface handled by kernel.
+    pub async fn verify_wireguard(
+        &mut self,
+        iface_name: &str,
+    ) -> Result<(), WireguardError> {
+        self.query(iface_name).await.map(|_| ())
     }
 
     pub async fn get_by_name(
         &mut self,
         iface_name: &str,
     ) -> Result<WireguardParsed, WireguardError> {
-        let msg = WireguardParsed {
-            iface_name: Some(iface_name.to_string()),
-            ..Default::default()
+        let reply = self.query(iface_name).await?;
+        let parsed = match self.parse_warning_hook.as_ref() {
+            Some(hook) => {
+                let (parsed, warnings) =
+                    WireguardParsed::parse_with_warnings(reply);
+                for warning in &warnings {
+                    (hook.0)(iface_name, warning);
+                }
+                parsed
+            }
+            None => WireguardParsed::from(reply),
+        };
+        if let Some(hook) = self.protocol_hook.as_ref() {
+            for peer in parsed.unexpected_protocol_peers(hook.expected) {
+                (hook.callback)(iface_name, peer);
+            }
         }
-        .build(WireguardCmd::GetDevice)?;
-        match self
+        Ok(parsed)
+    }
+
+    // Query the device without invoking the hooks
+    async fn query(
+        &mut self,
+        iface_name: &str,
+    ) -> Result<WireguardMessage, WireguardError> {
+        let msg =
+            WireguardParsed::new(iface_name).build(WireguardCmd::GetDevice)?;
+        let started = Instant::now();
+        let reply = self
             .request(NLM_F_REQUEST | NLM_F_ACK | NLM_F_DUMP, msg.clone())
             .await?
             .next()
-            .await
-        {
+            .await;
+        self.warn_if_slow(
+            "get",
+            iface_name,
+            reply
+                .as_ref()
+                .and_then(|r| r.as_ref().ok())
+                .map(|r| r.buffer_len())
+                .unwrap_or_default(),
+            started,
+        );
+        match reply {
             None => Err(WireguardError::new(
                 ErrorKind::Bug,
                 "Got no reply from kernel for request".to_string(),
                 Some(NetlinkMessage::from(GenlMessage::from_payload(msg))),
             )),
-            Some(reply) => reply.map(WireguardParsed::from),
+            Some(reply) => reply,
+        }
+    }
+
+    /// Query all wireguard interfaces sorted by name. Interfaces are
+    /// enumerated via `/sys/class/net`, hence only those in the network
+    /// namespace of the mounted sysfs are found.
+    pub async fn list_all(
+        &mut self,
+    ) -> Result<Vec<WireguardParsed>, WireguardError> {
+        let mut ret = Vec::new();
+        for iface_name in wireguard_iface_names()? {
+            match self.get_by_name(&iface_name).await {
+                Ok(parsed) => ret.push(parsed),
+                Err(e) if removed_after_enumeration(&e) => {
+                    log::debug!("Skipping {iface_name}: {e}");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Query traffic statistics of specified wireguard interface.
+    /// Keys and allowed IPs in kernel reply are ignored rather than
+    /// converted, hence this is cheaper than [WireguardHandle::get_by_name()]
+    /// for frequent monitoring.
+    pub async fn get_stats(
+        &mut self,
+        iface_name: &str,
+    ) -> Result<WireguardDeviceStats, WireguardError> {
+        let msg =
+            WireguardParsed::new(iface_name).build(WireguardCmd::GetDevice)?;
+        let started = Instant::now();
+        let mut replies = self
+            .request(NLM_F_REQUEST | NLM_F_ACK | NLM_F_DUMP, msg)
+            .await?;
+        let mut stats = WireguardDeviceStats::default();
+        let mut reply_len = 0;
+        while let Some(reply) = replies.next().await {
+            let reply = reply?;
+            if self.slow_threshold.is_some() {
+                reply_len += reply.buffer_len();
+            }
+            stats.append(reply);
+        }
+        self.warn_if_slow("get_stats", iface_name, reply_len, started);
+        Ok(stats)
+    }
+
+    /// Query listen port of specified wireguard interface. Peers in kernel
+    /// reply are ignored.
+    pub async fn get_listen_port(
+        &mut self,
+        iface_name: &str,
+    ) -> Result<Option<u16>, WireguardError> {
+        self.get_device_attr(iface_name, |attr| match attr {
+            WireguardAttribute::ListenPort(v) => Some(v),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Query base64 encoded public key of specified wireguard interface.
+    /// Peers in kernel reply are ignored.
+    pub async fn get_public_key(
+        &mut self,
+        iface_name: &str,
+    ) -> Result<Option<String>, WireguardError> {
+        self.get_device_attr(iface_name, |attr| match attr {
+            WireguardAttribute::PublicKey(v) => Some(BASE64_STANDARD.encode(v)),
+            _ => None,
+        })
+        .await
+    }
+
+    // Kernel repeats device attributes in every message of the dump, hence
+    // only the first match is used.
+    async fn get_device_attr<T, F>(
+        &mut self,
+        iface_name: &str,
+        mut extract: F,
+    ) -> Result<Option<T>, WireguardError>
+    where
+        F: FnMut(WireguardAttribute) -> Option<T>,
+    {
+        let msg =
+            WireguardParsed::new(iface_name).build(WireguardCmd::GetDevice)?;
+        let started = Instant::now();
+        let mut replies = self
+            .request(NLM_F_REQUEST | NLM_F_ACK | NLM_F_DUMP, msg)
+            .await?;
+        let mut ret = None;
+        let mut reply_len = 0;
+        while let Some(reply) = replies.next().await {
+            let reply = reply?;
+            if self.slow_threshold.is_some() {
+                reply_len += reply.buffer_len();
+            }
+            if ret.is_none() {
+                ret = reply.attributes.into_iter().find_map(&mut extract);
+            }
         }
+        self.warn_if_slow("get", iface_name, reply_len, started);
+        Ok(ret)
     }
 
     pub async fn set(
         &mut self,
         parsed: WireguardParsed,
     ) -> Result<(), WireguardError> {
+        if self.verify_destructive && is_destructive(&parsed) {
+            if let Some(iface_name) = parsed.iface_name.as_deref() {
+                self.verify_wireguard(iface_name).await?;
+            }
+        }
         let msg = parsed.build(WireguardCmd::SetDevice)?;
+        let before =
+            match (self.journal.is_some(), parsed.iface_name.as_deref()) {
+                // Without the state before, the changes are unknown,
+                // hence no journal entry rather than a wrong one.
+                (true, Some(iface_name)) => {
+                    match self.query(iface_name).await {
+                        Ok(reply) => {
+                            Some(WireguardParsed::parse_with_warnings(reply).0)
+                        }
+                        Err(e) => {
+                            log::warn!(
+                                "Failed to query {iface_name} before set, no \
+                                 journal entry will be recorded: {e}"
+                            );
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            };
+        let started = Instant::now();
         //TODO: Polished this
-        match self
+        let reply = self
             .request(NLM_F_REQUEST | NLM_F_ACK, msg.clone())
             .await?
             .next()
-            .await
-        {
-            None | Some(Ok(_)) => Ok(()),
-            Some(Err(e)) => Err(e),
+            .await;
+        self.warn_if_slow(
+            "set",
+            parsed.iface_name.as_deref().unwrap_or_default(),
+            msg.buffer_len(),
+            started,
+        );
+        match reply {
+            None | Some(Ok(_)) => (),
+            Some(Err(e)) => return Err(e),
+        }
+        if let (Some(before), Some(journal)) = (before, self.journal.as_ref()) {
+            journal.record(&before, &parsed);
+        }
+        Ok(())
+    }
+
+    /// Add or update `peers` of specified interface. Peers are consumed
+    /// from the iterator and sent in batches each fitting in one set
+    /// request, hence the whole peer set is never held in memory. With
+    /// `replace`, the first batch carries
+    /// [WireguardParsedDeviceFlags::ReplacePeers] so existing peers not in
+    /// `peers` are removed.
+    ///
+    /// On failure, the batches already sent stay applied.
+    /// Return the number of peers sent.
+    pub async fn set_peers<I>(
+        &mut self,
+        iface_name: &str,
+        peers: I,
+        replace: bool,
+    ) -> Result<usize, WireguardError>
+    where
+        I: IntoIterator<Item = WireguardPeerParsed>,
+    {
+        let mut count = 0usize;
+        let mut batch: Vec<WireguardPeerParsed> = Vec::new();
+        // Header of the `Peers` attribute
+        let mut batch_len = NLA_HEADER_SIZE;
+        let mut first = true;
+        for peer in peers {
+            let peer_len = peer.build()?.buffer_len();
+            if !batch.is_empty() && batch_len + peer_len > NLA_MAX_LEN {
+                batch_len = NLA_HEADER_SIZE;
+                count += batch.len();
+                self.set_peer_batch(
+                    iface_name,
+                    std::mem::take(&mut batch),
+                    replace && first,
+                )
+                .await?;
+                first = false;
+            }
+            batch_len += peer_len;
+            batch.push(peer);
+        }
+        if !batch.is_empty() || (replace && first) {
+            count += batch.len();
+            self.set_peer_batch(iface_name, batch, replace && first)
+                .await?;
+        }
+        Ok(count)
+    }
+
+    async fn set_peer_batch(
+        &mut self,
+        iface_name: &str,
+        peers: Vec<WireguardPeerParsed>,
+        replace: bool,
+    ) -> Result<(), WireguardError> {
+        let mut parsed = WireguardParsed::new(iface_name).with_peers(peers);
+        if replace {
+            parsed.flags = Some(vec![WireguardParsedDeviceFlags::ReplacePeers]);
+        }
+        self.set(parsed).await
+    }
+
+    fn warn_if_slow(
+        &self,
+        operation: &str,
+        iface_name: &str,
+        msg_len: usize,
+        started: Instant,
+    ) {
+        let elapsed = started.elapsed();
+        if let Some(threshold) = self.slow_threshold.filter(|t| elapsed > *t) {
+            log::warn!(
+                "Slow wireguard {operation} of interface {iface_name}: took \
+                 {elapsed:?} exceeding {threshold:?}, message size {msg_len} \
+                 bytes"
+            );
         }
     }
 
@@ -72,21 +453,134 @@ impl WireguardHandle {
         impl Stream<Item = Result<WireguardMessage, WireguardError>>,
         WireguardError,
     > {
+        self.request_with_header(message, move |header| {
+            header.flags = nl_header_flags
+        })
+        .await
+    }
+
+    /// Like [WireguardHandle::request()], but let `set_header` modify
+    /// netlink header (e.g. flags and port number) before sending.
+    /// The sequence number is always assigned by the connection, as it is
+    /// used for matching the replies.
+    pub async fn request_with_header<F>(
+        &mut self,
+        message: WireguardMessage,
+        set_header: F,
+    ) -> Result<
+        impl Stream<Item = Result<WireguardMessage, WireguardError>>,
+        WireguardError,
+    >
+    where
+        F: FnOnce(&mut NetlinkHeader),
+    {
         let mut nl_msg =
             NetlinkMessage::from(GenlMessage::from_payload(message));
-        nl_msg.header.flags = nl_header_flags;
+        set_header(&mut nl_msg.header);
 
         match self.handle.request(nl_msg.clone()).await {
             Ok(stream) => Ok(parse_nl_msg_stream(nl_msg, stream)),
+            Err(e) => {
+                let mut e = WireguardError::from(e);
+                e.netlink_msg = Some(nl_msg);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl JournalSink {
+    fn record(&self, before: &WireguardParsed, applied: &WireguardParsed) {
+        let changes = WireguardChange::list(before, applied);
+        if changes.is_empty() {
+            return;
+        }
+        let entry = WireguardJournalEntry {
+            time: SystemTime::now(),
+            iface_name: applied.iface_name.clone().unwrap_or_default(),
+            changes,
+        };
+        let result = match self.0.lock() {
+            Ok(mut journal) => journal.record(&entry),
             Err(e) => Err(WireguardError::new(
-                ErrorKind::NetlinkError,
-                format!("Netlink request failed: {e}"),
-                Some(nl_msg),
+                ErrorKind::Bug,
+                format!("Journal of WireguardHandle poisoned: {e}"),
+                None,
             )),
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to record journal: {e}");
         }
     }
 }
 
+pub(crate) const SYSFS_NET_DIR: &str = "/sys/class/net";
+
+pub(crate) fn wireguard_iface_names() -> Result<Vec<String>, WireguardError> {
+    let entries = std::fs::read_dir(SYSFS_NET_DIR).map_err(|e| {
+        WireguardError::new(
+            ErrorKind::IoError,
+            format!("Failed to read {SYSFS_NET_DIR}: {e}"),
+            None,
+        )
+    })?;
+    let mut ret: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            std::fs::read_to_string(entry.path().join("uevent"))
+                .map(|c| c.lines().any(|l| l == "DEVTYPE=wireguard"))
+                .unwrap_or_default()
+        })
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    ret.sort_unstable();
+    Ok(ret)
+}
+
+fn is_destructive(parsed: &WireguardParsed) -> bool {
+    parsed
+        .flags
+        .as_deref()
+        .unwrap_or_default()
+        .contains(&WireguardParsedDeviceFlags::ReplacePeers)
+        || parsed.peers_iter().any(|peer| {
+            peer.flags.as_deref().unwrap_or_default().iter().any(|f| {
+                matches!(
+                    f,
+                    WireguardParsedPeerFlags::RemoveMe
+                        | WireguardParsedPeerFlags::ReplaceAllowedIps
+                )
+            })
+        })
+}
+
+// EOPNOTSUPP and kernel internal ENOTSUPP, returned by kernel when the
+// interface is not a wireguard interface.
+const NOT_SUPPORTED_ERRNOS: [i32; 2] = [95, 524];
+const ENODEV: i32 = 19;
+
+// Interface deleted, or deleted and recreated with another type, between
+// the sysfs enumeration and the query of list_all()
+fn removed_after_enumeration(e: &WireguardError) -> bool {
+    matches!(e.kind, ErrorKind::NotWireguard | ErrorKind::NoSuchInterface)
+}
+
+fn request_iface_name(
+    nl_msg: &NetlinkMessage<GenlMessage<WireguardMessage>>,
+) -> Option<&str> {
+    if let NetlinkPayload::InnerMessage(genl_msg) = &nl_msg.payload {
+        genl_msg.payload.attributes.iter().find_map(|attr| {
+            if let WireguardAttribute::IfName(name) = attr {
+                Some(name.as_str())
+            } else {
+                None
+            }
+        })
+    } else {
+        None
+    }
+}
+
 fn parse_nl_msg_stream(
     nl_msg: NetlinkMessage<GenlMessage<WireguardMessage>>,
     stream: impl Stream<
@@ -104,11 +598,39 @@ fn parse_nl_msg_stream(
                     let (_genl_hdr, wg_msg) = genl_msg.into_parts();
                     Ok(wg_msg)
                 }
-                NetlinkPayload::Error(ref err) => Err(WireguardError::new(
-                    ErrorKind::NetlinkError,
-                    format!("netlink error: {err:?}"),
-                    Some(NetlinkMessage::new(header, payload)),
-                )),
+                NetlinkPayload::Error(ref err)
+                    if err
+                        .code
+                        .map(|c| NOT_SUPPORTED_ERRNOS.contains(&-c.get()))
+                        .unwrap_or_default() =>
+                {
+                    Err(WireguardError::new(
+                        ErrorKind::NotWireguard,
+                        format!(
+                            "Interface {} is not a wireguard interface",
+                            request_iface_name(&nl_msg).unwrap_or("")
+                        ),
+                        Some(NetlinkMessage::new(header, payload)),
+                    ))
+                }
+                NetlinkPayload::Error(ref err) if err.raw_code() == -ENODEV => {
+                    Err(WireguardError::new(
+                        ErrorKind::NoSuchInterface,
+                        format!(
+                            "Interface {} does not exist",
+                            request_iface_name(&nl_msg).unwrap_or("")
+                        ),
+                        Some(NetlinkMessage::new(header, payload)),
+                    ))
+                }
+                NetlinkPayload::Error(err) => {
+                    let mut e = WireguardError::from(err.clone());
+                    e.netlink_msg = Some(NetlinkMessage::new(
+                        header,
+                        NetlinkPayload::Error(err),
+                    ));
+                    Err(e)
+                }
                 _ => Err(WireguardError::new(
                     ErrorKind::Bug,
                     format!("Unexpected NetlinkPayload type: {payload:?}"),
@@ -116,10 +638,60 @@ fn parse_nl_msg_stream(
                 )),
             }
         }
-        Err(e) => Err(WireguardError::new(
-            ErrorKind::DecodeError,
-            format!("netlink decode error: {e}"),
-            Some(nl_msg.clone()),
-        )),
+        Err(e) => {
+            let mut e = WireguardError::from(e);
+            e.netlink_msg = Some(nl_msg.clone());
+            Err(e)
+        }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroI32;
+
+    use netlink_packet_core::ErrorMessage;
+
+    use super::*;
+
+    async fn reply_error(errno: i32) -> WireguardError {
+        let request = NetlinkMessage::from(GenlMessage::from_payload(
+            WireguardParsed::new("wg0")
+                .build(WireguardCmd::GetDevice)
+                .unwrap(),
+        ));
+        let mut err = ErrorMessage::default();
+        err.code = NonZeroI32::new(-errno);
+        let reply = NetlinkMessage::new(
+            NetlinkHeader::default(),
+            NetlinkPayload::Error(err),
+        );
+        let mut replies = Box::pin(parse_nl_msg_stream(
+            request,
+            futures_util::stream::iter([Ok(reply)]),
+        ));
+        replies.next().await.unwrap().unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn list_all_skips_removed_iface() {
+        let e = reply_error(ENODEV).await;
+        assert_eq!(e.kind, ErrorKind::NoSuchInterface);
+        assert_eq!(e.msg, "Interface wg0 does not exist");
+        assert!(removed_after_enumeration(&e));
+
+        for errno in NOT_SUPPORTED_ERRNOS {
+            let e = reply_error(errno).await;
+            assert_eq!(e.kind, ErrorKind::NotWireguard);
+            assert!(removed_after_enumeration(&e));
+        }
+    }
+
+    #[tokio::test]
+    async fn list_all_fails_on_other_error() {
+        // EPERM
+        let e = reply_error(1).await;
+        assert_eq!(e.kind, ErrorKind::NetlinkError);
+        assert!(!removed_after_enumeration(&e));
+    }
+}
@@ -0,0 +1,258 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::WireguardDeviceStats;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum WireguardHandshakeAlert {
+    /// No handshake with peer within the timeout
+    Timeout {
+        iface_name: String,
+        public_key: String,
+        /// Last handshake time since UNIX_EPOCH, `None` if never handshaked
+        last_handshake: Option<Duration>,
+    },
+    /// Handshake succeeded again after [WireguardHandshakeAlert::Timeout]
+    Recovered {
+        iface_name: String,
+        public_key: String,
+        last_handshake: Duration,
+    },
+}
+
+type AlertCallback = Box<dyn FnMut(&WireguardHandshakeAlert) + Send>;
+
+/// Generate [WireguardHandshakeAlert] from periodic
+/// [WireguardDeviceStats], for example the ones from
+/// [crate::WireguardWatcher].
+///
+/// Each alert is only raised once until the state of peer changes.
+#[derive(Default)]
+pub struct WireguardHandshakeMonitor {
+    default_timeout: Option<Duration>,
+    timeouts: HashMap<String, Duration>,
+    callbacks: Vec<AlertCallback>,
+    // (iface_name, public_key) of peers in timeout state
+    alerted: HashSet<(String, String)>,
+    // When peer never handshaked was first seen
+    first_seen: HashMap<(String, String), Instant>,
+}
+
+impl std::fmt::Debug for WireguardHandshakeMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WireguardHandshakeMonitor")
+            .field("default_timeout", &self.default_timeout)
+            .field("timeouts", &self.timeouts)
+            .field("alerted", &self.alerted)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WireguardHandshakeMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Monitor all peers with specified timeout.
+    pub fn watch_all(mut self, timeout: Duration) -> Self {
+        self.default_timeout = Some(timeout);
+        self
+    }
+
+    /// Monitor peer of specified public key with its own timeout.
+    pub fn watch_peer(mut self, public_key: &str, timeout: Duration) -> Self {
+        self.timeouts.insert(public_key.to_string(), timeout);
+        self
+    }
+
+    /// Invoke `callback` on every alert.
+    pub fn on_alert<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&WireguardHandshakeAlert) + Send + 'static,
+    {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Check the handshake time of peers in `stats`, return new alerts
+    /// after invoking the callbacks.
+    pub fn update(
+        &mut self,
+        stats: &WireguardDeviceStats,
+    ) -> Vec<WireguardHandshakeAlert> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let iface_name = stats.iface_name.clone().unwrap_or_default();
+        let mut alerts = Vec::new();
+        for peer in &stats.peers {
+            let Some(timeout) = self
+                .timeouts
+                .get(&peer.public_key)
+                .copied()
+                .or(self.default_timeout)
+            else {
+                continue;
+            };
+            let id = (iface_name.clone(), peer.public_key.clone());
+            let timed_out = match peer.last_handshake {
+                Some(t) => {
+                    self.first_seen.remove(&id);
+                    now.saturating_sub(t) >= timeout
+                }
+                None => {
+                    self.first_seen
+                        .entry(id.clone())
+                        .or_insert_with(Instant::now)
+                        .elapsed()
+                        >= timeout
+                }
+            };
+            if timed_out {
+                if self.alerted.insert(id) {
+                    alerts.push(WireguardHandshakeAlert::Timeout {
+                        iface_name: iface_name.clone(),
+                        public_key: peer.public_key.clone(),
+                        last_handshake: peer.last_handshake,
+                    });
+                }
+            } else if self.alerted.remove(&id) {
+                if let Some(last_handshake) = peer.last_handshake {
+                    alerts.push(WireguardHandshakeAlert::Recovered {
+                        iface_name: iface_name.clone(),
+                        public_key: peer.public_key.clone(),
+                        last_handshake,
+                    });
+                }
+            }
+        }
+        // Forget removed peers so a re-added one starts from fresh state
+        let present = |id: &(String, String)| {
+            id.0 != iface_name
+                || stats.peers.iter().any(|p| p.public_key == id.1)
+        };
+        self.alerted.retain(present);
+        self.first_seen.retain(|id, _| present(id));
+        for alert in &alerts {
+            for callback in self.callbacks.iter_mut() {
+                callback(alert);
+            }
+        }
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WireguardPeerStats;
+
+    const KEY1: &str = "uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM=";
+    const KEY2: &str = "WEAuaVuhdyscyTCXVfBDJR6nf9zxD75jmJzrfhkyE3Y=";
+
+    fn now() -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
+    }
+
+    fn stats(
+        iface_name: &str,
+        peers: &[(&str, Option<Duration>)],
+    ) -> WireguardDeviceStats {
+        WireguardDeviceStats {
+            iface_name: Some(iface_name.to_string()),
+            peers: peers
+                .iter()
+                .map(|(key, last_handshake)| WireguardPeerStats {
+                    public_key: key.to_string(),
+                    last_handshake: *last_handshake,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn timeout(
+        key: &str,
+        last_handshake: Option<Duration>,
+    ) -> WireguardHandshakeAlert {
+        WireguardHandshakeAlert::Timeout {
+            iface_name: "wg0".to_string(),
+            public_key: key.to_string(),
+            last_handshake,
+        }
+    }
+
+    #[test]
+    fn timeout_and_recovered() {
+        let old = now() - Duration::from_secs(600);
+        let mut monitor = WireguardHandshakeMonitor::new()
+            .watch_all(Duration::from_secs(300))
+            .watch_peer(KEY2, Duration::from_secs(900));
+
+        let alerts = monitor
+            .update(&stats("wg0", &[(KEY1, Some(old)), (KEY2, Some(old))]));
+        assert_eq!(alerts, [timeout(KEY1, Some(old))]);
+        // Only alerted once
+        assert!(monitor
+            .update(&stats("wg0", &[(KEY1, Some(old)), (KEY2, Some(old))]))
+            .is_empty());
+
+        let recent = now();
+        assert_eq!(
+            monitor.update(&stats("wg0", &[(KEY1, Some(recent))])),
+            [WireguardHandshakeAlert::Recovered {
+                iface_name: "wg0".to_string(),
+                public_key: KEY1.to_string(),
+                last_handshake: recent,
+            }]
+        );
+    }
+
+    #[test]
+    fn never_handshaked() {
+        let mut monitor =
+            WireguardHandshakeMonitor::new().watch_all(Duration::ZERO);
+        assert_eq!(
+            monitor.update(&stats("wg0", &[(KEY1, None)])),
+            [timeout(KEY1, None)]
+        );
+        // Not watched peer is ignored
+        let mut monitor =
+            WireguardHandshakeMonitor::new().watch_peer(KEY2, Duration::ZERO);
+        assert!(monitor.update(&stats("wg0", &[(KEY1, None)])).is_empty());
+    }
+
+    #[test]
+    fn removed_peer_is_forgotten() {
+        let old = now() - Duration::from_secs(600);
+        let mut monitor = WireguardHandshakeMonitor::new()
+            .watch_all(Duration::from_secs(300));
+        monitor.update(&stats("wg0", &[(KEY1, Some(old)), (KEY2, None)]));
+        monitor.update(&stats("wg1", &[(KEY1, Some(old))]));
+        assert_eq!(monitor.alerted.len(), 2);
+        assert_eq!(monitor.first_seen.len(), 1);
+
+        // Removing peers from wg0 keeps the state of wg1
+        monitor.update(&stats("wg0", &[]));
+        assert_eq!(
+            monitor.alerted,
+            HashSet::from([("wg1".to_string(), KEY1.to_string())])
+        );
+        assert!(monitor.first_seen.is_empty());
+
+        // Re-added peer does not inherit the timeout state
+        assert!(monitor
+            .update(&stats("wg0", &[(KEY1, Some(now()))]))
+            .is_empty());
+        assert_eq!(
+            monitor.update(&stats("wg0", &[(KEY1, Some(old))])),
+            [timeout(KEY1, Some(old))]
+        );
+    }
+}
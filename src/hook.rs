@@ -0,0 +1,96 @@
+// SPDX-License-Identifier: MIT
+
+#[cfg(feature = "exec")]
+use crate::cmd::run_cmd;
+use crate::{ErrorKind, WireguardError, WireguardQuickConfig};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum WireguardHookKind {
+    PreUp,
+    PostUp,
+    PreDown,
+    PostDown,
+}
+
+impl std::fmt::Display for WireguardHookKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::PreUp => "PreUp",
+                Self::PostUp => "PostUp",
+                Self::PreDown => "PreDown",
+                Self::PostDown => "PostDown",
+            }
+        )
+    }
+}
+
+/// Executor of wg-quick hook commands, implement this to run hooks in a
+/// sandbox or to only audit them.
+pub trait WireguardHookExecutor {
+    /// The `%i` in `command` is already replaced by interface name.
+    fn execute(
+        &mut self,
+        kind: WireguardHookKind,
+        iface_name: &str,
+        command: &str,
+    ) -> Result<(), WireguardError>;
+}
+
+/// Execute hook commands via `bash -c` like `wg-quick` does.
+#[cfg(feature = "exec")]
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub struct WireguardShellHookExecutor;
+
+#[cfg(feature = "exec")]
+impl WireguardHookExecutor for WireguardShellHookExecutor {
+    fn execute(
+        &mut self,
+        _kind: WireguardHookKind,
+        _iface_name: &str,
+        command: &str,
+    ) -> Result<(), WireguardError> {
+        run_cmd("bash", &["-c", command], None)
+    }
+}
+
+impl WireguardQuickConfig {
+    /// Execute hook commands of specified kind in order, stop on first
+    /// failure.
+    pub fn run_hooks(
+        &self,
+        kind: WireguardHookKind,
+        executor: &mut dyn WireguardHookExecutor,
+    ) -> Result<(), WireguardError> {
+        let cmds = match kind {
+            WireguardHookKind::PreUp => &self.pre_up,
+            WireguardHookKind::PostUp => &self.post_up,
+            WireguardHookKind::PreDown => &self.pre_down,
+            WireguardHookKind::PostDown => &self.post_down,
+        };
+        let cmds = cmds.as_deref().unwrap_or_default();
+        if cmds.is_empty() {
+            return Ok(());
+        }
+        let iface_name =
+            self.wireguard.iface_name.as_deref().ok_or_else(|| {
+                WireguardError::new(
+                    ErrorKind::InvalidConfig,
+                    format!("Cannot run {kind} hooks without iface_name"),
+                    None,
+                )
+            })?;
+        for cmd in cmds {
+            executor.execute(
+                kind,
+                iface_name,
+                &cmd.replace("%i", iface_name),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: MIT
+
+use std::path::Path;
+
+use crate::WireguardHandle;
+
+/// Directory holding the UAPI sockets of userspace implementations like
+/// wireguard-go and boringtun.
+pub const WIREGUARD_UAPI_SOCKET_DIR: &str = "/var/run/wireguard";
+
+/// Implementation backing a wireguard interface
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum WireguardImplementation {
+    /// Kernel module, managed via netlink
+    Kernel,
+    /// Userspace implementation, managed via UAPI socket
+    Userspace,
+    /// Not found or not a wireguard interface
+    Unknown,
+}
+
+impl std::fmt::Display for WireguardImplementation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Kernel => "kernel",
+                Self::Userspace => "userspace",
+                Self::Unknown => "unknown",
+            }
+        )
+    }
+}
+
+impl WireguardHandle {
+    /// Detect the implementation of specified interface: kernel if the
+    /// wireguard generic netlink family answers for it, otherwise
+    /// userspace if `<iface>.sock` exists in [WIREGUARD_UAPI_SOCKET_DIR].
+    pub async fn implementation(
+        &mut self,
+        iface_name: &str,
+    ) -> WireguardImplementation {
+        match self.get_by_name(iface_name).await {
+            Ok(_) => return WireguardImplementation::Kernel,
+            Err(e) => {
+                log::debug!(
+                    "Interface {iface_name} is not kernel wireguard: {e}"
+                )
+            }
+        }
+        if Path::new(WIREGUARD_UAPI_SOCKET_DIR)
+            .join(format!("{iface_name}.sock"))
+            .exists()
+        {
+            WireguardImplementation::Userspace
+        } else {
+            WireguardImplementation::Unknown
+        }
+    }
+}
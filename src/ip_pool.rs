@@ -0,0 +1,330 @@
+// SPDX-License-Identifier: MIT
+
+use std::{collections::BTreeSet, net::IpAddr};
+
+use crate::{
+    peer_parsed::{ip_to_u128, u128_to_ip},
+    ErrorKind, WireguardError, WireguardIpAddress, WireguardParsed,
+};
+
+/// Storage of allocated addresses used by [WireguardIpPool].
+pub trait WireguardIpPoolStore: Send {
+    /// Load previously allocated addresses
+    fn load(&mut self) -> Result<Vec<IpAddr>, WireguardError>;
+    /// Invoked with all allocated addresses after every change
+    fn save(&mut self, allocated: &[IpAddr]) -> Result<(), WireguardError>;
+}
+
+/// Allocate unused host addresses from a subnet for new peers.
+///
+/// The network address is never allocated, neither is the broadcast address
+/// of IPv4 subnet.
+pub struct WireguardIpPool {
+    subnet: WireguardIpAddress,
+    allocated: BTreeSet<IpAddr>,
+    // Ranges already routed to existing peers
+    reserved: Vec<(u128, u128)>,
+    store: Option<Box<dyn WireguardIpPoolStore>>,
+}
+
+impl std::fmt::Debug for WireguardIpPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WireguardIpPool")
+            .field("subnet", &self.subnet)
+            .field("allocated", &self.allocated)
+            .field("reserved", &self.reserved)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WireguardIpPool {
+    pub fn new(subnet: WireguardIpAddress) -> Self {
+        Self {
+            subnet,
+            allocated: BTreeSet::new(),
+            reserved: Vec::new(),
+            store: None,
+        }
+    }
+
+    /// Load allocated addresses from `store` and save to it on every change.
+    pub fn with_store(
+        subnet: WireguardIpAddress,
+        mut store: Box<dyn WireguardIpPoolStore>,
+    ) -> Result<Self, WireguardError> {
+        let mut ret = Self::new(subnet);
+        ret.allocated.extend(store.load()?);
+        ret.store = Some(store);
+        Ok(ret)
+    }
+
+    /// The subnet addresses are allocated from
+    pub fn subnet(&self) -> &WireguardIpAddress {
+        &self.subnet
+    }
+
+    /// Treat the allowed IPs of all peers in `config` as allocated.
+    pub fn reserve_config(&mut self, config: &WireguardParsed) {
+        for peer in config.peers.as_deref().unwrap_or_default() {
+            for ip in peer.allowed_ips.as_deref().unwrap_or_default() {
+                if ip.ip_addr.is_ipv4() == self.subnet.ip_addr.is_ipv4() {
+                    self.reserved.push(ip.range());
+                }
+            }
+        }
+    }
+
+    /// Mark specified address as allocated, e.g. the address of server.
+    /// Return `false` if already allocated, [ErrorKind::InvalidConfig] if
+    /// not in the subnet of pool.
+    pub fn reserve(&mut self, ip: IpAddr) -> Result<bool, WireguardError> {
+        if !self.subnet.contains(ip) {
+            return Err(WireguardError::new(
+                ErrorKind::InvalidConfig,
+                format!("Cannot reserve {ip} which is not in {}", self.subnet),
+                None,
+            ));
+        }
+        if self.allocated.insert(ip) {
+            self.save()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Allocate the lowest unused address as host route (`/32` or `/128`).
+    pub fn allocate(&mut self) -> Result<WireguardIpAddress, WireguardError> {
+        let (first, last) = self.subnet.range();
+        let is_ipv4 = self.subnet.ip_addr.is_ipv4();
+        let last = if is_ipv4 && last > first + 1 {
+            last - 1
+        } else {
+            last
+        };
+        let mut cur = first.saturating_add(1).min(last);
+        loop {
+            if let Some(&(_, end)) =
+                self.reserved.iter().find(|(s, e)| *s <= cur && cur <= *e)
+            {
+                if end >= last {
+                    break;
+                }
+                cur = end + 1;
+                continue;
+            }
+            let ip = u128_to_ip(cur, is_ipv4);
+            if !self.allocated.contains(&ip) {
+                self.allocated.insert(ip);
+                self.save()?;
+                return Ok(WireguardIpAddress {
+                    ip_addr: ip,
+                    prefix_length: if is_ipv4 { 32 } else { 128 },
+                    flags: None,
+                });
+            }
+            if cur >= last {
+                break;
+            }
+            cur += 1;
+        }
+        Err(WireguardError::new(
+            ErrorKind::IpPoolExhausted,
+            format!("No unused address left in {}", self.subnet),
+            None,
+        ))
+    }
+
+    /// Return address to pool, return `false` if it was not allocated.
+    pub fn release(&mut self, ip: IpAddr) -> Result<bool, WireguardError> {
+        if self.allocated.remove(&ip) {
+            self.save()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn is_allocated(&self, ip: IpAddr) -> bool {
+        let (value, _) = ip_to_u128(ip);
+        self.allocated.contains(&ip)
+            || (ip.is_ipv4() == self.subnet.ip_addr.is_ipv4()
+                && self
+                    .reserved
+                    .iter()
+                    .any(|(s, e)| *s <= value && value <= *e))
+    }
+
+    fn save(&mut self) -> Result<(), WireguardError> {
+        if let Some(store) = self.store.as_mut() {
+            let allocated: Vec<IpAddr> =
+                self.allocated.iter().copied().collect();
+            store.save(&allocated)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::WireguardPeerParsed;
+
+    fn pool(subnet: &str) -> WireguardIpPool {
+        WireguardIpPool::new(subnet.parse().unwrap())
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn allocate_all(pool: &mut WireguardIpPool) -> Vec<String> {
+        let mut ret = Vec::new();
+        loop {
+            match pool.allocate() {
+                Ok(ip) => ret.push(ip.to_string()),
+                Err(e) => {
+                    assert_eq!(e.kind, ErrorKind::IpPoolExhausted);
+                    return ret;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn allocate_skips_network_and_broadcast() {
+        let mut pool = pool("10.0.0.0/30");
+        assert_eq!(allocate_all(&mut pool), ["10.0.0.1/32", "10.0.0.2/32"]);
+        assert!(!pool.is_allocated(ip("10.0.0.0")));
+        assert!(!pool.is_allocated(ip("10.0.0.3")));
+    }
+
+    #[test]
+    fn allocate_exhausted_until_released() {
+        let mut pool = pool("10.0.0.0/24");
+        assert_eq!(allocate_all(&mut pool).len(), 254);
+        assert!(pool.release(ip("10.0.0.100")).unwrap());
+        assert!(!pool.release(ip("10.0.0.100")).unwrap());
+        assert_eq!(pool.allocate().unwrap().to_string(), "10.0.0.100/32");
+        assert!(pool.allocate().is_err());
+    }
+
+    #[test]
+    fn allocate_ipv6() {
+        // No broadcast address in IPv6
+        let mut pool = pool("fd00::/126");
+        assert_eq!(
+            allocate_all(&mut pool),
+            ["fd00::1/128", "fd00::2/128", "fd00::3/128"]
+        );
+        assert!(pool.is_allocated(ip("fd00::3")));
+        assert!(!pool.is_allocated(ip("fd00::4")));
+    }
+
+    #[test]
+    fn allocate_ipv6_large_subnet() {
+        let mut pool = pool("fd00::/64");
+        pool.reserve(ip("fd00::1")).unwrap();
+        assert_eq!(pool.allocate().unwrap().to_string(), "fd00::2/128");
+    }
+
+    #[test]
+    fn reserve_config_skips_existing_peers() {
+        let mut pool = pool("10.0.0.0/28");
+        let config = WireguardParsed::default().with_peers([
+            WireguardPeerParsed {
+                allowed_ips: Some(vec![
+                    "10.0.0.0/29".parse().unwrap(),
+                    // Different family, ignored
+                    "::/0".parse().unwrap(),
+                ]),
+                ..Default::default()
+            },
+            WireguardPeerParsed {
+                allowed_ips: Some(vec!["10.0.0.9/32".parse().unwrap()]),
+                ..Default::default()
+            },
+        ]);
+        pool.reserve_config(&config);
+        assert!(pool.is_allocated(ip("10.0.0.5")));
+        assert!(pool.is_allocated(ip("10.0.0.9")));
+        assert!(!pool.is_allocated(ip("::1")));
+        assert_eq!(
+            allocate_all(&mut pool),
+            [
+                "10.0.0.8/32",
+                "10.0.0.10/32",
+                "10.0.0.11/32",
+                "10.0.0.12/32",
+                "10.0.0.13/32",
+                "10.0.0.14/32",
+            ]
+        );
+    }
+
+    #[test]
+    fn reserve_config_covering_whole_subnet() {
+        let mut pool = pool("10.0.0.0/24");
+        pool.reserve_config(&WireguardParsed::default().with_peers([
+            WireguardPeerParsed {
+                allowed_ips: Some(vec!["0.0.0.0/0".parse().unwrap()]),
+                ..Default::default()
+            },
+        ]));
+        assert!(pool.allocate().is_err());
+    }
+
+    #[test]
+    fn reserve() {
+        let mut pool = pool("10.0.0.0/24");
+        assert!(pool.reserve(ip("10.0.0.1")).unwrap());
+        assert!(!pool.reserve(ip("10.0.0.1")).unwrap());
+        assert!(pool.is_allocated(ip("10.0.0.1")));
+        assert_eq!(pool.allocate().unwrap().to_string(), "10.0.0.2/32");
+    }
+
+    #[test]
+    fn reserve_outside_subnet() {
+        let mut pool = pool("10.0.0.0/24");
+        for outside in ["10.0.1.1", "192.0.2.1", "fd00::1"] {
+            let e = pool.reserve(ip(outside)).unwrap_err();
+            assert_eq!(e.kind, ErrorKind::InvalidConfig, "{outside}");
+            assert!(!pool.is_allocated(ip(outside)), "{outside}");
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct MemoryStore(Arc<Mutex<Vec<IpAddr>>>);
+
+    impl WireguardIpPoolStore for MemoryStore {
+        fn load(&mut self) -> Result<Vec<IpAddr>, WireguardError> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+
+        fn save(&mut self, allocated: &[IpAddr]) -> Result<(), WireguardError> {
+            *self.0.lock().unwrap() = allocated.to_vec();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn store_persists_allocation() {
+        let store = MemoryStore::default();
+        let subnet: WireguardIpAddress = "10.0.0.0/24".parse().unwrap();
+        let mut pool = WireguardIpPool::with_store(
+            subnet.clone(),
+            Box::new(store.clone()),
+        )
+        .unwrap();
+        pool.allocate().unwrap();
+        pool.reserve(ip("10.0.0.5")).unwrap();
+        assert_eq!(*store.0.lock().unwrap(), [ip("10.0.0.1"), ip("10.0.0.5")]);
+
+        let mut pool =
+            WireguardIpPool::with_store(subnet, Box::new(store.clone()))
+                .unwrap();
+        assert_eq!(pool.allocate().unwrap().to_string(), "10.0.0.2/32");
+    }
+}
@@ -0,0 +1,150 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    io::Write,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{ErrorKind, WireguardChange, WireguardError};
+
+/// Changes applied to an interface by one
+/// [crate::WireguardHandle::set()].
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct WireguardJournalEntry {
+    pub time: SystemTime,
+    pub iface_name: String,
+    pub changes: Vec<WireguardChange>,
+}
+
+/// Sink of [WireguardJournalEntry], see
+/// [crate::WireguardHandle::with_journal()]. Closures taking
+/// `&WireguardJournalEntry` are journals as well.
+pub trait WireguardJournal: Send {
+    fn record(
+        &mut self,
+        entry: &WireguardJournalEntry,
+    ) -> Result<(), WireguardError>;
+}
+
+impl<F> WireguardJournal for F
+where
+    F: FnMut(&WireguardJournalEntry) + Send,
+{
+    fn record(
+        &mut self,
+        entry: &WireguardJournalEntry,
+    ) -> Result<(), WireguardError> {
+        self(entry);
+        Ok(())
+    }
+}
+
+/// Append one line per change to a file:
+/// `<UNIX seconds> <interface> <change>`.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct WireguardFileJournal {
+    pub path: PathBuf,
+}
+
+impl WireguardFileJournal {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl WireguardJournal for WireguardFileJournal {
+    fn record(
+        &mut self,
+        entry: &WireguardJournalEntry,
+    ) -> Result<(), WireguardError> {
+        let time = entry
+            .time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut content = String::new();
+        for change in &entry.changes {
+            content
+                .push_str(&format!("{time} {} {change}\n", entry.iface_name));
+        }
+        std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)
+            .and_then(|mut fd| fd.write_all(content.as_bytes()))
+            .map_err(|e| {
+                WireguardError::new(
+                    ErrorKind::IoError,
+                    format!(
+                        "Failed to append journal {}: {e}",
+                        self.path.display()
+                    ),
+                    None,
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const KEY1: &str = "uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM=";
+
+    fn entry() -> WireguardJournalEntry {
+        WireguardJournalEntry {
+            time: UNIX_EPOCH + Duration::from_secs(1700000000),
+            iface_name: "wg0".to_string(),
+            changes: vec![
+                WireguardChange::PeerRemoved {
+                    public_key: KEY1.to_string(),
+                },
+                WireguardChange::Property {
+                    public_key: None,
+                    name: "private_key",
+                    old: Some("(hidden)".to_string()),
+                    new: Some("(hidden)".to_string()),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn file_journal_appends_lines() {
+        let dir = std::env::temp_dir()
+            .join(format!("nl-wireguard-journal-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("journal.log");
+        std::fs::remove_file(&path).ok();
+
+        let mut journal = WireguardFileJournal::new(&path);
+        journal.record(&entry()).unwrap();
+        journal.record(&entry()).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        let line1 = "1700000000 wg0 - peer uFmW/syc…";
+        let line2 = "1700000000 wg0 private_key (hidden) → (hidden)";
+        assert_eq!(
+            content.lines().collect::<Vec<_>>(),
+            [line1, line2, line1, line2]
+        );
+
+        let e = WireguardFileJournal::new(dir.join("missing/journal.log"))
+            .record(&entry())
+            .unwrap_err();
+        assert_eq!(e.kind, ErrorKind::IoError);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn closure_journal() {
+        let mut recorded = Vec::new();
+        let mut journal = |e: &WireguardJournalEntry| recorded.push(e.clone());
+        journal.record(&entry()).unwrap();
+        assert_eq!(recorded, [entry()]);
+    }
+}
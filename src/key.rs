@@ -0,0 +1,245 @@
+// SPDX-License-Identifier: MIT
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+
+#[cfg(feature = "x25519")]
+use crate::parsed::decode_key;
+use crate::{parsed::decode_key_tolerant, WireguardError};
+
+const FINGERPRINT_LEN: usize = 8;
+
+/// Short identifier of base64 encoded public key for logging: its first 8
+/// characters, the same prefix shown by most wireguard tools.
+/// Do not use this on private or preshared keys.
+pub fn key_fingerprint(key: &str) -> String {
+    key.trim().chars().take(FINGERPRINT_LEN).collect()
+}
+
+/// Whether base64 encoded `public_key` is derived from base64 encoded
+/// `private_key`, `false` if either is not a valid key. Call it before
+/// applying configuration carrying both keys to catch copy-paste errors,
+/// it is never done implicitly.
+/// The key derivation is done by `x25519-dalek` crate, requires the
+/// `x25519` feature.
+///
+/// ```
+/// // Key pair of Alice in RFC 7748 section 6.1
+/// assert!(nl_wireguard::verify_keypair(
+///     "dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=",
+///     "hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=",
+/// ));
+/// ```
+#[cfg(feature = "x25519")]
+pub fn verify_keypair(private_key: &str, public_key: &str) -> bool {
+    match (
+        decode_key("private_key", private_key),
+        decode_key("public_key", public_key),
+    ) {
+        (Ok(private_key), Ok(public_key)) => {
+            let secret = x25519_dalek::StaticSecret::from(private_key);
+            x25519_dalek::PublicKey::from(&secret).as_bytes() == &public_key
+        }
+        _ => false,
+    }
+}
+
+/// Convert base64 encoded key copied from other tools into the standard
+/// padded form this crate expects, tolerating surrounding whitespace,
+/// missing padding and the URL-safe alphabet (`-` and `_`).
+///
+/// ```
+/// assert_eq!(
+///     nl_wireguard::normalize_key(
+///         " hSDwCYkwp1R0i33ctD73Wg2_Og0mOBr066SpjqqbTmo\n"
+///     )
+///     .unwrap(),
+///     "hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=",
+/// );
+/// ```
+pub fn normalize_key(key: &str) -> Result<String, WireguardError> {
+    Ok(BASE64_STANDARD.encode(decode_key_tolerant("key", key)?))
+}
+
+// Canonical form of base64 encoded key for comparing keys written
+// differently (e.g. unpadded), the key itself if invalid.
+pub(crate) fn canonical_key(key: &str) -> String {
+    normalize_key(key).unwrap_or_else(|_| key.to_string())
+}
+
+// Whether two optional keys are the same key.
+pub(crate) fn same_key(a: Option<&str>, b: Option<&str>) -> bool {
+    a.map(canonical_key) == b.map(canonical_key)
+}
+
+// FNV-1a 64 bits offset basis and prime
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// Short stable identifier of secret key which does not reveal the key, for
+// correlating redacted debug output.
+pub(crate) fn secret_fingerprint(key: &str) -> String {
+    let hash = key.trim().bytes().fold(FNV_OFFSET, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(FNV_PRIME)
+    });
+    format!("{:08x}", hash >> 32)
+}
+
+// Rendering of public key in Debug output, fingerprint in alternate mode.
+pub(crate) fn debug_public_key(
+    key: &Option<String>,
+    alternate: bool,
+) -> Option<String> {
+    key.as_deref().map(|k| {
+        if alternate {
+            key_fingerprint(k)
+        } else {
+            k.to_string()
+        }
+    })
+}
+
+// Rendering of secret in Debug output, with fingerprint in alternate mode.
+// The `debug_show_secrets` feature shows the secret itself.
+pub(crate) fn hidden_secret(
+    key: &Option<String>,
+    alternate: bool,
+) -> Option<String> {
+    key.as_deref().map(|k| {
+        if cfg!(feature = "debug_show_secrets") {
+            k.to_string()
+        } else if alternate {
+            format!("(hidden, fp={})", secret_fingerprint(k))
+        } else {
+            "(hidden)".to_string()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    // Key pairs of Alice and Bob in RFC 7748 section 6.1
+    const ALICE_PRIVATE: &str = "dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=";
+    const ALICE_PUBLIC: &str = "hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=";
+    const BOB_PRIVATE: &str = "XasIfmJKikt54X+Lg4AO5m87sSkmGLb9HC+LJ/+I4Os=";
+    const BOB_PUBLIC: &str = "3p7bfXt9wbTTW2HC7OQ1Nz+DQ8hbeGdNrfx+FG+IK08=";
+
+    #[test]
+    fn normalize_key_tolerated_forms() {
+        let url_safe = BOB_PRIVATE.replace('+', "-").replace('/', "_");
+        let cases = [
+            BOB_PRIVATE.to_string(),
+            format!(" {BOB_PRIVATE}\n"),
+            BOB_PRIVATE.trim_end_matches('=').to_string(),
+            url_safe.clone(),
+            url_safe.trim_end_matches('=').to_string(),
+        ];
+        for key in cases {
+            assert_eq!(normalize_key(&key).unwrap(), BOB_PRIVATE, "{key:?}");
+        }
+    }
+
+    #[test]
+    fn normalize_key_invalid() {
+        let cases = [
+            String::new(),
+            "not a key".to_string(),
+            // 31 and 33 bytes
+            BASE64_STANDARD.encode([1u8; 31]),
+            BASE64_STANDARD.encode([1u8; 33]),
+            // Mixing both alphabets
+            BOB_PRIVATE.replace('/', "_"),
+        ];
+        for key in cases {
+            let e = normalize_key(&key).unwrap_err();
+            assert_eq!(e.kind, ErrorKind::InvalidKey, "{key:?}");
+            // Error message never leaks the key
+            if !key.is_empty() {
+                assert!(!e.msg.contains(key.as_str()), "{}", e.msg);
+            }
+        }
+        let e = normalize_key(&BASE64_STANDARD.encode([1u8; 16])).unwrap_err();
+        assert!(e.msg.contains("current length 16"), "{}", e.msg);
+    }
+
+    #[test]
+    fn canonical_and_same_key() {
+        let unpadded = ALICE_PUBLIC.trim_end_matches('=');
+        assert_eq!(canonical_key(unpadded), ALICE_PUBLIC);
+        assert_eq!(canonical_key("not a key"), "not a key");
+        assert!(same_key(Some(unpadded), Some(ALICE_PUBLIC)));
+        assert!(!same_key(Some(ALICE_PUBLIC), Some(BOB_PUBLIC)));
+        assert!(!same_key(Some(ALICE_PUBLIC), None));
+        assert!(same_key(None, None));
+    }
+
+    #[test]
+    fn fingerprints() {
+        assert_eq!(key_fingerprint(ALICE_PUBLIC), "hSDwCYkw");
+        assert_eq!(key_fingerprint(&format!("  {ALICE_PUBLIC}")), "hSDwCYkw");
+        assert_eq!(key_fingerprint("abc"), "abc");
+
+        let fp = secret_fingerprint(ALICE_PRIVATE);
+        assert_eq!(fp.len(), 8);
+        assert!(!ALICE_PRIVATE.contains(fp.as_str()));
+        assert_eq!(secret_fingerprint(&format!("{ALICE_PRIVATE}\n")), fp);
+        assert_ne!(secret_fingerprint(BOB_PRIVATE), fp);
+    }
+
+    #[test]
+    fn debug_rendering() {
+        let public = Some(ALICE_PUBLIC.to_string());
+        assert_eq!(debug_public_key(&public, false), public);
+        assert_eq!(
+            debug_public_key(&public, true).as_deref(),
+            Some("hSDwCYkw")
+        );
+        assert_eq!(debug_public_key(&None, true), None);
+
+        let secret = Some(ALICE_PRIVATE.to_string());
+        assert_eq!(hidden_secret(&None, false), None);
+        if cfg!(feature = "debug_show_secrets") {
+            assert_eq!(hidden_secret(&secret, false), secret);
+            assert_eq!(hidden_secret(&secret, true), secret);
+        } else {
+            assert_eq!(
+                hidden_secret(&secret, false).as_deref(),
+                Some("(hidden)")
+            );
+            assert_eq!(
+                hidden_secret(&secret, true),
+                Some(format!(
+                    "(hidden, fp={})",
+                    secret_fingerprint(ALICE_PRIVATE)
+                ))
+            );
+        }
+    }
+
+    #[cfg(feature = "x25519")]
+    #[test]
+    fn verify_keypair_rfc7748() {
+        assert!(verify_keypair(ALICE_PRIVATE, ALICE_PUBLIC));
+        assert!(verify_keypair(BOB_PRIVATE, BOB_PUBLIC));
+        assert!(!verify_keypair(ALICE_PRIVATE, BOB_PUBLIC));
+        assert!(!verify_keypair(BOB_PRIVATE, ALICE_PUBLIC));
+        // Public key is not a private key of itself
+        assert!(!verify_keypair(ALICE_PUBLIC, ALICE_PUBLIC));
+    }
+
+    #[cfg(feature = "x25519")]
+    #[test]
+    fn verify_keypair_invalid_keys() {
+        assert!(!verify_keypair("", ALICE_PUBLIC));
+        assert!(!verify_keypair(ALICE_PRIVATE, ""));
+        assert!(!verify_keypair(ALICE_PRIVATE, &ALICE_PUBLIC[..40]));
+        assert!(!verify_keypair("not a key", "not a key"));
+        // Whitespace and missing padding are tolerated like everywhere
+        assert!(verify_keypair(
+            &format!(" {ALICE_PRIVATE}\n"),
+            ALICE_PUBLIC.trim_end_matches('=')
+        ));
+    }
+}
@@ -20,6 +20,9 @@
 //! To set wireguard configuration.
 //! You need to use `rtnetlink` crate to create a interface with `wireguard`
 //! interface type before.
+//! Alternatively, the opt-in `exec` feature provides wg-quick style helpers
+//! running `ip`, `nft` and other commands, e.g. `WireguardManager` creating
+//! interfaces and `WireguardQuickConfig::add_routes()`.
 //!
 //! ```no_run
 //! use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
@@ -68,21 +71,139 @@
 //! }
 //! ```
 
+mod allowed_ip_table;
+#[cfg(feature = "connection")]
+mod api;
+#[cfg(feature = "connection")]
+mod cache;
+mod change;
+#[cfg(feature = "exec")]
+mod cmd;
+#[cfg(feature = "notify")]
+mod conf_watcher;
+#[cfg(feature = "connection")]
 mod connection;
+mod diff;
+mod dns;
+mod endpoint;
 mod error;
+mod event;
+#[cfg(feature = "connection")]
+mod family;
+#[cfg(feature = "connection")]
 mod handle;
+mod handshake_monitor;
+mod hook;
+#[cfg(feature = "connection")]
+mod implementation;
+mod ip_pool;
+#[cfg(feature = "connection")]
+mod journal;
+mod key;
+#[cfg(all(feature = "connection", feature = "exec"))]
+mod manager;
+mod merge;
+mod mtu;
+mod nftables;
 mod parsed;
+#[cfg(feature = "connection")]
+mod peer_expiry;
 mod peer_parsed;
+mod policy_route;
+mod pretty;
+mod provision;
+#[cfg(feature = "connection")]
+mod reconciler;
+#[cfg(feature = "connection")]
+mod rollout;
+mod route;
+#[cfg(feature = "connection")]
+mod runtime;
+mod shell_script;
+#[cfg(feature = "connection")]
+mod snapshot;
+mod stats;
+mod stats_history;
+mod topology;
+mod uapi;
+#[cfg(any(feature = "tokio_socket", feature = "smol_socket"))]
+mod watcher;
+mod wg_quick;
 
+// Types needed by WireguardHandle::request() and
+// WireguardParsed::build(), so the matching versions are used.
+pub use netlink_packet_core::{
+    NetlinkHeader, NetlinkMessage, NetlinkPayload, NLM_F_ACK, NLM_F_CREATE,
+    NLM_F_DUMP, NLM_F_ECHO, NLM_F_EXCL, NLM_F_MULTIPART, NLM_F_REPLACE,
+    NLM_F_REQUEST,
+};
+pub use netlink_packet_generic::GenlMessage;
+pub use netlink_packet_wireguard::{
+    WireguardAttribute, WireguardCmd, WireguardMessage,
+};
+#[cfg(feature = "connection")]
+pub use netlink_sys::AsyncSocket;
+
+#[cfg(feature = "notify")]
+pub use self::conf_watcher::WireguardConfWatcher;
 #[cfg(feature = "tokio_socket")]
-pub use self::connection::new_connection;
+pub use self::connection::{
+    new_connection, spawn_connection, WireguardConnectionGuard,
+};
+#[cfg(feature = "x25519")]
+pub use self::key::verify_keypair;
+#[cfg(all(feature = "connection", feature = "exec"))]
+pub use self::manager::WireguardManager;
+#[cfg(any(feature = "tokio_socket", feature = "smol_socket"))]
+pub use self::watcher::WireguardWatcher;
 pub use self::{
-    connection::new_connection_with_socket,
+    allowed_ip_table::WireguardAllowedIpTable,
+    change::WireguardChange,
+    diff::render_diff,
+    dns::WireguardDnsConfigurator,
+    endpoint::WireguardEndpoint,
     error::{ErrorKind, WireguardError},
-    handle::WireguardHandle,
+    event::WireguardEvent,
+    handshake_monitor::{WireguardHandshakeAlert, WireguardHandshakeMonitor},
+    hook::{WireguardHookExecutor, WireguardHookKind},
+    ip_pool::{WireguardIpPool, WireguardIpPoolStore},
+    key::{key_fingerprint, normalize_key},
+    mtu::suggest_mtu,
+    nftables::WireguardNftables,
     parsed::{WireguardParsed, WireguardParsedDeviceFlags},
     peer_parsed::{
         WireguardIpAddress, WireguardParsedAllowedIpFlags,
         WireguardParsedPeerFlags, WireguardPeerParsed,
     },
+    policy_route::WireguardPolicyRouting,
+    pretty::{pretty_netlink_message, pretty_wireguard_message},
+    provision::provision_client,
+    route::WireguardQuickTable,
+    stats::{
+        WireguardDeviceStats, WireguardPeerStats, WireguardPeerStatsDelta,
+    },
+    stats_history::{WireguardPeerRate, WireguardStatsHistory},
+    topology::{full_mesh, hub_and_spoke, WireguardTopologyNode},
+    wg_quick::WireguardQuickConfig,
+};
+#[cfg(feature = "connection")]
+pub use self::{
+    api::{DynWireguardApi, WireguardBoxFuture},
+    cache::WireguardCache,
+    connection::{
+        connection_with_shutdown, new_connection_with_socket,
+        WireguardConnection, WireguardShutdown,
+    },
+    family::{WireguardFamilyInfo, WireguardFamilyOperation},
+    handle::WireguardHandle,
+    implementation::{WireguardImplementation, WIREGUARD_UAPI_SOCKET_DIR},
+    journal::{WireguardFileJournal, WireguardJournal, WireguardJournalEntry},
+    peer_expiry::{WireguardPeerExpiry, WIREGUARD_EXPIRY_LABEL},
+    reconciler::WireguardReconciler,
+    rollout::{WireguardRollout, WireguardRolloutResult},
+};
+#[cfg(feature = "exec")]
+pub use self::{
+    dns::{WireguardResolvconf, WireguardResolvectl},
+    hook::WireguardShellHookExecutor,
 };
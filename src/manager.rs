@@ -0,0 +1,207 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::BTreeMap,
+    net::{Ipv6Addr, SocketAddr},
+};
+
+use crate::{
+    cmd::run_cmd,
+    parsed::{validate_iface_name, IFACE_NAME_MAX_LEN},
+    suggest_mtu, ErrorKind, WireguardDeviceStats, WireguardError,
+    WireguardHandle, WireguardParsed,
+};
+
+const DEFAULT_IFACE_PREFIX: &str = "wgt";
+const DEFAULT_BASE_MTU: u16 = 1500;
+
+/// Manage wireguard interfaces of many tenants, each tenant owns one
+/// interface named by this manager as `<prefix><number>`.
+///
+/// Interfaces are created and deleted via `ip link` command. The mapping
+/// between tenant and interface is only kept in memory, use
+/// [WireguardManager::adopt()] to restore it after restart.
+#[derive(Clone, Debug)]
+pub struct WireguardManager {
+    handle: WireguardHandle,
+    prefix: String,
+    base_mtu: u16,
+    tenants: BTreeMap<String, String>,
+}
+
+impl WireguardManager {
+    pub fn new(handle: WireguardHandle) -> Self {
+        Self {
+            handle,
+            prefix: DEFAULT_IFACE_PREFIX.to_string(),
+            base_mtu: DEFAULT_BASE_MTU,
+            tenants: BTreeMap::new(),
+        }
+    }
+
+    /// Prefix of interface names created by this manager, default `wgt`.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// MTU of the link carrying encrypted packets, default 1500.
+    /// Interfaces are created with the MTU from [suggest_mtu()] of IPv6
+    /// endpoint over it (e.g. 1420 for 1500), fitting peers of either
+    /// address family.
+    pub fn with_base_mtu(mut self, base_mtu: u16) -> Self {
+        self.base_mtu = base_mtu;
+        self
+    }
+
+    /// Interface name of specified tenant.
+    pub fn iface_name(&self, tenant: &str) -> Option<&str> {
+        self.tenants.get(tenant).map(String::as_str)
+    }
+
+    /// Iterate over `(tenant, iface_name)` sorted by tenant.
+    pub fn tenants(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.tenants.iter().map(|(t, i)| (t.as_str(), i.as_str()))
+    }
+
+    /// Track existing interface as owned by tenant without creating it.
+    pub fn adopt(
+        &mut self,
+        tenant: &str,
+        iface_name: &str,
+    ) -> Result<(), WireguardError> {
+        self.check_new_tenant(tenant)?;
+        validate_iface_name(iface_name)?;
+        if let Some((owner, _)) =
+            self.tenants.iter().find(|(_, i)| i.as_str() == iface_name)
+        {
+            return Err(WireguardError::new(
+                ErrorKind::InvalidConfig,
+                format!("Interface {iface_name} is already owned by {owner}"),
+                None,
+            ));
+        }
+        self.tenants
+            .insert(tenant.to_string(), iface_name.to_string());
+        Ok(())
+    }
+
+    /// Create wireguard interface for new tenant, return its name.
+    /// See [WireguardManager::with_base_mtu()] for its MTU.
+    pub fn create(&mut self, tenant: &str) -> Result<String, WireguardError> {
+        self.check_new_tenant(tenant)?;
+        let iface_name = self.next_iface_name()?;
+        validate_iface_name(&iface_name)?;
+        let mtu = iface_mtu(self.base_mtu).to_string();
+        run_cmd(
+            "ip",
+            &[
+                "link",
+                "add",
+                "dev",
+                &iface_name,
+                "mtu",
+                &mtu,
+                "type",
+                "wireguard",
+            ],
+            None,
+        )?;
+        log::info!(
+            "Created interface {iface_name} with MTU {mtu} for tenant {tenant}"
+        );
+        self.tenants.insert(tenant.to_string(), iface_name.clone());
+        Ok(iface_name)
+    }
+
+    /// Apply configuration to interface of tenant, the `iface_name` and
+    /// `iface_index` of `config` are overridden.
+    pub async fn configure(
+        &mut self,
+        tenant: &str,
+        config: WireguardParsed,
+    ) -> Result<(), WireguardError> {
+        let iface_name = self.get_iface_name(tenant)?.to_string();
+        self.handle
+            .set(WireguardParsed {
+                iface_name: Some(iface_name),
+                iface_index: None,
+                ..config
+            })
+            .await
+    }
+
+    /// Current traffic statistics of interface of tenant.
+    pub async fn status(
+        &mut self,
+        tenant: &str,
+    ) -> Result<WireguardDeviceStats, WireguardError> {
+        let iface_name = self.get_iface_name(tenant)?.to_string();
+        self.handle.get_stats(&iface_name).await
+    }
+
+    /// Delete interface of tenant and forget the tenant.
+    pub fn teardown(&mut self, tenant: &str) -> Result<(), WireguardError> {
+        let iface_name = self.get_iface_name(tenant)?.to_string();
+        run_cmd("ip", &["link", "del", "dev", &iface_name], None)?;
+        log::info!("Deleted interface {iface_name} of tenant {tenant}");
+        self.tenants.remove(tenant);
+        Ok(())
+    }
+
+    fn check_new_tenant(&self, tenant: &str) -> Result<(), WireguardError> {
+        if self.tenants.contains_key(tenant) {
+            Err(WireguardError::new(
+                ErrorKind::InvalidConfig,
+                format!("Tenant {tenant} already exists"),
+                None,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn get_iface_name(&self, tenant: &str) -> Result<&str, WireguardError> {
+        self.iface_name(tenant).ok_or_else(|| {
+            WireguardError::new(
+                ErrorKind::InvalidConfig,
+                format!("Tenant {tenant} not found"),
+                None,
+            )
+        })
+    }
+
+    fn next_iface_name(&self) -> Result<String, WireguardError> {
+        (0u32..)
+            .map(|i| format!("{}{i}", self.prefix))
+            .take_while(|name| name.len() <= IFACE_NAME_MAX_LEN)
+            .find(|name| !self.tenants.values().any(|i| i == name))
+            .ok_or_else(|| {
+                WireguardError::new(
+                    ErrorKind::InvalidConfig,
+                    format!(
+                        "No interface name available for prefix {}",
+                        self.prefix
+                    ),
+                    None,
+                )
+            })
+    }
+}
+
+// Peers might roam between IPv4 and IPv6, hence use the smaller one
+fn iface_mtu(base_mtu: u16) -> u16 {
+    suggest_mtu(&SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0), base_mtu)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mtu() {
+        assert_eq!(iface_mtu(DEFAULT_BASE_MTU), 1420);
+        assert_eq!(iface_mtu(9000), 8920);
+        assert_eq!(iface_mtu(60), 0);
+    }
+}
@@ -0,0 +1,227 @@
+// SPDX-License-Identifier: MIT
+
+use crate::{WireguardIpAddress, WireguardParsed, WireguardPeerParsed};
+
+impl WireguardParsed {
+    /// Layer `overlay` on top of this configuration:
+    ///  * Properties set (`Some`) in `overlay` win.
+    ///  * Peers of the same public key are merged the same way, so
+    ///    `allowed_ips` of overlay peer replaces the existing one.
+    ///  * Other peers of `overlay` are appended.
+    pub fn merge(&mut self, overlay: WireguardParsed) {
+        self.merge_full(overlay, false)
+    }
+
+    /// Like [WireguardParsed::merge()], but the `allowed_ips` of peers of
+    /// the same public key are unioned instead of replaced.
+    pub fn merge_union_allowed_ips(&mut self, overlay: WireguardParsed) {
+        self.merge_full(overlay, true)
+    }
+
+    fn merge_full(&mut self, overlay: WireguardParsed, union_ips: bool) {
+        let WireguardParsed {
+            iface_name,
+            iface_index,
+            public_key,
+            private_key,
+            listen_port,
+            fwmark,
+            peers,
+            flags,
+        } = overlay;
+        merge_opt(&mut self.iface_name, iface_name);
+        merge_opt(&mut self.iface_index, iface_index);
+        merge_opt(&mut self.public_key, public_key);
+        merge_opt(&mut self.private_key, private_key);
+        merge_opt(&mut self.listen_port, listen_port);
+        merge_opt(&mut self.fwmark, fwmark);
+        merge_opt(&mut self.flags, flags);
+        if let Some(overlay_peers) = peers {
+            let cur_peers = self.peers.get_or_insert_with(Vec::new);
+            for overlay_peer in overlay_peers {
+                match cur_peers.iter_mut().find(|p| {
+                    p.public_key.is_some()
+                        && p.public_key == overlay_peer.public_key
+                }) {
+                    Some(cur_peer) => {
+                        cur_peer.merge_full(overlay_peer, union_ips)
+                    }
+                    None => cur_peers.push(overlay_peer),
+                }
+            }
+        }
+    }
+}
+
+impl WireguardPeerParsed {
+    fn merge_full(&mut self, overlay: WireguardPeerParsed, union_ips: bool) {
+        let WireguardPeerParsed {
+            endpoint,
+            public_key,
+            preshared_key,
+            persistent_keepalive,
+            last_handshake,
+            rx_bytes,
+            tx_bytes,
+            allowed_ips,
+            protocol_version,
+            flags,
+        } = overlay;
+        merge_opt(&mut self.endpoint, endpoint);
+        merge_opt(&mut self.public_key, public_key);
+        merge_opt(&mut self.preshared_key, preshared_key);
+        merge_opt(&mut self.persistent_keepalive, persistent_keepalive);
+        merge_opt(&mut self.last_handshake, last_handshake);
+        merge_opt(&mut self.rx_bytes, rx_bytes);
+        merge_opt(&mut self.tx_bytes, tx_bytes);
+        merge_opt(&mut self.protocol_version, protocol_version);
+        merge_opt(&mut self.flags, flags);
+        match (self.allowed_ips.as_mut(), allowed_ips) {
+            (Some(cur_ips), Some(overlay_ips)) if union_ips => {
+                for ip in overlay_ips {
+                    if !cur_ips.iter().any(|i| same_prefix(i, &ip)) {
+                        cur_ips.push(ip);
+                    }
+                }
+            }
+            (_, overlay_ips) => merge_opt(&mut self.allowed_ips, overlay_ips),
+        }
+    }
+}
+
+fn merge_opt<T>(cur: &mut Option<T>, overlay: Option<T>) {
+    if overlay.is_some() {
+        *cur = overlay;
+    }
+}
+
+fn same_prefix(a: &WireguardIpAddress, b: &WireguardIpAddress) -> bool {
+    a.ip_addr == b.ip_addr && a.prefix_length == b.prefix_length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(public_key: &str, ips: &[&str]) -> WireguardPeerParsed {
+        WireguardPeerParsed {
+            public_key: Some(public_key.to_string()),
+            allowed_ips: Some(ips.iter().map(|i| i.parse().unwrap()).collect()),
+            ..Default::default()
+        }
+    }
+
+    fn base() -> WireguardParsed {
+        WireguardParsed {
+            listen_port: Some(51820),
+            fwmark: Some(1),
+            ..WireguardParsed::new("wg0")
+        }
+        .with_peers([
+            WireguardPeerParsed {
+                persistent_keepalive: Some(25),
+                ..peer("A", &["10.0.0.1/32"])
+            },
+            peer("B", &["10.0.0.2/32"]),
+        ])
+    }
+
+    #[test]
+    fn merge_properties() {
+        let cases = [
+            ("empty overlay", WireguardParsed::default(), base()),
+            (
+                "port changed",
+                WireguardParsed {
+                    listen_port: Some(51821),
+                    ..Default::default()
+                },
+                WireguardParsed {
+                    listen_port: Some(51821),
+                    ..base()
+                },
+            ),
+            (
+                // Zero is a value like any other for merging
+                "port zero",
+                WireguardParsed {
+                    listen_port: Some(0),
+                    ..Default::default()
+                },
+                WireguardParsed {
+                    listen_port: Some(0),
+                    ..base()
+                },
+            ),
+        ];
+        for (name, overlay, expected) in cases {
+            let mut config = base();
+            config.merge(overlay.clone());
+            assert_eq!(config, expected, "{name}");
+            let mut config = base();
+            config.merge_union_allowed_ips(overlay);
+            assert_eq!(config, expected, "{name} union");
+        }
+    }
+
+    #[test]
+    fn merge_peers() {
+        let mut config = base();
+        config.merge(WireguardParsed::default().with_peers([
+            WireguardPeerParsed {
+                endpoint: Some("192.0.2.1:51820".parse().unwrap()),
+                ..peer("A", &["10.1.0.0/16"])
+            },
+            peer("C", &["10.0.0.3/32"]),
+        ]));
+        let expected = WireguardParsed {
+            peers: Some(vec![
+                WireguardPeerParsed {
+                    endpoint: Some("192.0.2.1:51820".parse().unwrap()),
+                    persistent_keepalive: Some(25),
+                    ..peer("A", &["10.1.0.0/16"])
+                },
+                peer("B", &["10.0.0.2/32"]),
+                peer("C", &["10.0.0.3/32"]),
+            ]),
+            ..base()
+        };
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn merge_union_allowed_ips() {
+        let mut config = base();
+        config.merge_union_allowed_ips(WireguardParsed::default().with_peers(
+            [
+                peer("A", &["10.1.0.0/16", "10.0.0.1/32"]),
+                // Allowed IPs not managed by overlay
+                WireguardPeerParsed {
+                    public_key: Some("B".to_string()),
+                    ..Default::default()
+                },
+            ],
+        ));
+        let peers = config.peers.unwrap();
+        assert_eq!(
+            peers[0],
+            WireguardPeerParsed {
+                persistent_keepalive: Some(25),
+                ..peer("A", &["10.0.0.1/32", "10.1.0.0/16"])
+            }
+        );
+        assert_eq!(peers[1], peer("B", &["10.0.0.2/32"]));
+    }
+
+    #[test]
+    fn merge_peer_without_public_key_appended() {
+        let keyless = WireguardPeerParsed {
+            persistent_keepalive: Some(10),
+            ..Default::default()
+        };
+        let mut config =
+            WireguardParsed::default().with_peers([keyless.clone()]);
+        config.merge(WireguardParsed::default().with_peers([keyless.clone()]));
+        assert_eq!(config.peers, Some(vec![keyless.clone(), keyless]));
+    }
+}
@@ -0,0 +1,64 @@
+// SPDX-License-Identifier: MIT
+
+use std::net::SocketAddr;
+
+const IPV4_HEADER_LEN: u16 = 20;
+const IPV6_HEADER_LEN: u16 = 40;
+const UDP_HEADER_LEN: u16 = 8;
+// Message type, receiver index and counter (16 bytes) plus the
+// authentication tag (16 bytes) of transport data message.
+const WG_DATA_OVERHEAD: u16 = 32;
+
+/// Suggest MTU of wireguard interface sending packets to `endpoint` via link
+/// of `base_mtu`, e.g. 1440 for IPv4 endpoint and 1420 for IPv6 endpoint
+/// over 1500 MTU ethernet. Return 0 if `base_mtu` cannot even hold the
+/// headers.
+///
+/// The IPv4-mapped IPv6 endpoint is treated as IPv4.
+/// When peers may roam between IPv4 and IPv6, use the IPv6 one which is
+/// smaller (what `wg-quick` uses).
+pub fn suggest_mtu(endpoint: &SocketAddr, base_mtu: u16) -> u16 {
+    let ip_header_len = match endpoint {
+        SocketAddr::V4(_) => IPV4_HEADER_LEN,
+        SocketAddr::V6(addr) => {
+            if addr.ip().to_ipv4_mapped().is_some() {
+                IPV4_HEADER_LEN
+            } else {
+                IPV6_HEADER_LEN
+            }
+        }
+    };
+    base_mtu.saturating_sub(ip_header_len + UDP_HEADER_LEN + WG_DATA_OVERHEAD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(addr: &str) -> SocketAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn suggest_mtu_by_family() {
+        let ipv4 = endpoint("192.0.2.1:51820");
+        let ipv6 = endpoint("[2001:db8::1]:51820");
+        let mapped = endpoint("[::ffff:192.0.2.1]:51820");
+        assert_eq!(suggest_mtu(&ipv4, 1500), 1440);
+        assert_eq!(suggest_mtu(&ipv6, 1500), 1420);
+        assert_eq!(suggest_mtu(&mapped, 1500), 1440);
+        assert_eq!(suggest_mtu(&ipv4, 9000), 8940);
+        assert_eq!(suggest_mtu(&ipv6, 9000), 8920);
+    }
+
+    #[test]
+    fn suggest_mtu_below_overhead() {
+        let ipv4 = endpoint("192.0.2.1:51820");
+        let ipv6 = endpoint("[2001:db8::1]:51820");
+        assert_eq!(suggest_mtu(&ipv4, 60), 0);
+        assert_eq!(suggest_mtu(&ipv4, 61), 1);
+        assert_eq!(suggest_mtu(&ipv6, 80), 0);
+        assert_eq!(suggest_mtu(&ipv6, 60), 0);
+        assert_eq!(suggest_mtu(&ipv6, 0), 0);
+    }
+}
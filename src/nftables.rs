@@ -0,0 +1,256 @@
+// SPDX-License-Identifier: MIT
+
+use std::fmt::Write;
+
+#[cfg(feature = "exec")]
+use crate::cmd::run_cmd;
+use crate::{ErrorKind, WireguardError, WireguardIpAddress, WireguardParsed};
+
+/// nftables rules commonly needed for wireguard interface to work on host
+/// with firewall.
+///
+/// [WireguardNftables::to_script()] only masquerades packets from allowed
+/// IPs of peers leaving via specified interface (see
+/// [WireguardNftables::masquerade_via()]), in a dedicated `inet` table.
+///
+/// An `accept` verdict in one table does not override the `drop` of other
+/// tables, hence the rules accepting UDP packets to the listen port and
+/// forwarding packets of the interface cannot open the firewall from a
+/// dedicated table. They are provided by [WireguardNftables::input_rules()]
+/// and [WireguardNftables::forward_rules()] for inserting into the chains
+/// of host firewall instead.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct WireguardNftables {
+    /// nftables table name, default `wireguard_<iface_name>`
+    pub table: String,
+    pub iface_name: String,
+    pub listen_port: Option<u16>,
+    /// Allowed IPs of peers, default routes excluded
+    pub allowed_ips: Vec<WireguardIpAddress>,
+    pub forward: bool,
+    /// Outgoing interface for masquerading
+    pub masquerade_iface: Option<String>,
+}
+
+impl WireguardNftables {
+    /// Rules for listen port and allowed IPs of `config`, forwarding
+    /// enabled and masquerading disabled.
+    pub fn new(config: &WireguardParsed) -> Result<Self, WireguardError> {
+        let iface_name = config.iface_name.as_deref().ok_or_else(|| {
+            WireguardError::new(
+                ErrorKind::InvalidConfig,
+                "Cannot generate nftables rules without iface_name".to_string(),
+                None,
+            )
+        })?;
+        let mut allowed_ips: Vec<WireguardIpAddress> = Vec::new();
+        for (_, ip) in config.iter_allowed_ips() {
+            if !ip.is_default_route()
+                && !allowed_ips.iter().any(|i| {
+                    i.ip_addr == ip.ip_addr
+                        && i.prefix_length == ip.prefix_length
+                })
+            {
+                allowed_ips.push(WireguardIpAddress {
+                    flags: None,
+                    ..ip.clone()
+                });
+            }
+        }
+        Ok(Self {
+            table: format!("wireguard_{iface_name}"),
+            iface_name: iface_name.to_string(),
+            listen_port: config.listen_port,
+            allowed_ips,
+            forward: true,
+            masquerade_iface: None,
+        })
+    }
+
+    pub fn table(mut self, table: &str) -> Self {
+        self.table = table.to_string();
+        self
+    }
+
+    pub fn forward(mut self, enabled: bool) -> Self {
+        self.forward = enabled;
+        self
+    }
+
+    /// Masquerade packets from allowed IPs leaving via `iface_name`.
+    pub fn masquerade_via(mut self, iface_name: &str) -> Self {
+        self.masquerade_iface = Some(iface_name.to_string());
+        self
+    }
+
+    /// Rules accepting UDP packets to the listen port, for inserting into
+    /// the input chain of host firewall, e.g.
+    /// `nft insert rule inet filter input <rule>`.
+    pub fn input_rules(&self) -> Vec<String> {
+        self.listen_port
+            .map(|port| format!("udp dport {port} accept"))
+            .into_iter()
+            .collect()
+    }
+
+    /// Rules accepting packets forwarded from the interface and the replies
+    /// to them, for inserting into the forward chain of host firewall.
+    /// Empty if forwarding is disabled.
+    pub fn forward_rules(&self) -> Vec<String> {
+        if !self.forward {
+            return Vec::new();
+        }
+        let iface_name = &self.iface_name;
+        vec![
+            format!("iifname \"{iface_name}\" accept"),
+            format!(
+                "oifname \"{iface_name}\" ct state established,related accept"
+            ),
+        ]
+    }
+
+    /// Render the masquerading rules as script for `nft -f`. Applying it
+    /// again replaces the previous rules of the same table.
+    pub fn to_script(&self) -> String {
+        let mut ret = String::new();
+        let table = &self.table;
+        // Declaring the table first makes the delete succeed on first run
+        writeln!(ret, "table inet {table}").ok();
+        writeln!(ret, "delete table inet {table}").ok();
+        writeln!(ret, "table inet {table} {{").ok();
+        if let Some(out_iface) = self.masquerade_iface.as_deref() {
+            if !self.allowed_ips.is_empty() {
+                writeln!(ret, "    chain postrouting {{").ok();
+                writeln!(
+                    ret,
+                    "        type nat hook postrouting priority srcnat; \
+                     policy accept;"
+                )
+                .ok();
+                for ip in &self.allowed_ips {
+                    writeln!(
+                        ret,
+                        "        oifname \"{out_iface}\" {} saddr {ip} \
+                         masquerade",
+                        if ip.ip_addr.is_ipv4() { "ip" } else { "ip6" }
+                    )
+                    .ok();
+                }
+                writeln!(ret, "    }}").ok();
+            }
+        }
+        writeln!(ret, "}}").ok();
+        ret
+    }
+
+    /// Apply [WireguardNftables::to_script()] via `nft` command.
+    #[cfg(feature = "exec")]
+    pub fn apply(&self) -> Result<(), WireguardError> {
+        run_cmd("nft", &["-f", "-"], Some(&self.to_script()))
+    }
+
+    /// Delete the table created by [WireguardNftables::apply()].
+    #[cfg(feature = "exec")]
+    pub fn remove(&self) -> Result<(), WireguardError> {
+        run_cmd("nft", &["delete", "table", "inet", &self.table], None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WireguardPeerParsed;
+
+    fn config() -> WireguardParsed {
+        let peer = |ips: &[&str]| WireguardPeerParsed {
+            allowed_ips: Some(ips.iter().map(|i| i.parse().unwrap()).collect()),
+            ..Default::default()
+        };
+        WireguardParsed {
+            listen_port: Some(51820),
+            ..WireguardParsed::new("wg0")
+        }
+        .with_peers([
+            peer(&["10.0.0.2/32", "fd00::2/128", "0.0.0.0/0"]),
+            peer(&["10.0.0.2/32", "192.168.0.0/16"]),
+        ])
+    }
+
+    #[test]
+    fn new() {
+        let rules = WireguardNftables::new(&config()).unwrap();
+        assert_eq!(rules.table, "wireguard_wg0");
+        assert_eq!(rules.iface_name, "wg0");
+        assert_eq!(rules.listen_port, Some(51820));
+        // Default route and duplicate are excluded
+        assert_eq!(
+            rules
+                .allowed_ips
+                .iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>(),
+            ["10.0.0.2/32", "fd00::2/128", "192.168.0.0/16"]
+        );
+        assert!(rules.forward);
+        assert_eq!(rules.masquerade_iface, None);
+
+        let e =
+            WireguardNftables::new(&WireguardParsed::default()).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::InvalidConfig);
+    }
+
+    #[test]
+    fn accept_rules() {
+        let rules = WireguardNftables::new(&config()).unwrap();
+        assert_eq!(rules.input_rules(), ["udp dport 51820 accept"]);
+        assert_eq!(
+            rules.forward_rules(),
+            [
+                "iifname \"wg0\" accept",
+                "oifname \"wg0\" ct state established,related accept",
+            ]
+        );
+
+        let rules = WireguardNftables::new(&WireguardParsed::new("wg0"))
+            .unwrap()
+            .forward(false);
+        assert!(rules.input_rules().is_empty());
+        assert!(rules.forward_rules().is_empty());
+    }
+
+    #[test]
+    fn script_masquerade() {
+        let script = WireguardNftables::new(&config())
+            .unwrap()
+            .table("vpn")
+            .masquerade_via("eth0")
+            .to_script();
+        assert_eq!(
+            script.lines().collect::<Vec<_>>(),
+            [
+                "table inet vpn",
+                "delete table inet vpn",
+                "table inet vpn {",
+                "    chain postrouting {",
+                "        type nat hook postrouting priority srcnat; policy \
+                 accept;",
+                "        oifname \"eth0\" ip saddr 10.0.0.2/32 masquerade",
+                "        oifname \"eth0\" ip6 saddr fd00::2/128 masquerade",
+                "        oifname \"eth0\" ip saddr 192.168.0.0/16 masquerade",
+                "    }",
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn script_without_masquerade() {
+        let script = WireguardNftables::new(&config()).unwrap().to_script();
+        assert_eq!(
+            script,
+            "table inet wireguard_wg0\ndelete table inet wireguard_wg0\ntable \
+             inet wireguard_wg0 {\n}\n"
+        );
+    }
+}
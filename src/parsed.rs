@@ -1,24 +1,50 @@
 // SPDX-License-Identifier: MIT
 
-use base64::{prelude::BASE64_STANDARD, Engine};
+use std::net::IpAddr;
+
+use base64::{
+    prelude::{
+        BASE64_STANDARD, BASE64_STANDARD_NO_PAD, BASE64_URL_SAFE_NO_PAD,
+    },
+    Engine,
+};
+use netlink_packet_core::Emitable;
+use netlink_packet_generic::GenlMessage;
 use netlink_packet_wireguard::{
     WireguardAttribute, WireguardCmd, WireguardDeviceFlags, WireguardMessage,
 };
 
-use crate::{ErrorKind, WireguardError, WireguardPeerParsed};
+use crate::{
+    key::{debug_public_key, hidden_secret},
+    ErrorKind, WireguardError, WireguardIpAddress, WireguardPeerParsed,
+};
+
+// The `nla_len` of netlink attribute is u16, hence nested attribute like
+// WGDEVICE_A_PEERS cannot hold more than this.
+pub(crate) const NLA_MAX_LEN: usize = u16::MAX as usize;
+
+// Number of WireguardAttribute variants the build functions may emit
+const DEVICE_ATTR_MAX: usize = 8;
 
 #[derive(Clone, PartialEq, Eq, Default)]
 #[non_exhaustive]
 pub struct WireguardParsed {
+    /// Checked on build: at most 15 bytes without slash, colon, whitespace
+    /// or NUL, otherwise [ErrorKind::InvalidConfig].
     pub iface_name: Option<String>,
     pub iface_index: Option<u32>,
     /// Base64 encoded public key
     pub public_key: Option<String>,
     /// Base64 encoded private key, this property will be display as
-    /// `(hidden)` for `Debug` trait.
+    /// `(hidden)` for `Debug` trait. The alternate `Debug` (`{:#?}`) shows
+    /// a fingerprint of it and [crate::key_fingerprint()] of public keys.
+    /// The `debug_show_secrets` feature shows the key itself.
     pub private_key: Option<String>,
     pub listen_port: Option<u16>,
     pub fwmark: Option<u32>,
+    /// When setting, both `None` and empty list leave existing peers
+    /// untouched unless [WireguardParsedDeviceFlags::ReplacePeers] is set,
+    /// use [WireguardParsed::clear_peers()] to remove all peers.
     pub peers: Option<Vec<WireguardPeerParsed>>,
     pub flags: Option<Vec<WireguardParsedDeviceFlags>>,
 }
@@ -61,7 +87,7 @@ impl From<WireguardDeviceFlags> for WireguardParsedDeviceFlags {
 struct _WireguardParsed<'a> {
     iface_name: &'a Option<String>,
     iface_index: &'a Option<u32>,
-    public_key: &'a Option<String>,
+    public_key: Option<String>,
     private_key: Option<String>,
     listen_port: &'a Option<u16>,
     fwmark: &'a Option<u32>,
@@ -89,12 +115,8 @@ impl std::fmt::Debug for WireguardParsed {
             &_WireguardParsed {
                 iface_name,
                 iface_index,
-                public_key,
-                private_key: if private_key.is_some() {
-                    Some("(hidden)".to_string())
-                } else {
-                    None
-                },
+                public_key: debug_public_key(public_key, f.alternate()),
+                private_key: hidden_secret(private_key, f.alternate()),
                 listen_port,
                 fwmark,
                 peers,
@@ -105,8 +127,31 @@ impl std::fmt::Debug for WireguardParsed {
     }
 }
 
+/// Invalid attributes are ignored with warning logged, use
+/// [WireguardParsed::parse_with_warnings()] to get them instead.
 impl From<WireguardMessage> for WireguardParsed {
     fn from(msg: WireguardMessage) -> Self {
+        Self::parse(msg, &mut |e| log::warn!("Ignoring {}", e.msg))
+    }
+}
+
+impl WireguardParsed {
+    /// Like `From<WireguardMessage>`, but return the invalid attributes
+    /// ignored (e.g. malformed allowed IPs or handshake time) as
+    /// [ErrorKind::DecodeError] errors instead of logging them, so
+    /// applications can surface these data-quality issues.
+    pub fn parse_with_warnings(
+        msg: WireguardMessage,
+    ) -> (Self, Vec<WireguardError>) {
+        let mut warnings = Vec::new();
+        let ret = Self::parse(msg, &mut |e| warnings.push(e));
+        (ret, warnings)
+    }
+
+    fn parse(
+        msg: WireguardMessage,
+        on_invalid: &mut dyn FnMut(WireguardError),
+    ) -> Self {
         let mut ret = Self::default();
         for attr in msg.attributes {
             match attr {
@@ -123,8 +168,15 @@ impl From<WireguardMessage> for WireguardParsed {
                 WireguardAttribute::Peers(peers) => {
                     ret.peers = Some(
                         peers
-                            .into_iter()
-                            .map(WireguardPeerParsed::from)
+                            .iter()
+                            .map(|peer| {
+                                // Never fails as `on_invalid` never fails
+                                WireguardPeerParsed::parse(peer, &mut |e| {
+                                    on_invalid(e);
+                                    Ok(())
+                                })
+                                .unwrap_or_default()
+                            })
                             .collect(),
                     );
                 }
@@ -145,15 +197,105 @@ impl From<WireguardMessage> for WireguardParsed {
     }
 }
 
+/// Same attributes as [WireguardParsed::build()] holds, for composing
+/// custom [WireguardMessage].
+impl TryFrom<&WireguardParsed> for Vec<WireguardAttribute> {
+    type Error = WireguardError;
+
+    fn try_from(parsed: &WireguardParsed) -> Result<Self, WireguardError> {
+        Ok(parsed.build(WireguardCmd::SetDevice)?.attributes)
+    }
+}
+
 impl WireguardParsed {
+    /// Configuration of specified interface with everything else unset.
+    pub fn new(iface_name: &str) -> Self {
+        Self {
+            iface_name: Some(iface_name.to_string()),
+            ..Default::default()
+        }
+    }
+
     /// Build [WireguardMessage]
     pub fn build(
         &self,
         cmd: WireguardCmd,
     ) -> Result<WireguardMessage, WireguardError> {
-        let mut attributes: Vec<WireguardAttribute> = Vec::new();
+        let mut msg = WireguardMessage {
+            cmd,
+            attributes: Vec::with_capacity(DEVICE_ATTR_MAX),
+        };
+        self.build_into(cmd, &mut msg)?;
+        Ok(msg)
+    }
+
+    /// Serialize as generic netlink payload (generic netlink header
+    /// followed by wireguard attributes) appended to `buffer`, returning the
+    /// number of bytes appended. The netlink header is not included as its
+    /// message type (family ID) is only known at runtime.
+    ///
+    /// The output is stable for the same input, attributes are emitted in
+    /// this order and unset properties are omitted:
+    ///  * Device: `IfName`, `IfIndex`, `PublicKey`, `PrivateKey`, `ListenPort`,
+    ///    `Fwmark`, `Peers`, `Flags`
+    ///  * Peer: `Endpoint`, `PublicKey`, `PresharedKey`, `PersistentKeepalive`,
+    ///    `LastHandshake`, `RxBytes`, `TxBytes`, `AllowedIps`,
+    ///    `ProtocolVersion`, `Flags`
+    ///  * Allowed IP: `Cidr`, `Family`, `IpAddr`, `Flags`
+    ///
+    /// ```
+    /// use nl_wireguard::{WireguardCmd, WireguardParsed};
+    ///
+    /// let mut config = WireguardParsed::default();
+    /// config.iface_name = Some("wg0".into());
+    /// config.listen_port = Some(51820);
+    /// let mut buffer = Vec::new();
+    /// config.emit(WireguardCmd::SetDevice, &mut buffer).unwrap();
+    /// assert_eq!(
+    ///     buffer,
+    ///     [
+    ///         1, 1, 0, 0, // cmd, version, reserved
+    ///         8, 0, 2, 0, b'w', b'g', b'0', 0, // IfName
+    ///         6, 0, 6, 0, 0x6c, 0xca, 0, 0, // ListenPort, host byte order
+    ///     ]
+    /// );
+    /// ```
+    pub fn emit(
+        &self,
+        cmd: WireguardCmd,
+        buffer: &mut Vec<u8>,
+    ) -> Result<usize, WireguardError> {
+        let mut genl_msg = GenlMessage::from_payload(self.build(cmd)?);
+        genl_msg.finalize();
+        let len = genl_msg.buffer_len();
+        let start = buffer.len();
+        buffer.resize(start + len, 0);
+        genl_msg.emit(&mut buffer[start..]);
+        Ok(len)
+    }
+
+    /// Like [WireguardParsed::build()] but overwrite `msg` in place, reusing
+    /// the buffers of its attributes and peers. Keeping a scratch message
+    /// across calls reduces allocations when regenerating large
+    /// configurations repeatedly.
+    /// On error, the content of `msg` is unspecified.
+    pub fn build_into(
+        &self,
+        cmd: WireguardCmd,
+        msg: &mut WireguardMessage,
+    ) -> Result<(), WireguardError> {
+        let mut old_peers = Vec::new();
+        for attr in msg.attributes.iter_mut() {
+            if let WireguardAttribute::Peers(peers) = attr {
+                old_peers = std::mem::take(peers);
+            }
+        }
+        msg.cmd = cmd;
+        let attributes = &mut msg.attributes;
+        attributes.clear();
 
         if let Some(v) = self.iface_name.as_ref() {
+            validate_iface_name(v)?;
             attributes.push(WireguardAttribute::IfName(v.to_string()));
         }
 
@@ -184,11 +326,28 @@ impl WireguardParsed {
         }
 
         if let Some(peers) = self.peers.as_ref() {
-            let mut peer_addrs = Vec::new();
-            for peer in peers {
-                peer_addrs.push(peer.build()?);
+            old_peers.truncate(peers.len());
+            for (i, peer) in peers.iter().enumerate() {
+                match old_peers.get_mut(i) {
+                    Some(old_peer) => peer.build_into(old_peer)?,
+                    None => old_peers.push(peer.build()?),
+                }
             }
-            attributes.push(WireguardAttribute::Peers(peer_addrs));
+            let peers_attr = WireguardAttribute::Peers(old_peers);
+            if peers_attr.buffer_len() > NLA_MAX_LEN {
+                return Err(WireguardError::new(
+                    ErrorKind::TooLarge,
+                    format!(
+                        "{} peers require {} bytes which exceeds the netlink \
+                         attribute limit {NLA_MAX_LEN}, please split peers \
+                         into multiple set requests",
+                        peers.len(),
+                        peers_attr.buffer_len(),
+                    ),
+                    None,
+                ));
+            }
+            attributes.push(peers_attr);
         }
 
         if let Some(flags) = self.flags.as_ref() {
@@ -199,7 +358,193 @@ impl WireguardParsed {
             attributes.push(WireguardAttribute::Flags(flag_bits));
         }
 
-        Ok(WireguardMessage { cmd, attributes })
+        Ok(())
+    }
+
+    /// Copy without the runtime properties reported by kernel (interface
+    /// index, traffic counters, last handshake and protocol version of
+    /// peers), leaving pure configuration for comparing or persisting.
+    pub fn without_runtime_fields(&self) -> Self {
+        Self {
+            iface_index: None,
+            peers: self.peers.as_ref().map(|peers| {
+                peers
+                    .iter()
+                    .map(WireguardPeerParsed::without_runtime_fields)
+                    .collect()
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// Append `peers`, accepting any iterator (e.g. rows streamed from
+    /// database) instead of requiring a `Vec`.
+    pub fn with_peers<I>(mut self, peers: I) -> Self
+    where
+        I: IntoIterator<Item = WireguardPeerParsed>,
+    {
+        self.peers.get_or_insert_with(Vec::new).extend(peers);
+        self
+    }
+
+    /// Make applying this configuration remove every existing peer, i.e.
+    /// empty `peers` with [WireguardParsedDeviceFlags::ReplacePeers].
+    pub fn clear_peers(mut self) -> Self {
+        self.peers = Some(Vec::new());
+        let flags = self.flags.get_or_insert_with(Vec::new);
+        if !flags.contains(&WireguardParsedDeviceFlags::ReplacePeers) {
+            flags.push(WireguardParsedDeviceFlags::ReplacePeers);
+        }
+        self
+    }
+
+    /// Whether applying this configuration removes every existing peer,
+    /// see [WireguardParsed::clear_peers()].
+    pub fn is_clearing_peers(&self) -> bool {
+        self.peers.as_ref().is_some_and(Vec::is_empty)
+            && self
+                .flags
+                .as_deref()
+                .unwrap_or_default()
+                .contains(&WireguardParsedDeviceFlags::ReplacePeers)
+    }
+
+    /// Run [WireguardPeerParsed::check_duplicate_allowed_ips()] on every
+    /// peer.
+    pub fn check_duplicate_allowed_ips(
+        &mut self,
+        fix: bool,
+    ) -> Result<(), WireguardError> {
+        for peer in self.peers.iter_mut().flatten() {
+            peer.check_duplicate_allowed_ips(fix)?;
+        }
+        Ok(())
+    }
+
+    /// Peers reporting protocol version other than `expected`, see
+    /// [WireguardPeerParsed::has_unexpected_protocol()].
+    pub fn unexpected_protocol_peers(
+        &self,
+        expected: u32,
+    ) -> impl Iterator<Item = &WireguardPeerParsed> {
+        self.peers_iter()
+            .filter(move |p| p.has_unexpected_protocol(expected))
+    }
+
+    /// Number of peers, 0 if `peers` is `None`
+    pub fn peer_count(&self) -> usize {
+        self.peers.as_ref().map(Vec::len).unwrap_or_default()
+    }
+
+    /// Iterate peers, empty if `peers` is `None`
+    pub fn peers_iter(&self) -> impl Iterator<Item = &WireguardPeerParsed> {
+        self.peers.as_deref().unwrap_or_default().iter()
+    }
+
+    /// Iterate allowed IPs of all peers along with the peer holding it
+    pub fn iter_allowed_ips(
+        &self,
+    ) -> impl Iterator<Item = (&WireguardPeerParsed, &WireguardIpAddress)> {
+        self.peers_iter().flat_map(|peer| {
+            peer.allowed_ips
+                .as_deref()
+                .unwrap_or_default()
+                .iter()
+                .map(move |ip| (peer, ip))
+        })
+    }
+
+    /// Whether contains peer with specified base64 encoded public key
+    pub fn contains_peer(&self, public_key: &str) -> bool {
+        self.peers_iter()
+            .any(|p| p.public_key.as_deref() == Some(public_key))
+    }
+
+    /// Append `peer` without checking for existing peer of the same
+    /// public key, see [WireguardParsed::upsert_peer()].
+    pub fn push_peer(&mut self, peer: WireguardPeerParsed) {
+        self.peers.get_or_insert_with(Vec::new).push(peer);
+    }
+
+    /// Remove and return peer with specified base64 encoded public key.
+    pub fn remove_peer_by_key(
+        &mut self,
+        public_key: &str,
+    ) -> Option<WireguardPeerParsed> {
+        let peers = self.peers.as_mut()?;
+        let index = peers
+            .iter()
+            .position(|p| p.public_key.as_deref() == Some(public_key))?;
+        Some(peers.remove(index))
+    }
+
+    /// Replace the peer of the same public key with `peer` and return the
+    /// old one, or append `peer` if not found.
+    pub fn upsert_peer(
+        &mut self,
+        peer: WireguardPeerParsed,
+    ) -> Option<WireguardPeerParsed> {
+        let peers = self.peers.get_or_insert_with(Vec::new);
+        match peers
+            .iter_mut()
+            .find(|p| p.public_key.is_some() && p.public_key == peer.public_key)
+        {
+            Some(existing) => Some(std::mem::replace(existing, peer)),
+            None => {
+                peers.push(peer);
+                None
+            }
+        }
+    }
+
+    /// The peer which kernel sends packet destined to `ip` to, that is the
+    /// peer having the allowed IP of longest prefix matching `ip`
+    /// (cryptokey routing). Like kernel, the allowed IP listed in multiple
+    /// peers is owned by the last one.
+    pub fn route_lookup(&self, ip: IpAddr) -> Option<&WireguardPeerParsed> {
+        let mut ret: Option<(&WireguardPeerParsed, u8)> = None;
+        for (peer, allowed_ip) in self.iter_allowed_ips() {
+            let longer = match ret {
+                Some((_, len)) => allowed_ip.prefix_length >= len,
+                None => true,
+            };
+            if longer && allowed_ip.contains(ip) {
+                ret = Some((peer, allowed_ip.prefix_length));
+            }
+        }
+        ret.map(|(peer, _)| peer)
+    }
+}
+
+// IFNAMSIZ minus the trailing NUL
+pub(crate) const IFACE_NAME_MAX_LEN: usize = 15;
+
+// Same rules as `dev_valid_name()` of kernel
+pub(crate) fn validate_iface_name(name: &str) -> Result<(), WireguardError> {
+    let reason = if name.is_empty() {
+        Some("empty".to_string())
+    } else if name.len() > IFACE_NAME_MAX_LEN {
+        Some(format!(
+            "{} bytes exceeds the limit {IFACE_NAME_MAX_LEN}",
+            name.len()
+        ))
+    } else if name == "." || name == ".." {
+        Some("reserved".to_string())
+    } else if name
+        .chars()
+        .any(|c| c == '/' || c == ':' || c == '\0' || c.is_whitespace())
+    {
+        Some("slash, colon, whitespace or NUL is not allowed".to_string())
+    } else {
+        None
+    };
+    match reason {
+        Some(reason) => Err(WireguardError::new(
+            ErrorKind::InvalidConfig,
+            format!("Invalid interface name {name:?}: {reason}"),
+            None,
+        )),
+        None => Ok(()),
     }
 }
 
@@ -207,12 +552,36 @@ pub(crate) fn decode_key(
     prop_name: &str,
     key_str: &str,
 ) -> Result<[u8; WireguardAttribute::WG_KEY_LEN], WireguardError> {
-    let key = BASE64_STANDARD.decode(key_str).map_err(|e| {
+    decode_key_with_alphabet(prop_name, key_str, false)
+}
+
+/// Like [decode_key()] but also accept the URL-safe alphabet.
+pub(crate) fn decode_key_tolerant(
+    prop_name: &str,
+    key_str: &str,
+) -> Result<[u8; WireguardAttribute::WG_KEY_LEN], WireguardError> {
+    decode_key_with_alphabet(prop_name, key_str, true)
+}
+
+// The key is never included in error message as it might be a private key.
+fn decode_key_with_alphabet(
+    prop_name: &str,
+    key_str: &str,
+    url_safe: bool,
+) -> Result<[u8; WireguardAttribute::WG_KEY_LEN], WireguardError> {
+    // Tolerate surrounding whitespace and missing padding of keys copied
+    // from other tools
+    let trimmed = key_str.trim().trim_end_matches('=');
+    let engine = if url_safe && trimmed.contains(['-', '_']) {
+        &BASE64_URL_SAFE_NO_PAD
+    } else {
+        &BASE64_STANDARD_NO_PAD
+    };
+    let key = engine.decode(trimmed).map_err(|e| {
         WireguardError::new(
             ErrorKind::InvalidKey,
             format!(
-                "Invalid {prop_name}: not valid base64 encoded string \
-                 {key_str}: {e}"
+                "Invalid {prop_name}: not valid base64 encoded string: {e}"
             ),
             None,
         )
@@ -222,7 +591,7 @@ pub(crate) fn decode_key(
             ErrorKind::InvalidKey,
             format!(
                 "Invalid {prop_name}: current length {}, but expecting {} \
-                 length of u8 encoded base64 string, {key_str}",
+                 length of u8 encoded base64 string",
                 key.len(),
                 WireguardAttribute::WG_KEY_LEN
             ),
@@ -233,3 +602,302 @@ pub(crate) fn decode_key(
     key_data.copy_from_slice(&key);
     Ok(key_data)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{
+        WireguardIpAddress, WireguardParsedAllowedIpFlags,
+        WireguardParsedPeerFlags,
+    };
+
+    // Key pair of Alice in RFC 7748 section 6.1
+    const PRIVATE_KEY: &str = "dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=";
+    const PUBLIC_KEY: &str = "hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=";
+    const PRIVATE_KEY_BYTES: [u8; 32] = [
+        0x77, 0x07, 0x6d, 0x0a, 0x73, 0x18, 0xa5, 0x7d, 0x3c, 0x16, 0xc1, 0x72,
+        0x51, 0xb2, 0x66, 0x45, 0xdf, 0x4c, 0x2f, 0x87, 0xeb, 0xc0, 0x99, 0x2a,
+        0xb1, 0x77, 0xfb, 0xa5, 0x1d, 0xb9, 0x2c, 0x2a,
+    ];
+    const PUBLIC_KEY_BYTES: [u8; 32] = [
+        0x85, 0x20, 0xf0, 0x09, 0x89, 0x30, 0xa7, 0x54, 0x74, 0x8b, 0x7d, 0xdc,
+        0xb4, 0x3e, 0xf7, 0x5a, 0x0d, 0xbf, 0x3a, 0x0d, 0x26, 0x38, 0x1a, 0xf4,
+        0xeb, 0xa4, 0xa9, 0x8e, 0xaa, 0x9b, 0x4e, 0x6a,
+    ];
+
+    const GENL_AND_IFNAME: [u8; 12] = [
+        1, 1, 0, 0, // cmd SetDevice, version, reserved
+        8, 0, 2, 0, b'w', b'g', b'0', 0, // IfName
+    ];
+
+    fn emit(config: &WireguardParsed) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        let len = config.emit(WireguardCmd::SetDevice, &mut buffer).unwrap();
+        assert_eq!(len, buffer.len());
+        buffer
+    }
+
+    #[test]
+    fn emit_device_with_keys_and_flags() {
+        let mut config = WireguardParsed::new("wg0");
+        config.flags = Some(vec![WireguardParsedDeviceFlags::ReplacePeers]);
+        config.fwmark = Some(0x10);
+        config.listen_port = Some(51820);
+        config.private_key = Some(PRIVATE_KEY.to_string());
+        config.public_key = Some(PUBLIC_KEY.to_string());
+        config.iface_index = Some(3);
+
+        let expected = [
+            &GENL_AND_IFNAME[..],
+            &[8, 0, 1, 0, 3, 0, 0, 0], // IfIndex
+            &[36, 0, 4, 0],            // PublicKey
+            &PUBLIC_KEY_BYTES,
+            &[36, 0, 3, 0], // PrivateKey
+            &PRIVATE_KEY_BYTES,
+            &[6, 0, 6, 0, 0x6c, 0xca, 0, 0], // ListenPort
+            &[8, 0, 7, 0, 0x10, 0, 0, 0],    // Fwmark
+            &[8, 0, 5, 0, 1, 0, 0, 0],       // Flags: ReplacePeers
+        ]
+        .concat();
+        assert_eq!(emit(&config), expected);
+    }
+
+    #[test]
+    fn emit_peer_with_all_attributes() {
+        let peer = WireguardPeerParsed {
+            flags: Some(vec![WireguardParsedPeerFlags::ReplaceAllowedIps]),
+            protocol_version: Some(1),
+            allowed_ips: Some(vec![WireguardIpAddress {
+                ip_addr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                prefix_length: 32,
+                flags: None,
+            }]),
+            tx_bytes: Some(2),
+            rx_bytes: Some(1),
+            last_handshake: Some(Duration::new(1, 2)),
+            persistent_keepalive: Some(25),
+            preshared_key: Some(PUBLIC_KEY.to_string()),
+            public_key: Some(PUBLIC_KEY.to_string()),
+            endpoint: Some("192.0.2.1:51820".parse().unwrap()),
+            ..Default::default()
+        };
+        let config = WireguardParsed::new("wg0").with_peers([peer]);
+
+        let expected = [
+            &GENL_AND_IFNAME[..],
+            &[200, 0, 8, 0x80], // Peers, nested
+            &[196, 0, 0, 0x80], // Peer 0, nested
+            &[20, 0, 4, 0],     // Endpoint: sockaddr_in
+            &[2, 0, 0xca, 0x6c, 192, 0, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0],
+            &[36, 0, 1, 0], // PublicKey
+            &PUBLIC_KEY_BYTES,
+            &[36, 0, 2, 0], // PresharedKey
+            &PUBLIC_KEY_BYTES,
+            &[6, 0, 5, 0, 25, 0, 0, 0], // PersistentKeepalive
+            &[20, 0, 6, 0],             // LastHandshake: timespec
+            &[1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0],
+            &[12, 0, 7, 0, 1, 0, 0, 0, 0, 0, 0, 0], // RxBytes
+            &[12, 0, 8, 0, 2, 0, 0, 0, 0, 0, 0, 0], // TxBytes
+            &[32, 0, 9, 0x80],                      // AllowedIps, nested
+            &[28, 0, 0, 0x80],                      // Allowed IP 0, nested
+            &[5, 0, 3, 0, 32, 0, 0, 0],             // Cidr
+            &[6, 0, 1, 0, 2, 0, 0, 0],              // Family: AF_INET
+            &[8, 0, 2, 0, 10, 0, 0, 1],             // IpAddr
+            &[8, 0, 10, 0, 1, 0, 0, 0],             // ProtocolVersion
+            &[8, 0, 3, 0, 2, 0, 0, 0],              // Flags: ReplaceAllowedIps
+        ]
+        .concat();
+        assert_eq!(emit(&config), expected);
+    }
+
+    #[test]
+    fn emit_multiple_allowed_ips() {
+        let peer = WireguardPeerParsed {
+            public_key: Some(PUBLIC_KEY.to_string()),
+            allowed_ips: Some(vec![
+                WireguardIpAddress {
+                    ip_addr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)),
+                    prefix_length: 8,
+                    flags: None,
+                },
+                WireguardIpAddress {
+                    ip_addr: "fd00::".parse().unwrap(),
+                    prefix_length: 64,
+                    flags: Some(vec![WireguardParsedAllowedIpFlags::RemoveMe]),
+                },
+            ]),
+            ..Default::default()
+        };
+        let config = WireguardParsed::new("wg0").with_peers([peer]);
+
+        let expected = [
+            &GENL_AND_IFNAME[..],
+            &[124, 0, 8, 0x80], // Peers, nested
+            &[120, 0, 0, 0x80], // Peer 0, nested
+            &[36, 0, 1, 0],     // PublicKey
+            &PUBLIC_KEY_BYTES,
+            &[80, 0, 9, 0x80],          // AllowedIps, nested
+            &[28, 0, 0, 0x80],          // Allowed IP 0, nested
+            &[5, 0, 3, 0, 8, 0, 0, 0],  // Cidr
+            &[6, 0, 1, 0, 2, 0, 0, 0],  // Family: AF_INET
+            &[8, 0, 2, 0, 10, 0, 0, 0], // IpAddr
+            &[48, 0, 0, 0x80],          // Allowed IP 1, nested
+            &[5, 0, 3, 0, 64, 0, 0, 0], // Cidr
+            &[6, 0, 1, 0, 10, 0, 0, 0], // Family: AF_INET6
+            &[20, 0, 2, 0],             // IpAddr
+            &[0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            &[8, 0, 4, 0, 1, 0, 0, 0], // Flags: RemoveMe
+        ]
+        .concat();
+        assert_eq!(emit(&config), expected);
+    }
+
+    fn peer_with_ips(public_key: &str, ips: &[&str]) -> WireguardPeerParsed {
+        WireguardPeerParsed {
+            public_key: Some(public_key.to_string()),
+            allowed_ips: Some(ips.iter().map(|i| i.parse().unwrap()).collect()),
+            ..Default::default()
+        }
+    }
+
+    fn route_lookup_key(config: &WireguardParsed, ip: &str) -> Option<String> {
+        config
+            .route_lookup(ip.parse().unwrap())
+            .and_then(|p| p.public_key.clone())
+    }
+
+    #[test]
+    fn route_lookup_longest_prefix() {
+        let config = WireguardParsed::new("wg0").with_peers([
+            peer_with_ips("default", &["0.0.0.0/0", "::/0"]),
+            peer_with_ips("site", &["10.0.0.0/8", "fd00::/16"]),
+            peer_with_ips("subnet", &["10.1.0.0/16", "fd00:1::/32"]),
+            peer_with_ips("host", &["10.1.0.1/32", "fd00:1::1/128"]),
+        ]);
+        let cases = [
+            ("192.0.2.1", "default"),
+            ("10.2.0.1", "site"),
+            ("10.1.0.2", "subnet"),
+            ("10.1.0.1", "host"),
+            ("2001:db8::1", "default"),
+            ("fd00:2::1", "site"),
+            ("fd00:1::2", "subnet"),
+            ("fd00:1::1", "host"),
+        ];
+        for (ip, expected) in cases {
+            assert_eq!(
+                route_lookup_key(&config, ip).as_deref(),
+                Some(expected),
+                "{ip}"
+            );
+        }
+    }
+
+    #[test]
+    fn route_lookup_no_match() {
+        assert_eq!(
+            route_lookup_key(&WireguardParsed::default(), "10.0.0.1"),
+            None
+        );
+        let config = WireguardParsed::new("wg0")
+            .with_peers([peer_with_ips("v4", &["0.0.0.0/0"])]);
+        assert_eq!(route_lookup_key(&config, "fd00::1"), None);
+        let config = WireguardParsed::new("wg0")
+            .with_peers([peer_with_ips("v6", &["::/0"])]);
+        assert_eq!(route_lookup_key(&config, "10.0.0.1"), None);
+    }
+
+    #[test]
+    fn route_lookup_tie() {
+        // Order of peers in config does not matter for different lengths
+        let config = WireguardParsed::new("wg0").with_peers([
+            peer_with_ips("subnet", &["10.1.0.0/16"]),
+            peer_with_ips("site", &["10.0.0.0/8"]),
+        ]);
+        assert_eq!(
+            route_lookup_key(&config, "10.1.0.1").as_deref(),
+            Some("subnet")
+        );
+        // Same allowed IP in two peers, the last one wins
+        let config = WireguardParsed::new("wg0").with_peers([
+            peer_with_ips("first", &["10.0.0.0/8"]),
+            peer_with_ips("last", &["10.0.0.0/8"]),
+        ]);
+        assert_eq!(
+            route_lookup_key(&config, "10.0.0.1").as_deref(),
+            Some("last")
+        );
+    }
+
+    #[test]
+    fn decode_key_tolerates_whitespace_and_missing_padding() {
+        let cases = [
+            PUBLIC_KEY.to_string(),
+            format!("  {PUBLIC_KEY}\n"),
+            format!("\t{PUBLIC_KEY}\r\n"),
+            PUBLIC_KEY.trim_end_matches('=').to_string(),
+            format!(" {} ", PUBLIC_KEY.trim_end_matches('=')),
+        ];
+        for key in cases {
+            assert_eq!(
+                decode_key("public_key", &key).unwrap(),
+                PUBLIC_KEY_BYTES,
+                "{key:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn decode_key_url_safe_is_opt_in() {
+        let url_safe = PUBLIC_KEY.replace('/', "_");
+        let e = decode_key("public_key", &url_safe).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::InvalidKey);
+        assert_eq!(
+            decode_key_tolerant("public_key", &url_safe).unwrap(),
+            PUBLIC_KEY_BYTES
+        );
+        assert_eq!(
+            decode_key_tolerant("public_key", PUBLIC_KEY).unwrap(),
+            PUBLIC_KEY_BYTES
+        );
+        assert_eq!(crate::normalize_key(&url_safe).unwrap(), PUBLIC_KEY);
+    }
+
+    #[test]
+    fn decode_key_wrong_length() {
+        let cases = [
+            "",
+            "   ",
+            // 31 bytes
+            "dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LA==",
+            // 33 bytes
+            "dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCoA",
+        ];
+        for key in cases {
+            let e = decode_key("private_key", key).unwrap_err();
+            assert_eq!(e.kind, ErrorKind::InvalidKey, "{key:?}");
+            assert!(e.msg.contains("expecting 32"), "{e}");
+        }
+    }
+
+    #[test]
+    fn decode_key_error_hides_key() {
+        let cases = [
+            format!("{PRIVATE_KEY}A"),
+            format!("{PRIVATE_KEY}AAAA"),
+            PRIVATE_KEY.replace('C', "*"),
+            format!("{PRIVATE_KEY}{PRIVATE_KEY}"),
+        ];
+        for key in cases {
+            let e = decode_key("private_key", &key).unwrap_err();
+            assert_eq!(e.kind, ErrorKind::InvalidKey, "{key:?}");
+            assert!(e.msg.starts_with("Invalid private_key: "), "{e}");
+            assert!(!e.msg.contains(&PRIVATE_KEY[..16]), "{e}");
+        }
+    }
+}
@@ -0,0 +1,270 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(any(feature = "tokio_socket", feature = "smol_socket"))]
+use crate::runtime::sleep;
+use crate::{
+    key::canonical_key, key_fingerprint, WireguardError, WireguardHandle,
+    WireguardParsed, WireguardParsedPeerFlags, WireguardPeerParsed,
+    WireguardQuickConfig,
+};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Peer label holding expiry time in seconds since UNIX_EPOCH, used by
+/// [WireguardPeerExpiry::with_labels()].
+pub const WIREGUARD_EXPIRY_LABEL: &str = "expiry";
+
+type ExpirySource =
+    Box<dyn FnMut(&WireguardPeerParsed) -> Option<SystemTime> + Send>;
+
+/// Remove peers from wireguard interface once they expired, e.g. for guest
+/// access or short-lived credentials.
+///
+/// The expiry time set by [WireguardPeerExpiry::expire_at()] takes
+/// precedence over the one returned by [WireguardPeerExpiry::with_source()].
+/// Peers without expiry time are never removed.
+pub struct WireguardPeerExpiry {
+    handle: WireguardHandle,
+    iface_name: String,
+    expiry: HashMap<String, SystemTime>,
+    source: Option<ExpirySource>,
+    interval: Duration,
+}
+
+impl std::fmt::Debug for WireguardPeerExpiry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WireguardPeerExpiry")
+            .field("handle", &self.handle)
+            .field("iface_name", &self.iface_name)
+            .field("expiry", &self.expiry)
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WireguardPeerExpiry {
+    pub fn new(handle: WireguardHandle, iface_name: &str) -> Self {
+        Self {
+            handle,
+            iface_name: iface_name.to_string(),
+            expiry: HashMap::new(),
+            source: None,
+            interval: DEFAULT_INTERVAL,
+        }
+    }
+
+    /// Remove peer of specified public key after `time`.
+    pub fn expire_at(mut self, public_key: &str, time: SystemTime) -> Self {
+        self.expiry.insert(canonical_key(public_key), time);
+        self
+    }
+
+    /// Use the [WIREGUARD_EXPIRY_LABEL] label of peers in `config`.
+    /// Invalid label values are ignored with warning.
+    pub fn with_labels(mut self, config: &WireguardQuickConfig) -> Self {
+        self.expiry.extend(expiry_from_labels(config));
+        self
+    }
+
+    /// Query expiry time of peers not set by
+    /// [WireguardPeerExpiry::expire_at()] from `source`.
+    pub fn with_source<F>(mut self, source: F) -> Self
+    where
+        F: FnMut(&WireguardPeerParsed) -> Option<SystemTime> + Send + 'static,
+    {
+        self.source = Some(Box::new(source));
+        self
+    }
+
+    /// Interval between two checks of [WireguardPeerExpiry::run()], default
+    /// is 60 seconds.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Remove expired peers from interface, return their public keys.
+    pub async fn remove_expired(
+        &mut self,
+    ) -> Result<Vec<String>, WireguardError> {
+        let current = self.handle.get_by_name(&self.iface_name).await?;
+        let expired = expired_peers(
+            &self.expiry,
+            self.source.as_mut(),
+            &current,
+            SystemTime::now(),
+        );
+        if expired.is_empty() {
+            return Ok(expired);
+        }
+        log::info!(
+            "Removing expired peers from {}: {}",
+            self.iface_name,
+            expired
+                .iter()
+                .map(|k| key_fingerprint(k))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        self.handle
+            .set(WireguardParsed {
+                iface_name: Some(self.iface_name.clone()),
+                peers: Some(
+                    expired
+                        .iter()
+                        .map(|public_key| WireguardPeerParsed {
+                            public_key: Some(public_key.clone()),
+                            flags: Some(vec![
+                                WireguardParsedPeerFlags::RemoveMe,
+                            ]),
+                            ..Default::default()
+                        })
+                        .collect(),
+                ),
+                ..Default::default()
+            })
+            .await?;
+        for public_key in expired.iter() {
+            self.expiry.remove(&canonical_key(public_key));
+        }
+        Ok(expired)
+    }
+
+    /// Remove expired peers periodically, never return.
+    #[cfg(any(feature = "tokio_socket", feature = "smol_socket"))]
+    pub async fn run(&mut self) {
+        loop {
+            if let Err(e) = self.remove_expired().await {
+                log::warn!(
+                    "Failed to remove expired peers of {}: {e}",
+                    self.iface_name
+                );
+            }
+            sleep(self.interval).await;
+        }
+    }
+}
+
+// Expiry time indexed by public key in canonical form
+fn expiry_from_labels(
+    config: &WireguardQuickConfig,
+) -> HashMap<String, SystemTime> {
+    let mut ret = HashMap::new();
+    for (public_key, labels) in config.peer_labels.iter().flatten() {
+        let Some(value) = labels.get(WIREGUARD_EXPIRY_LABEL) else {
+            continue;
+        };
+        match value.parse::<u64>() {
+            Ok(secs) => {
+                ret.insert(
+                    canonical_key(public_key),
+                    UNIX_EPOCH + Duration::from_secs(secs),
+                );
+            }
+            Err(e) => log::warn!(
+                "Ignoring invalid {WIREGUARD_EXPIRY_LABEL} label {value} of \
+                 peer {}: {e}",
+                key_fingerprint(public_key)
+            ),
+        }
+    }
+    ret
+}
+
+// Public keys (as reported by kernel) of peers in `current` expired at `now`
+fn expired_peers(
+    expiry: &HashMap<String, SystemTime>,
+    mut source: Option<&mut ExpirySource>,
+    current: &WireguardParsed,
+    now: SystemTime,
+) -> Vec<String> {
+    let mut ret = Vec::new();
+    for peer in current.peers_iter() {
+        let Some(public_key) = peer.public_key.as_deref() else {
+            continue;
+        };
+        let expiry = match expiry.get(&canonical_key(public_key)) {
+            Some(t) => Some(*t),
+            None => source.as_mut().and_then(|s| s(peer)),
+        };
+        if expiry.map(|t| t <= now) == Some(true) {
+            ret.push(public_key.to_string());
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY1: &str = "uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM=";
+    const KEY2: &str = "WEAuaVuhdyscyTCXVfBDJR6nf9zxD75jmJzrfhkyE3Y=";
+    const KEY3: &str = "hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=";
+
+    fn secs(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn expiry_labels() {
+        let config: WireguardQuickConfig = [
+            "[Peer]",
+            // Unpadded key is stored in canonical form
+            "PublicKey = uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM",
+            "#! expiry = 1700000000",
+            "[Peer]",
+            &format!("PublicKey = {KEY2}"),
+            "#! expiry = tomorrow",
+            "[Peer]",
+            &format!("PublicKey = {KEY3}"),
+            "#! owner = alice",
+        ]
+        .join("\n")
+        .parse()
+        .unwrap();
+        assert_eq!(
+            expiry_from_labels(&config),
+            HashMap::from([(KEY1.to_string(), secs(1700000000))])
+        );
+    }
+
+    #[test]
+    fn expired_peers_by_time_and_source() {
+        let current = WireguardParsed::new("wg0").with_peers(
+            [KEY1, KEY2, KEY3].into_iter().map(WireguardPeerParsed::new),
+        );
+        let expiry = HashMap::from([
+            (KEY1.to_string(), secs(100)),
+            (KEY2.to_string(), secs(300)),
+        ]);
+        assert_eq!(expired_peers(&expiry, None, &current, secs(200)), [KEY1]);
+
+        // Source is only consulted for peers without expiry time
+        let mut source: ExpirySource = Box::new(|peer| {
+            assert_eq!(peer.public_key.as_deref(), Some(KEY3));
+            Some(secs(150))
+        });
+        assert_eq!(
+            expired_peers(&expiry, Some(&mut source), &current, secs(200)),
+            [KEY1, KEY3]
+        );
+        assert!(expired_peers(&expiry, None, &current, secs(50)).is_empty());
+    }
+
+    #[test]
+    fn expired_peers_match_canonical_key() {
+        let current = WireguardParsed::new("wg0")
+            .with_peers([WireguardPeerParsed::new(KEY1)]);
+        let expiry = HashMap::from([(
+            canonical_key(" uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM"),
+            secs(100),
+        )]);
+        assert_eq!(expired_peers(&expiry, None, &current, secs(200)), [KEY1]);
+    }
+}
@@ -2,19 +2,29 @@
 
 use std::{
     convert::TryFrom,
-    net::{IpAddr, SocketAddr},
-    time::Duration,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use base64::{prelude::BASE64_STANDARD, Engine};
+use netlink_packet_core::Emitable;
 use netlink_packet_wireguard::{
     WireguardAddressFamily, WireguardAllowedIp, WireguardAllowedIpAttr,
     WireguardAllowedIpFlags, WireguardPeer, WireguardPeerAttribute,
     WireguardPeerFlags, WireguardTimeSpec,
 };
 
-use super::parsed::decode_key;
-use crate::{ErrorKind, WireguardError};
+use super::parsed::{decode_key, NLA_MAX_LEN};
+use crate::{
+    key::{debug_public_key, hidden_secret},
+    ErrorKind, WireguardError,
+};
+
+// Number of WireguardPeerAttribute variants the build functions may emit
+const PEER_ATTR_MAX: usize = 10;
+// Number of WireguardAllowedIpAttr variants the build functions may emit
+const ALLOWED_IP_ATTR_MAX: usize = 4;
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[non_exhaustive]
@@ -64,7 +74,8 @@ pub struct WireguardPeerParsed {
     /// Base64 encoded public key
     pub public_key: Option<String>,
     /// Base64 encoded pre-shared key, this property will be display as
-    /// `(hidden)` for `Debug` trait.
+    /// `(hidden)` for `Debug` trait unless the `debug_show_secrets` feature
+    /// is enabled.
     pub preshared_key: Option<String>,
     pub persistent_keepalive: Option<u16>,
     /// Last handshake time since UNIX_EPOCH
@@ -72,6 +83,7 @@ pub struct WireguardPeerParsed {
     pub rx_bytes: Option<u64>,
     pub tx_bytes: Option<u64>,
     pub allowed_ips: Option<Vec<WireguardIpAddress>>,
+    /// Reported by kernel, see [WireguardPeerParsed::PROTOCOL_VERSION]
     pub protocol_version: Option<u32>,
     pub flags: Option<Vec<WireguardParsedPeerFlags>>,
 }
@@ -82,7 +94,7 @@ pub struct WireguardPeerParsed {
 #[derive(Debug)]
 struct _WireguardPeerParsed<'a> {
     endpoint: &'a Option<SocketAddr>,
-    public_key: &'a Option<String>,
+    public_key: Option<String>,
     preshared_key: Option<String>,
     persistent_keepalive: &'a Option<u16>,
     last_handshake: &'a Option<Duration>,
@@ -114,12 +126,8 @@ impl std::fmt::Debug for WireguardPeerParsed {
         std::fmt::Debug::fmt(
             &_WireguardPeerParsed {
                 endpoint,
-                public_key,
-                preshared_key: if preshared_key.is_some() {
-                    Some("(hidden)".to_string())
-                } else {
-                    None
-                },
+                public_key: debug_public_key(public_key, f.alternate()),
+                preshared_key: hidden_secret(preshared_key, f.alternate()),
                 persistent_keepalive,
                 last_handshake,
                 rx_bytes,
@@ -133,10 +141,191 @@ impl std::fmt::Debug for WireguardPeerParsed {
     }
 }
 
+impl WireguardPeerParsed {
+    /// Peer of specified base64 encoded public key with everything else
+    /// unset.
+    pub fn new(public_key: &str) -> Self {
+        Self {
+            public_key: Some(public_key.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Endpoint as raw socket address, e.g. for passing
+    /// `sockaddr_in`/`sockaddr_in6` via [socket2::SockAddr::as_ptr()] to
+    /// FFI.
+    #[cfg(feature = "socket2")]
+    pub fn endpoint_sockaddr(&self) -> Option<socket2::SockAddr> {
+        self.endpoint.map(socket2::SockAddr::from)
+    }
+
+    /// Set endpoint from raw socket address, e.g. the source address of
+    /// packet received from roamed peer. Only `AF_INET` and `AF_INET6`
+    /// are supported.
+    #[cfg(feature = "socket2")]
+    pub fn set_endpoint_sockaddr(
+        &mut self,
+        addr: &socket2::SockAddr,
+    ) -> Result<(), WireguardError> {
+        let endpoint = addr.as_socket().ok_or_else(|| {
+            WireguardError::new(
+                ErrorKind::InvalidConfig,
+                format!(
+                    "Endpoint socket address of family {} is not IPv4 or IPv6",
+                    addr.family()
+                ),
+                None,
+            )
+        })?;
+        self.endpoint = Some(normalize_endpoint(endpoint));
+        Ok(())
+    }
+
+    /// Append `ips` to allowed IPs.
+    pub fn with_allowed_ips<I>(mut self, ips: I) -> Self
+    where
+        I: IntoIterator<Item = WireguardIpAddress>,
+    {
+        self.allowed_ips.get_or_insert_with(Vec::new).extend(ips);
+        self
+    }
+
+    /// Route all IPv4 and IPv6 traffic to this peer, i.e. allowed IPs
+    /// `0.0.0.0/0` and `::/0`.
+    pub fn allow_all_traffic(mut self) -> Self {
+        self.allowed_ips = Some(vec![
+            WireguardIpAddress::any_v4(),
+            WireguardIpAddress::any_v6(),
+        ]);
+        self
+    }
+
+    /// The only WireGuard protocol version supported by Linux kernel
+    pub const PROTOCOL_VERSION: u32 = 1;
+
+    /// Whether `protocol_version` is reported and differs from `expected`
+    /// (usually [WireguardPeerParsed::PROTOCOL_VERSION]).
+    pub fn has_unexpected_protocol(&self, expected: u32) -> bool {
+        self.protocol_version.is_some_and(|v| v != expected)
+    }
+
+    /// WireGuard rejects the session after this long without new handshake
+    /// (`REJECT_AFTER_TIME`), a good `max_age` for
+    /// [WireguardPeerParsed::is_connected()].
+    pub const REJECT_AFTER_TIME: Duration = Duration::from_secs(180);
+
+    /// Check whether the same network is listed more than once in
+    /// `allowed_ips`. With `fix`, duplicates are removed (keeping the first
+    /// one) instead of failing with [ErrorKind::InvalidConfig].
+    pub fn check_duplicate_allowed_ips(
+        &mut self,
+        fix: bool,
+    ) -> Result<(), WireguardError> {
+        let Some(ips) = self.allowed_ips.as_mut() else {
+            return Ok(());
+        };
+        let mut uniq: Vec<WireguardIpAddress> = Vec::with_capacity(ips.len());
+        for ip in ips.iter() {
+            if uniq.iter().any(|i| i.is_same_network(ip)) {
+                if !fix {
+                    return Err(WireguardError::new(
+                        ErrorKind::InvalidConfig,
+                        format!(
+                            "Peer {} has duplicate allowed IP {ip}",
+                            self.public_key
+                                .as_deref()
+                                .unwrap_or("(no public key)"),
+                        ),
+                        None,
+                    ));
+                }
+                log::info!("Removing duplicate allowed IP {ip}");
+            } else {
+                uniq.push(ip.clone());
+            }
+        }
+        *ips = uniq;
+        Ok(())
+    }
+
+    /// Time elapsed since last handshake, `None` if never handshaked.
+    pub fn handshake_age(&self) -> Option<Duration> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.last_handshake.map(|t| now.saturating_sub(t))
+    }
+
+    /// Whether last handshake happened within `max_age`.
+    pub fn is_connected(&self, max_age: Duration) -> bool {
+        self.handshake_age().map(|age| age < max_age) == Some(true)
+    }
+
+    /// Copy without traffic counters, last handshake and protocol version.
+    pub fn without_runtime_fields(&self) -> Self {
+        Self {
+            last_handshake: None,
+            rx_bytes: None,
+            tx_bytes: None,
+            protocol_version: None,
+            ..self.clone()
+        }
+    }
+}
+
 impl From<WireguardPeer> for WireguardPeerParsed {
     fn from(attrs: WireguardPeer) -> Self {
+        Self::from(&attrs)
+    }
+}
+
+/// Invalid attributes are ignored with warning logged, use
+/// [WireguardPeerParsed::try_parse()] to get error instead.
+impl From<&WireguardPeer> for WireguardPeerParsed {
+    fn from(attrs: &WireguardPeer) -> Self {
+        // Never fails as invalid attributes are only logged
+        Self::parse(attrs, &mut |e| {
+            log::warn!("Ignoring {}", e.msg);
+            Ok(())
+        })
+        .unwrap_or_default()
+    }
+}
+
+impl WireguardPeerParsed {
+    /// Parse peer attributes (e.g. from reply of
+    /// [crate::WireguardHandle::request()]), fail with
+    /// [ErrorKind::DecodeError] naming the first invalid attribute instead
+    /// of ignoring it like `From<&WireguardPeer>` does.
+    pub fn try_parse(attrs: &WireguardPeer) -> Result<Self, WireguardError> {
+        Self::parse(attrs, &mut Err)
+    }
+
+    /// Like `From<&WireguardPeer>`, but return the invalid attributes
+    /// ignored as [ErrorKind::DecodeError] errors instead of logging them.
+    pub fn parse_with_warnings(
+        attrs: &WireguardPeer,
+    ) -> (Self, Vec<WireguardError>) {
+        let mut warnings = Vec::new();
+        // Never fails as invalid attributes are only collected
+        let ret = Self::parse(attrs, &mut |e| {
+            warnings.push(e);
+            Ok(())
+        })
+        .unwrap_or_default();
+        (ret, warnings)
+    }
+
+    // Invalid attribute is reported to `on_invalid` and then ignored, unless
+    // `on_invalid` returns error.
+    pub(crate) fn parse(
+        attrs: &WireguardPeer,
+        on_invalid: &mut dyn FnMut(
+            WireguardError,
+        ) -> Result<(), WireguardError>,
+    ) -> Result<Self, WireguardError> {
         let mut ret = Self::default();
-        for attr in attrs.0 {
+        for attr in &attrs.0 {
             match attr {
                 WireguardPeerAttribute::PublicKey(v) => {
                     ret.public_key = Some(BASE64_STANDARD.encode(v));
@@ -148,45 +337,44 @@ impl From<WireguardPeer> for WireguardPeerParsed {
                         ret.preshared_key = Some(BASE64_STANDARD.encode(v));
                     }
                 }
-                WireguardPeerAttribute::Endpoint(v) => ret.endpoint = Some(v),
+                WireguardPeerAttribute::Endpoint(v) => {
+                    ret.endpoint = Some(normalize_endpoint(*v))
+                }
                 WireguardPeerAttribute::PersistentKeepalive(v) => {
-                    ret.persistent_keepalive = Some(v)
+                    ret.persistent_keepalive = Some(*v)
                 }
                 WireguardPeerAttribute::LastHandshake(v) => {
-                    if v.seconds == 0 && v.nano_seconds == 0 {
-                        ret.last_handshake = None;
-                    } else if v.seconds >= 0
-                        && v.nano_seconds >= 0
-                        && (v.nano_seconds as u64) < (u32::MAX as u64)
-                    {
-                        ret.last_handshake = Some(Duration::new(
-                            v.seconds as u64,
-                            v.nano_seconds as u32,
-                        ));
-                    } else {
-                        log::warn!(
-                            "Ignoring invalid last handshake time: {v:?}"
-                        );
+                    match try_parse_last_handshake(v) {
+                        Ok(t) => ret.last_handshake = t,
+                        Err(e) => {
+                            on_invalid(invalid_peer_attr("LastHandshake", e))?
+                        }
                     }
                 }
-                WireguardPeerAttribute::RxBytes(v) => ret.rx_bytes = Some(v),
-                WireguardPeerAttribute::TxBytes(v) => ret.tx_bytes = Some(v),
+                WireguardPeerAttribute::RxBytes(v) => ret.rx_bytes = Some(*v),
+                WireguardPeerAttribute::TxBytes(v) => ret.tx_bytes = Some(*v),
                 WireguardPeerAttribute::ProtocolVersion(v) => {
-                    ret.protocol_version = Some(v)
+                    ret.protocol_version = Some(*v)
                 }
                 WireguardPeerAttribute::AllowedIps(wg_ips) => {
-                    let mut ips = Vec::new();
-                    for wg_ip in &wg_ips {
-                        match WireguardIpAddress::try_from(wg_ip) {
-                            Ok(i) => ips.push(i),
+                    let mut ips = Vec::with_capacity(wg_ips.len());
+                    for wg_ip in wg_ips {
+                        match WireguardIpAddress::parse(wg_ip) {
+                            Ok((i, warning)) => {
+                                if let Some(w) = warning {
+                                    on_invalid(invalid_peer_attr(
+                                        "AllowedIps",
+                                        w,
+                                    ))?;
+                                }
+                                ips.push(i);
+                            }
                             Err(e) => {
-                                log::warn!(
-                                    "Ignoring invalid WireguardAllowedIp: {e}"
-                                );
+                                on_invalid(invalid_peer_attr("AllowedIps", e))?
                             }
                         }
                     }
-                    ret.allowed_ips = Some(ips.into_iter().collect());
+                    ret.allowed_ips = Some(ips);
                 }
                 WireguardPeerAttribute::Flags(flag_bits) => {
                     let mut flags = Vec::new();
@@ -200,15 +388,70 @@ impl From<WireguardPeer> for WireguardPeerParsed {
                 }
             }
         }
-        ret
+        Ok(ret)
+    }
+}
+
+#[cfg(feature = "connection")]
+pub(crate) fn parse_last_handshake(v: &WireguardTimeSpec) -> Option<Duration> {
+    try_parse_last_handshake(v).unwrap_or_else(|e| {
+        log::warn!("Ignoring {}", e.msg);
+        None
+    })
+}
+
+fn try_parse_last_handshake(
+    v: &WireguardTimeSpec,
+) -> Result<Option<Duration>, WireguardError> {
+    if v.seconds == 0 && v.nano_seconds == 0 {
+        Ok(None)
+    } else if v.seconds >= 0
+        && v.nano_seconds >= 0
+        && (v.nano_seconds as u64) < (u32::MAX as u64)
+    {
+        Ok(Some(Duration::new(v.seconds as u64, v.nano_seconds as u32)))
+    } else {
+        Err(WireguardError::new(
+            ErrorKind::DecodeError,
+            format!("invalid last handshake time: {v:?}"),
+            None,
+        ))
     }
 }
 
+fn invalid_peer_attr(name: &str, e: WireguardError) -> WireguardError {
+    WireguardError::new(
+        ErrorKind::DecodeError,
+        format!("Invalid WireguardPeerAttribute::{name}: {}", e.msg),
+        None,
+    )
+}
+
 impl WireguardPeerParsed {
     pub fn build(&self) -> Result<WireguardPeer, WireguardError> {
-        let mut attrs: Vec<WireguardPeerAttribute> = Vec::new();
+        let mut peer = WireguardPeer(Vec::with_capacity(PEER_ATTR_MAX));
+        self.build_into(&mut peer)?;
+        Ok(peer)
+    }
+
+    /// Like [WireguardPeerParsed::build()] but overwrite `peer` in place,
+    /// reusing the buffers of its attributes and allowed IPs to reduce
+    /// allocations when building the same peers repeatedly.
+    /// On error, the content of `peer` is unspecified.
+    pub fn build_into(
+        &self,
+        peer: &mut WireguardPeer,
+    ) -> Result<(), WireguardError> {
+        let mut old_ips = Vec::new();
+        for attr in peer.0.iter_mut() {
+            if let WireguardPeerAttribute::AllowedIps(ips) = attr {
+                old_ips = std::mem::take(ips);
+            }
+        }
+        let attrs = &mut peer.0;
+        attrs.clear();
         if let Some(v) = self.endpoint {
-            attrs.push(WireguardPeerAttribute::Endpoint(v));
+            attrs.push(WireguardPeerAttribute::Endpoint(normalize_endpoint(v)));
         }
 
         if let Some(v) = self.public_key.as_deref() {
@@ -247,15 +490,22 @@ impl WireguardPeerParsed {
         }
 
         if let Some(ips) = self.allowed_ips.as_ref() {
-            attrs.push(WireguardPeerAttribute::AllowedIps(
-                ips.iter()
-                    .map(|ip| {
-                        WireguardAllowedIp(Vec::<WireguardAllowedIpAttr>::from(
-                            ip,
-                        ))
-                    })
-                    .collect(),
-            ));
+            old_ips.truncate(ips.len());
+            for (i, ip) in ips.iter().enumerate() {
+                ip.check_prefix_length(ErrorKind::InvalidConfig)?;
+                match old_ips.get_mut(i) {
+                    Some(old_ip) => {
+                        old_ip.0.clear();
+                        ip.write_attrs(&mut old_ip.0);
+                    }
+                    None => old_ips.push(WireguardAllowedIp(Vec::<
+                        WireguardAllowedIpAttr,
+                    >::from(
+                        ip
+                    ))),
+                }
+            }
+            attrs.push(WireguardPeerAttribute::AllowedIps(old_ips));
         }
 
         if let Some(v) = self.protocol_version {
@@ -268,7 +518,21 @@ impl WireguardPeerParsed {
             attrs.push(WireguardPeerAttribute::Flags(flag_bits));
         }
 
-        Ok(WireguardPeer(attrs))
+        if peer.buffer_len() > NLA_MAX_LEN {
+            return Err(WireguardError::new(
+                ErrorKind::TooLarge,
+                format!(
+                    "Peer {} requires {} bytes which exceeds the netlink \
+                     attribute limit {NLA_MAX_LEN}, please reduce the {} \
+                     allowed IPs of this peer",
+                    self.public_key.as_deref().unwrap_or("(no public key)"),
+                    peer.buffer_len(),
+                    self.allowed_ips.as_ref().map(Vec::len).unwrap_or_default(),
+                ),
+                None,
+            ));
+        }
+        Ok(())
     }
 }
 
@@ -310,19 +574,201 @@ pub struct WireguardIpAddress {
     pub flags: Option<Vec<WireguardParsedAllowedIpFlags>>,
 }
 
+impl WireguardIpAddress {
+    /// `0.0.0.0/0`
+    pub fn any_v4() -> Self {
+        Self {
+            prefix_length: 0,
+            ip_addr: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            flags: None,
+        }
+    }
+
+    /// `::/0`
+    pub fn any_v6() -> Self {
+        Self {
+            prefix_length: 0,
+            ip_addr: IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            flags: None,
+        }
+    }
+
+    /// Single address, `/32` for IPv4 or `/128` for IPv6.
+    pub fn host(ip: IpAddr) -> Self {
+        Self {
+            prefix_length: if ip.is_ipv4() { 32 } else { 128 },
+            ip_addr: ip,
+            flags: None,
+        }
+    }
+
+    /// Whether `ip` is covered by this prefix, always `false` for `ip` of
+    /// different address family.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        if self.ip_addr.is_ipv4() != ip.is_ipv4() {
+            return false;
+        }
+        let (start, end) = self.range();
+        let (addr, _) = ip_to_u128(ip);
+        addr >= start && addr <= end
+    }
+
+    /// Whether this is `0.0.0.0/0` or `::/0`
+    pub fn is_default_route(&self) -> bool {
+        self.prefix_length == 0
+    }
+
+    /// Zero the host bits beyond prefix length, e.g. `10.0.0.5/24` to
+    /// `10.0.0.0/24`. Kernel accepts allowed IPs with host bits set, but
+    /// they never compare equal to normalized ones. With `strict`, fail
+    /// with [ErrorKind::InvalidConfig] instead of modifying.
+    pub fn normalize(&mut self, strict: bool) -> Result<(), WireguardError> {
+        let network = u128_to_ip(self.range().0, self.ip_addr.is_ipv4());
+        if network != self.ip_addr {
+            if strict {
+                return Err(WireguardError::new(
+                    ErrorKind::InvalidConfig,
+                    format!(
+                        "Allowed IP {self} has host bits set, should be \
+                         {network}/{}",
+                        self.prefix_length
+                    ),
+                    None,
+                ));
+            }
+            self.ip_addr = network;
+        }
+        Ok(())
+    }
+
+    /// Whether both are the same network after masking host bits, e.g.
+    /// `10.0.0.1/24` and `10.0.0.0/24`. Flags are not compared.
+    pub fn is_same_network(&self, other: &Self) -> bool {
+        self.ip_addr.is_ipv4() == other.ip_addr.is_ipv4()
+            && self.prefix_length == other.prefix_length
+            && self.range().0 == other.range().0
+    }
+
+    // Return the first and last address covered, IPv4 address is stored in
+    // the lower 32 bits.
+    pub(crate) fn range(&self) -> (u128, u128) {
+        let (addr, bits) = ip_to_u128(self.ip_addr);
+        let host_bits = bits.saturating_sub(u32::from(self.prefix_length));
+        let host_mask = if host_bits >= 128 {
+            u128::MAX
+        } else {
+            (1u128 << host_bits) - 1
+        };
+        (addr & !host_mask, addr | host_mask)
+    }
+}
+
+// Return the address as u128 with the bit length of its family
+pub(crate) fn ip_to_u128(ip: IpAddr) -> (u128, u32) {
+    match ip {
+        IpAddr::V4(i) => (u128::from(u32::from(i)), 32),
+        IpAddr::V6(i) => (u128::from(i), 128),
+    }
+}
+
+/// Convert IPv4-mapped IPv6 endpoint (e.g. `[::ffff:192.0.2.1]:51820`) to
+/// plain IPv4 one, so the same endpoint always compares equal.
+pub(crate) fn normalize_endpoint(endpoint: SocketAddr) -> SocketAddr {
+    match endpoint {
+        SocketAddr::V6(addr) => match addr.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(IpAddr::V4(ip), addr.port()),
+            None => endpoint,
+        },
+        SocketAddr::V4(_) => endpoint,
+    }
+}
+
+pub(crate) fn u128_to_ip(value: u128, is_ipv4: bool) -> IpAddr {
+    if is_ipv4 {
+        IpAddr::V4(Ipv4Addr::from(value as u32))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(value))
+    }
+}
+
+impl std::fmt::Display for WireguardIpAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.ip_addr, self.prefix_length)
+    }
+}
+
+impl FromStr for WireguardIpAddress {
+    type Err = WireguardError;
+
+    /// Parse string like `10.0.0.0/8` or `fd00::1`, the prefix length is
+    /// treated as host route (32 for IPv4 and 128 for IPv6) if not defined.
+    fn from_str(s: &str) -> Result<Self, WireguardError> {
+        let s = s.trim();
+        let (ip_str, prefix_str) = match s.split_once('/') {
+            Some((ip, prefix)) => (ip, Some(prefix)),
+            None => (s, None),
+        };
+        let ip_addr = IpAddr::from_str(ip_str).map_err(|e| {
+            WireguardError::new(
+                ErrorKind::InvalidConfig,
+                format!("Invalid IP address {s}: {e}"),
+                None,
+            )
+        })?;
+        let max_prefix_length = if ip_addr.is_ipv4() { 32 } else { 128 };
+        let prefix_length = match prefix_str {
+            Some(p) => match u8::from_str(p) {
+                Ok(p) if p <= max_prefix_length => p,
+                _ => {
+                    return Err(WireguardError::new(
+                        ErrorKind::InvalidConfig,
+                        format!(
+                            "Invalid prefix length of {s}: should be in range \
+                             of 0 to {max_prefix_length}"
+                        ),
+                        None,
+                    ));
+                }
+            },
+            None => max_prefix_length,
+        };
+        Ok(Self {
+            ip_addr,
+            prefix_length,
+            flags: None,
+        })
+    }
+}
+
+/// Mismatched family attribute is logged as warning and ignored.
 impl TryFrom<&WireguardAllowedIp> for WireguardIpAddress {
     type Error = WireguardError;
 
     fn try_from(attrs: &WireguardAllowedIp) -> Result<Self, WireguardError> {
+        let (ret, warning) = Self::parse(attrs)?;
+        if let Some(w) = warning {
+            log::warn!("{}", w.msg);
+        }
+        Ok(ret)
+    }
+}
+
+impl WireguardIpAddress {
+    // Besides the parsed address, return the warning of inconsistent but
+    // usable attributes.
+    pub(crate) fn parse(
+        attrs: &WireguardAllowedIp,
+    ) -> Result<(Self, Option<WireguardError>), WireguardError> {
         let mut ip_addr: Option<IpAddr> = None;
         let mut prefix_length: Option<u8> = None;
+        let mut family: Option<WireguardAddressFamily> = None;
         let mut flags: Option<Vec<WireguardParsedAllowedIpFlags>> = None;
 
         for attr in &attrs.0 {
             match attr {
                 WireguardAllowedIpAttr::IpAddr(v) => ip_addr = Some(*v),
                 WireguardAllowedIpAttr::Cidr(v) => prefix_length = Some(*v),
-                WireguardAllowedIpAttr::Family(_) => (),
+                WireguardAllowedIpAttr::Family(v) => family = Some(*v),
                 WireguardAllowedIpAttr::Flags(flag_bits) => {
                     let mut flag_vec = Vec::new();
                     for flag_bit in flag_bits.iter() {
@@ -339,11 +785,31 @@ impl TryFrom<&WireguardAllowedIp> for WireguardIpAddress {
         }
         if let Some(ip_addr) = ip_addr {
             if let Some(prefix_length) = prefix_length {
-                Ok(Self {
+                // The address itself is trusted over the family attribute
+                let expected_family = if ip_addr.is_ipv4() {
+                    WireguardAddressFamily::Ipv4
+                } else {
+                    WireguardAddressFamily::Ipv6
+                };
+                let warning =
+                    family.filter(|f| *f != expected_family).map(|f| {
+                        WireguardError::new(
+                            ErrorKind::DecodeError,
+                            format!(
+                                "WireguardAllowedIp {ip_addr}/{prefix_length} \
+                                 has mismatched family {f:?}, treating it as \
+                                 {expected_family:?}"
+                            ),
+                            None,
+                        )
+                    });
+                let ret = Self {
                     ip_addr,
                     prefix_length,
                     flags,
-                })
+                };
+                ret.check_prefix_length(ErrorKind::DecodeError)?;
+                Ok((ret, warning))
             } else {
                 Err(WireguardError::new(
                     ErrorKind::DecodeError,
@@ -365,11 +831,49 @@ impl TryFrom<&WireguardAllowedIp> for WireguardIpAddress {
     }
 }
 
+/// Same attributes as [WireguardPeerParsed::build()] holds.
+impl TryFrom<&WireguardPeerParsed> for Vec<WireguardPeerAttribute> {
+    type Error = WireguardError;
+
+    fn try_from(peer: &WireguardPeerParsed) -> Result<Self, WireguardError> {
+        Ok(peer.build()?.0)
+    }
+}
+
 impl From<&WireguardIpAddress> for Vec<WireguardAllowedIpAttr> {
     fn from(ip: &WireguardIpAddress) -> Self {
-        let mut result = Vec::with_capacity(4);
-        result.push(WireguardAllowedIpAttr::Cidr(ip.prefix_length));
-        if ip.ip_addr.is_ipv4() {
+        let mut result = Vec::with_capacity(ALLOWED_IP_ATTR_MAX);
+        ip.write_attrs(&mut result);
+        result
+    }
+}
+
+impl WireguardIpAddress {
+    // Fail with `kind` if prefix length exceeds the bit length of address
+    // family.
+    fn check_prefix_length(
+        &self,
+        kind: ErrorKind,
+    ) -> Result<(), WireguardError> {
+        let (_, bits) = ip_to_u128(self.ip_addr);
+        if u32::from(self.prefix_length) > bits {
+            Err(WireguardError::new(
+                kind,
+                format!(
+                    "Invalid prefix length of {self}: should be in range of 0 \
+                     to {bits}"
+                ),
+                None,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Append the netlink attributes of this allowed IP to `result`
+    fn write_attrs(&self, result: &mut Vec<WireguardAllowedIpAttr>) {
+        result.push(WireguardAllowedIpAttr::Cidr(self.prefix_length));
+        if self.ip_addr.is_ipv4() {
             result.push(WireguardAllowedIpAttr::Family(
                 WireguardAddressFamily::Ipv4,
             ));
@@ -378,14 +882,123 @@ impl From<&WireguardIpAddress> for Vec<WireguardAllowedIpAttr> {
                 WireguardAddressFamily::Ipv6,
             ));
         }
-        result.push(WireguardAllowedIpAttr::IpAddr(ip.ip_addr));
-        if let Some(flags) = ip.flags.as_ref() {
+        result.push(WireguardAllowedIpAttr::IpAddr(self.ip_addr));
+        if let Some(flags) = self.flags.as_ref() {
             let flag_bits = flags
                 .iter()
                 .map(|&f| WireguardAllowedIpFlags::from(f))
                 .collect();
             result.push(WireguardAllowedIpAttr::Flags(flag_bits));
         }
-        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn net(s: &str) -> WireguardIpAddress {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn contains_default_route() {
+        assert!(net("0.0.0.0/0").contains(ip("0.0.0.0")));
+        assert!(net("0.0.0.0/0").contains(ip("255.255.255.255")));
+        assert!(net("::/0").contains(ip("::")));
+        assert!(
+            net("::/0").contains(ip("ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff"))
+        );
+        // Host bits of default route are ignored
+        assert!(net("10.0.0.1/0").contains(ip("192.0.2.1")));
+        assert!(net("0.0.0.0/0").is_default_route());
+        assert!(net("::/0").is_default_route());
+        assert!(!net("0.0.0.0/1").is_default_route());
+    }
+
+    #[test]
+    fn contains_host_route() {
+        assert!(net("192.0.2.1/32").contains(ip("192.0.2.1")));
+        assert!(!net("192.0.2.1/32").contains(ip("192.0.2.0")));
+        assert!(!net("192.0.2.1/32").contains(ip("192.0.2.2")));
+        assert!(net("fd00::1/128").contains(ip("fd00::1")));
+        assert!(!net("fd00::1/128").contains(ip("fd00::2")));
+        assert!(!net("fd00::1/128").contains(ip("fd00::")));
+    }
+
+    #[test]
+    fn contains_prefix() {
+        let v4 = net("10.1.0.0/16");
+        assert!(v4.contains(ip("10.1.0.0")));
+        assert!(v4.contains(ip("10.1.255.255")));
+        assert!(!v4.contains(ip("10.0.255.255")));
+        assert!(!v4.contains(ip("10.2.0.0")));
+        let v6 = net("fd00:1::/32");
+        assert!(v6.contains(ip("fd00:1::")));
+        assert!(v6.contains(ip("fd00:1:ffff:ffff:ffff:ffff:ffff:ffff")));
+        assert!(!v6.contains(ip("fd00:2::")));
+    }
+
+    #[test]
+    fn contains_family_mismatch() {
+        assert!(!net("0.0.0.0/0").contains(ip("::")));
+        assert!(!net("::/0").contains(ip("0.0.0.0")));
+        // Same numeric value in different family
+        assert!(!net("::a00:1/128").contains(ip("10.0.0.1")));
+        assert!(!net("10.0.0.1/32").contains(ip("::a00:1")));
+        assert!(!net("::ffff:0:0/96").contains(ip("10.0.0.1")));
+    }
+
+    fn allowed_ip(
+        ip: &str,
+        prefix_length: u8,
+        family: WireguardAddressFamily,
+    ) -> WireguardAllowedIp {
+        WireguardAllowedIp(vec![
+            WireguardAllowedIpAttr::Family(family),
+            WireguardAllowedIpAttr::IpAddr(ip.parse().unwrap()),
+            WireguardAllowedIpAttr::Cidr(prefix_length),
+        ])
+    }
+
+    #[test]
+    fn parse_family_mismatch_reported_as_warning() {
+        let attrs =
+            WireguardPeer(vec![WireguardPeerAttribute::AllowedIps(vec![
+                allowed_ip("10.0.0.0", 8, WireguardAddressFamily::Ipv6),
+                allowed_ip("fd00::", 64, WireguardAddressFamily::Ipv6),
+            ])]);
+        let expected = Some(vec![net("10.0.0.0/8"), net("fd00::/64")]);
+
+        let (peer, warnings) = WireguardPeerParsed::parse_with_warnings(&attrs);
+        assert_eq!(peer.allowed_ips, expected);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, ErrorKind::DecodeError);
+        assert_eq!(
+            warnings[0].msg,
+            "Invalid WireguardPeerAttribute::AllowedIps: WireguardAllowedIp \
+             10.0.0.0/8 has mismatched family Ipv6, treating it as Ipv4"
+        );
+
+        assert_eq!(WireguardPeerParsed::from(&attrs).allowed_ips, expected);
+        assert_eq!(
+            WireguardPeerParsed::try_parse(&attrs).unwrap_err().msg,
+            warnings[0].msg
+        );
+    }
+
+    #[test]
+    fn parse_allowed_ip_without_family() {
+        let attrs = WireguardAllowedIp(vec![
+            WireguardAllowedIpAttr::IpAddr("fd00::1".parse().unwrap()),
+            WireguardAllowedIpAttr::Cidr(128),
+        ]);
+        let (ip, warning) = WireguardIpAddress::parse(&attrs).unwrap();
+        assert_eq!(ip, net("fd00::1/128"));
+        assert!(warning.is_none());
     }
 }
@@ -0,0 +1,266 @@
+// SPDX-License-Identifier: MIT
+
+#[cfg(feature = "exec")]
+use crate::cmd::run_cmd;
+use crate::{
+    ErrorKind, WireguardError, WireguardQuickConfig, WireguardQuickTable,
+};
+
+// Default table and fwmark used by wg-quick
+const DEFAULT_TABLE: u32 = 51820;
+
+/// Policy routing used by wg-quick for routing all traffic (`0.0.0.0/0` or
+/// `::/0`) through wireguard interface without looping the encrypted
+/// packets:
+///  * Default route via wireguard interface in dedicated routing table.
+///  * Rule routing packets without fwmark to dedicated routing table.
+///  * Rule ignoring default route of main table.
+///
+/// The fwmark returned by [WireguardPolicyRouting::fwmark()] should be set
+/// to [crate::WireguardParsed::fwmark] so that the encrypted packets skip
+/// the dedicated table.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct WireguardPolicyRouting {
+    pub iface_name: String,
+    /// Routing table ID which is also the fwmark
+    pub table: u32,
+    pub ipv4: bool,
+    pub ipv6: bool,
+}
+
+impl WireguardPolicyRouting {
+    /// Return `None` if there is no default route in allowed IPs or the
+    /// `Table` of configuration is not [WireguardQuickTable::Auto].
+    /// The existing non-zero fwmark of configuration is used as table ID,
+    /// otherwise 51820.
+    pub fn new(
+        config: &WireguardQuickConfig,
+    ) -> Result<Option<Self>, WireguardError> {
+        if config.table.as_ref().unwrap_or(&WireguardQuickTable::Auto)
+            != &WireguardQuickTable::Auto
+        {
+            return Ok(None);
+        }
+        let iface_name =
+            config.wireguard.iface_name.as_deref().ok_or_else(|| {
+                WireguardError::new(
+                    ErrorKind::InvalidConfig,
+                    "Cannot setup policy routing without iface_name"
+                        .to_string(),
+                    None,
+                )
+            })?;
+        let default_routes: Vec<_> = config
+            .routes()
+            .into_iter()
+            .filter(|r| r.is_default_route())
+            .collect();
+        if default_routes.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            iface_name: iface_name.to_string(),
+            table: config
+                .wireguard
+                .fwmark
+                .filter(|m| *m != 0)
+                .unwrap_or(DEFAULT_TABLE),
+            ipv4: default_routes.iter().any(|r| r.ip_addr.is_ipv4()),
+            ipv6: default_routes.iter().any(|r| r.ip_addr.is_ipv6()),
+        }))
+    }
+
+    pub fn fwmark(&self) -> u32 {
+        self.table
+    }
+
+    /// Add default route and routing rules via `ip` command, also enable
+    /// `net.ipv4.conf.all.src_valid_mark` like `wg-quick` for IPv4.
+    /// Rules left by previous setup are deleted first, so calling it again
+    /// does not duplicate them.
+    #[cfg(feature = "exec")]
+    pub fn setup(&self) -> Result<(), WireguardError> {
+        self.delete_rules();
+        for args in self.setup_cmds() {
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            run_cmd(args[0], &args[1..], None)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the routing rules added by [WireguardPolicyRouting::setup()].
+    /// The routes are removed by kernel along with the interface.
+    #[cfg(feature = "exec")]
+    pub fn teardown(&self) -> Result<(), WireguardError> {
+        self.delete_rules();
+        Ok(())
+    }
+
+    // `ip rule delete` removes one matching rule per invocation and fails
+    // once nothing matches.
+    #[cfg(feature = "exec")]
+    fn delete_rules(&self) {
+        for args in self.rule_delete_cmds() {
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            while run_cmd(args[0], &args[1..], None).is_ok() {}
+        }
+    }
+
+    /// Commands (program followed by arguments) deleting one rule added by
+    /// [WireguardPolicyRouting::setup_cmds()], to be repeated until failure.
+    pub(crate) fn rule_delete_cmds(&self) -> Vec<Vec<String>> {
+        let table = self.table.to_string();
+        let mut ret = Vec::new();
+        for (family, _) in self.families() {
+            ret.push(args(&["ip", family, "rule", "delete", "table", &table]));
+            ret.push(args(&[
+                "ip",
+                family,
+                "rule",
+                "delete",
+                "table",
+                "main",
+                "suppress_prefixlength",
+                "0",
+            ]));
+        }
+        ret
+    }
+
+    /// Commands (program followed by arguments) adding the default routes
+    /// and routing rules.
+    pub(crate) fn setup_cmds(&self) -> Vec<Vec<String>> {
+        let table = self.table.to_string();
+        let mut ret = Vec::new();
+        for (family, dst) in self.families() {
+            ret.push(args(&[
+                "ip",
+                family,
+                "route",
+                "replace",
+                dst,
+                "dev",
+                &self.iface_name,
+                "table",
+                &table,
+            ]));
+            ret.push(args(&[
+                "ip", family, "rule", "add", "not", "fwmark", &table, "table",
+                &table,
+            ]));
+            ret.push(args(&[
+                "ip",
+                family,
+                "rule",
+                "add",
+                "table",
+                "main",
+                "suppress_prefixlength",
+                "0",
+            ]));
+            if family == "-4" {
+                // Allow reverse path filter to pass the reply of encrypted
+                // packets carrying the fwmark
+                ret.push(args(&[
+                    "sysctl",
+                    "-q",
+                    "net.ipv4.conf.all.src_valid_mark=1",
+                ]));
+            }
+        }
+        ret
+    }
+
+    fn families(&self) -> Vec<(&'static str, &'static str)> {
+        let mut ret = Vec::new();
+        if self.ipv4 {
+            ret.push(("-4", "0.0.0.0/0"));
+        }
+        if self.ipv6 {
+            ret.push(("-6", "::/0"));
+        }
+        ret
+    }
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(ipv4: bool, ipv6: bool) -> WireguardPolicyRouting {
+        WireguardPolicyRouting {
+            iface_name: "wg0".to_string(),
+            table: DEFAULT_TABLE,
+            ipv4,
+            ipv6,
+        }
+    }
+
+    fn lines(cmds: Vec<Vec<String>>) -> Vec<String> {
+        cmds.into_iter().map(|c| c.join(" ")).collect()
+    }
+
+    #[test]
+    fn setup_cmds() {
+        assert_eq!(
+            lines(policy(true, true).setup_cmds()),
+            [
+                "ip -4 route replace 0.0.0.0/0 dev wg0 table 51820",
+                "ip -4 rule add not fwmark 51820 table 51820",
+                "ip -4 rule add table main suppress_prefixlength 0",
+                "sysctl -q net.ipv4.conf.all.src_valid_mark=1",
+                "ip -6 route replace ::/0 dev wg0 table 51820",
+                "ip -6 rule add not fwmark 51820 table 51820",
+                "ip -6 rule add table main suppress_prefixlength 0",
+            ]
+        );
+        assert_eq!(
+            lines(policy(false, true).setup_cmds()),
+            [
+                "ip -6 route replace ::/0 dev wg0 table 51820",
+                "ip -6 rule add not fwmark 51820 table 51820",
+                "ip -6 rule add table main suppress_prefixlength 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn rule_delete_cmds() {
+        assert_eq!(
+            lines(policy(true, false).rule_delete_cmds()),
+            [
+                "ip -4 rule delete table 51820",
+                "ip -4 rule delete table main suppress_prefixlength 0",
+            ]
+        );
+        assert_eq!(lines(policy(true, true).rule_delete_cmds()).len(), 4);
+        assert!(policy(false, false).rule_delete_cmds().is_empty());
+    }
+
+    #[test]
+    fn new_from_config() {
+        let config: WireguardQuickConfig =
+            "[Interface]\nFwMark = 0x1234\n[Peer]\nAllowedIPs = ::/0\n"
+                .parse()
+                .unwrap();
+        // iface_name is mandatory
+        assert_eq!(
+            WireguardPolicyRouting::new(&config).unwrap_err().kind,
+            ErrorKind::InvalidConfig
+        );
+        let mut config = config;
+        config.wireguard.iface_name = Some("wg0".to_string());
+        let policy = WireguardPolicyRouting::new(&config).unwrap().unwrap();
+        assert_eq!(policy.table, 0x1234);
+        assert!(!policy.ipv4);
+        assert!(policy.ipv6);
+
+        config.table = Some(WireguardQuickTable::Off);
+        assert_eq!(WireguardPolicyRouting::new(&config).unwrap(), None);
+    }
+}
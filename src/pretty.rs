@@ -0,0 +1,307 @@
+// SPDX-License-Identifier: MIT
+
+use std::fmt::Write;
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use netlink_packet_core::{NetlinkMessage, NetlinkPayload};
+use netlink_packet_generic::GenlMessage;
+use netlink_packet_wireguard::{
+    WireguardAllowedIpAttr, WireguardAttribute, WireguardMessage,
+    WireguardPeerAttribute,
+};
+
+use crate::error::errno_to_string;
+
+const REDACTED: &str = "<redacted>";
+const INDENT: &str = "    ";
+
+/// Render wireguard message as indented attribute tree, private and
+/// preshared keys are redacted.
+pub fn pretty_wireguard_message(msg: &WireguardMessage) -> String {
+    let mut ret = String::new();
+    write_wireguard_message(&mut ret, msg, 0);
+    ret
+}
+
+/// Render netlink header and the wireguard message (or the netlink error)
+/// it holds as indented tree, private and preshared keys are redacted.
+pub fn pretty_netlink_message(
+    nl_msg: &NetlinkMessage<GenlMessage<WireguardMessage>>,
+) -> String {
+    let mut ret = String::new();
+    let header = &nl_msg.header;
+    writeln!(ret, "NetlinkHeader").ok();
+    writeln!(ret, "{INDENT}length: {}", header.length).ok();
+    writeln!(ret, "{INDENT}message_type: {}", header.message_type).ok();
+    writeln!(ret, "{INDENT}flags: {:#x}", header.flags).ok();
+    writeln!(ret, "{INDENT}sequence_number: {}", header.sequence_number).ok();
+    writeln!(ret, "{INDENT}port_number: {}", header.port_number).ok();
+    match &nl_msg.payload {
+        NetlinkPayload::InnerMessage(genl_msg) => {
+            writeln!(
+                ret,
+                "GenlHeader\n{INDENT}cmd: {}\n{INDENT}version: {}",
+                genl_msg.header.cmd, genl_msg.header.version
+            )
+            .ok();
+            write_wireguard_message(&mut ret, &genl_msg.payload, 0);
+        }
+        NetlinkPayload::Error(e) => {
+            let code = e.code.map(|c| c.get()).unwrap_or_default();
+            writeln!(ret, "Error\n{INDENT}code: {code}").ok();
+            if code != 0 {
+                writeln!(ret, "{INDENT}errno: {}", errno_to_string(code)).ok();
+            }
+        }
+        NetlinkPayload::Done(_) => {
+            writeln!(ret, "Done").ok();
+        }
+        payload => {
+            writeln!(ret, "{payload:?}").ok();
+        }
+    }
+    ret
+}
+
+fn write_wireguard_message(
+    out: &mut String,
+    msg: &WireguardMessage,
+    depth: usize,
+) {
+    line(out, depth, &format!("{:?}", msg.cmd));
+    for attr in &msg.attributes {
+        let depth = depth + 1;
+        match attr {
+            WireguardAttribute::IfIndex(v) => {
+                line(out, depth, &format!("IfIndex: {v}"))
+            }
+            WireguardAttribute::IfName(v) => {
+                line(out, depth, &format!("IfName: {v}"))
+            }
+            WireguardAttribute::PrivateKey(v) => {
+                line(out, depth, &format!("PrivateKey: {}", secret(v)))
+            }
+            WireguardAttribute::PublicKey(v) => line(
+                out,
+                depth,
+                &format!("PublicKey: {}", BASE64_STANDARD.encode(v)),
+            ),
+            WireguardAttribute::ListenPort(v) => {
+                line(out, depth, &format!("ListenPort: {v}"))
+            }
+            WireguardAttribute::Fwmark(v) => {
+                line(out, depth, &format!("Fwmark: {v:#x}"))
+            }
+            WireguardAttribute::Flags(v) => {
+                line(out, depth, &format!("Flags: {v:?}"))
+            }
+            WireguardAttribute::Peers(peers) => {
+                line(out, depth, "Peers");
+                for peer in peers {
+                    line(out, depth + 1, "Peer");
+                    for peer_attr in &peer.0 {
+                        write_peer_attribute(out, peer_attr, depth + 2);
+                    }
+                }
+            }
+            other => line(out, depth, &format!("{other:?}")),
+        }
+    }
+}
+
+fn write_peer_attribute(
+    out: &mut String,
+    attr: &WireguardPeerAttribute,
+    depth: usize,
+) {
+    match attr {
+        WireguardPeerAttribute::PublicKey(v) => line(
+            out,
+            depth,
+            &format!("PublicKey: {}", BASE64_STANDARD.encode(v)),
+        ),
+        WireguardPeerAttribute::PresharedKey(v) => {
+            line(out, depth, &format!("PresharedKey: {}", secret(v)))
+        }
+        WireguardPeerAttribute::Endpoint(v) => {
+            line(out, depth, &format!("Endpoint: {v}"))
+        }
+        WireguardPeerAttribute::PersistentKeepalive(v) => {
+            line(out, depth, &format!("PersistentKeepalive: {v}"))
+        }
+        WireguardPeerAttribute::LastHandshake(v) => line(
+            out,
+            depth,
+            &format!("LastHandshake: {}.{:09}", v.seconds, v.nano_seconds),
+        ),
+        WireguardPeerAttribute::RxBytes(v) => {
+            line(out, depth, &format!("RxBytes: {v}"))
+        }
+        WireguardPeerAttribute::TxBytes(v) => {
+            line(out, depth, &format!("TxBytes: {v}"))
+        }
+        WireguardPeerAttribute::ProtocolVersion(v) => {
+            line(out, depth, &format!("ProtocolVersion: {v}"))
+        }
+        WireguardPeerAttribute::Flags(v) => {
+            line(out, depth, &format!("Flags: {v:?}"))
+        }
+        WireguardPeerAttribute::AllowedIps(ips) => {
+            line(out, depth, "AllowedIps");
+            for ip in ips {
+                line(out, depth + 1, "AllowedIp");
+                for ip_attr in &ip.0 {
+                    let depth = depth + 2;
+                    match ip_attr {
+                        WireguardAllowedIpAttr::Family(v) => {
+                            line(out, depth, &format!("Family: {v:?}"))
+                        }
+                        WireguardAllowedIpAttr::IpAddr(v) => {
+                            line(out, depth, &format!("IpAddr: {v}"))
+                        }
+                        WireguardAllowedIpAttr::Cidr(v) => {
+                            line(out, depth, &format!("Cidr: {v}"))
+                        }
+                        WireguardAllowedIpAttr::Flags(v) => {
+                            line(out, depth, &format!("Flags: {v:?}"))
+                        }
+                        other => line(out, depth, &format!("{other:?}")),
+                    }
+                }
+            }
+        }
+        other => line(out, depth, &format!("{other:?}")),
+    }
+}
+
+// Redacted unless the `debug_show_secrets` feature is enabled
+fn secret(key: &[u8]) -> String {
+    if cfg!(feature = "debug_show_secrets") {
+        BASE64_STANDARD.encode(key)
+    } else {
+        REDACTED.to_string()
+    }
+}
+
+fn line(out: &mut String, depth: usize, content: &str) {
+    writeln!(out, "{}{content}", INDENT.repeat(depth)).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use netlink_packet_core::{ErrorMessage, NetlinkHeader};
+    use netlink_packet_wireguard::WireguardCmd;
+
+    use super::*;
+    use crate::{WireguardParsed, WireguardPeerParsed};
+
+    const KEY1: &str = "uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM=";
+    const KEY2: &str = "WEAuaVuhdyscyTCXVfBDJR6nf9zxD75jmJzrfhkyE3Y=";
+
+    fn message() -> WireguardMessage {
+        WireguardParsed {
+            private_key: Some(KEY1.to_string()),
+            listen_port: Some(51820),
+            fwmark: Some(0xca6c),
+            ..WireguardParsed::new("wg0")
+        }
+        .with_peers([WireguardPeerParsed {
+            preshared_key: Some(KEY1.to_string()),
+            endpoint: Some("192.0.2.1:51820".parse().unwrap()),
+            persistent_keepalive: Some(25),
+            allowed_ips: Some(vec!["10.0.0.2/32".parse().unwrap()]),
+            ..WireguardPeerParsed::new(KEY2)
+        }])
+        .build(WireguardCmd::SetDevice)
+        .unwrap()
+    }
+
+    // Expected lines with `<KEY>` replaced by the secret rendering
+    fn expected(lines: &[&str]) -> String {
+        let secret = if cfg!(feature = "debug_show_secrets") {
+            KEY1
+        } else {
+            REDACTED
+        };
+        let mut ret = lines.join("\n").replace("<KEY>", secret);
+        ret.push('\n');
+        ret
+    }
+
+    const MESSAGE_LINES: [&str; 16] = [
+        "SetDevice",
+        "    IfName: wg0",
+        "    PrivateKey: <KEY>",
+        "    ListenPort: 51820",
+        "    Fwmark: 0xca6c",
+        "    Peers",
+        "        Peer",
+        "            Endpoint: 192.0.2.1:51820",
+        "            PublicKey: WEAuaVuhdyscyTCXVfBDJR6nf9zxD75jmJzrfhkyE3Y=",
+        "            PresharedKey: <KEY>",
+        "            PersistentKeepalive: 25",
+        "            AllowedIps",
+        "                AllowedIp",
+        "                    Cidr: 32",
+        "                    Family: Ipv4",
+        "                    IpAddr: 10.0.0.2",
+    ];
+
+    #[test]
+    fn wireguard_message() {
+        let out = pretty_wireguard_message(&message());
+        assert_eq!(out, expected(&MESSAGE_LINES));
+        if !cfg!(feature = "debug_show_secrets") {
+            assert!(!out.contains(KEY1));
+        }
+    }
+
+    #[test]
+    fn netlink_message() {
+        let mut nl_msg = NetlinkMessage::new(
+            NetlinkHeader::default(),
+            NetlinkPayload::InnerMessage(GenlMessage::from_payload(message())),
+        );
+        nl_msg.finalize();
+        let header = [
+            "NetlinkHeader",
+            "    length: 220",
+            "    message_type: 0",
+            "    flags: 0x0",
+            "    sequence_number: 0",
+            "    port_number: 0",
+            "GenlHeader",
+            "    cmd: 1",
+            "    version: 1",
+        ];
+        assert_eq!(
+            pretty_netlink_message(&nl_msg),
+            expected(&[&header[..], &MESSAGE_LINES[..]].concat())
+        );
+    }
+
+    #[test]
+    fn netlink_error() {
+        let mut err = ErrorMessage::default();
+        err.code = std::num::NonZeroI32::new(-19);
+        let nl_msg: NetlinkMessage<GenlMessage<WireguardMessage>> =
+            NetlinkMessage::new(
+                NetlinkHeader::default(),
+                NetlinkPayload::Error(err),
+            );
+        assert_eq!(
+            pretty_netlink_message(&nl_msg),
+            expected(&[
+                "NetlinkHeader",
+                "    length: 0",
+                "    message_type: 0",
+                "    flags: 0x0",
+                "    sequence_number: 0",
+                "    port_number: 0",
+                "Error",
+                "    code: -19",
+                "    errno: ENODEV (no such device)",
+            ])
+        );
+    }
+}
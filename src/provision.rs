@@ -0,0 +1,217 @@
+// SPDX-License-Identifier: MIT
+
+use std::net::{IpAddr, SocketAddr};
+
+use crate::{
+    parsed::decode_key, ErrorKind, WireguardError, WireguardIpAddress,
+    WireguardParsed, WireguardPeerParsed, WireguardQuickConfig,
+};
+
+/// Generate the peer to add to server and the configuration of new client.
+///
+/// * `server`: Configuration of server, `public_key` is mandatory.
+/// * `server_endpoint`: Address of server reachable by client.
+/// * `client_private_key`, `client_public_key`: Key pair of new client.
+/// * `client_address`: IP address with prefix length assigned to client, server
+///   will route only this address to client.
+/// * `client_allowed_ips`: Destinations client should send to server, e.g.
+///   `0.0.0.0/0` for routing all traffic via server.
+pub fn provision_client(
+    server: &WireguardParsed,
+    server_endpoint: SocketAddr,
+    client_private_key: &str,
+    client_public_key: &str,
+    client_address: WireguardIpAddress,
+    client_allowed_ips: Vec<WireguardIpAddress>,
+) -> Result<(WireguardPeerParsed, WireguardQuickConfig), WireguardError> {
+    let server_public_key = server.public_key.as_deref().ok_or_else(|| {
+        WireguardError::new(
+            ErrorKind::InvalidConfig,
+            "Server configuration has no public key".to_string(),
+            None,
+        )
+    })?;
+    decode_key("client_private_key", client_private_key)?;
+    decode_key("client_public_key", client_public_key)?;
+
+    for peer in server.peers.as_deref().unwrap_or_default() {
+        if peer.public_key.as_deref() == Some(client_public_key) {
+            return Err(WireguardError::new(
+                ErrorKind::InvalidConfig,
+                format!(
+                    "Client public key {client_public_key} is already used by \
+                     server peer"
+                ),
+                None,
+            ));
+        }
+        if peer
+            .allowed_ips
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .any(|i| i.ip_addr == client_address.ip_addr)
+        {
+            return Err(WireguardError::new(
+                ErrorKind::InvalidConfig,
+                format!(
+                    "Client address {} is already used by server peer {}",
+                    client_address.ip_addr,
+                    peer.public_key.as_deref().unwrap_or_default()
+                ),
+                None,
+            ));
+        }
+    }
+
+    let server_peer = WireguardPeerParsed {
+        public_key: Some(client_public_key.to_string()),
+        allowed_ips: Some(vec![WireguardIpAddress {
+            ip_addr: client_address.ip_addr,
+            prefix_length: match client_address.ip_addr {
+                IpAddr::V4(_) => 32,
+                IpAddr::V6(_) => 128,
+            },
+            flags: None,
+        }]),
+        ..Default::default()
+    };
+
+    let mut client_config = WireguardQuickConfig::from(WireguardParsed {
+        private_key: Some(client_private_key.to_string()),
+        peers: Some(vec![WireguardPeerParsed {
+            public_key: Some(server_public_key.to_string()),
+            endpoint: Some(server_endpoint),
+            allowed_ips: Some(client_allowed_ips),
+            ..Default::default()
+        }]),
+        ..Default::default()
+    });
+    client_config.addresses = Some(vec![client_address]);
+
+    Ok((server_peer, client_config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER_KEY: &str = "uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM=";
+    const PEER_KEY: &str = "WEAuaVuhdyscyTCXVfBDJR6nf9zxD75jmJzrfhkyE3Y=";
+    const CLIENT_PRIVATE: &str = "dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=";
+    const CLIENT_PUBLIC: &str = "hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=";
+
+    fn server() -> WireguardParsed {
+        WireguardParsed {
+            public_key: Some(SERVER_KEY.to_string()),
+            ..WireguardParsed::new("wg0")
+        }
+        .with_peers([WireguardPeerParsed {
+            allowed_ips: Some(vec!["10.0.0.2/32".parse().unwrap()]),
+            ..WireguardPeerParsed::new(PEER_KEY)
+        }])
+    }
+
+    fn provision(
+        server: &WireguardParsed,
+        private_key: &str,
+        public_key: &str,
+        address: &str,
+    ) -> Result<(WireguardPeerParsed, WireguardQuickConfig), WireguardError>
+    {
+        provision_client(
+            server,
+            "192.0.2.1:51820".parse().unwrap(),
+            private_key,
+            public_key,
+            address.parse().unwrap(),
+            vec!["0.0.0.0/0".parse().unwrap(), "::/0".parse().unwrap()],
+        )
+    }
+
+    #[test]
+    fn client_config() {
+        let (server_peer, client_config) =
+            provision(&server(), CLIENT_PRIVATE, CLIENT_PUBLIC, "10.0.0.3/24")
+                .unwrap();
+        assert_eq!(
+            server_peer,
+            WireguardPeerParsed {
+                allowed_ips: Some(vec!["10.0.0.3/32".parse().unwrap()]),
+                ..WireguardPeerParsed::new(CLIENT_PUBLIC)
+            }
+        );
+        assert_eq!(
+            client_config.client_config_string().unwrap(),
+            [
+                "[Interface]",
+                "Address = 10.0.0.3/24",
+                &format!("PrivateKey = {CLIENT_PRIVATE}"),
+                "",
+                "[Peer]",
+                &format!("PublicKey = {SERVER_KEY}"),
+                "AllowedIPs = 0.0.0.0/0, ::/0",
+                "Endpoint = 192.0.2.1:51820",
+                "",
+            ]
+            .join("\n")
+        );
+
+        // Server only routes the address itself to client
+        let (server_peer, _) =
+            provision(&server(), CLIENT_PRIVATE, CLIENT_PUBLIC, "fd00::3/64")
+                .unwrap();
+        assert_eq!(
+            server_peer.allowed_ips,
+            Some(vec!["fd00::3/128".parse().unwrap()])
+        );
+    }
+
+    #[test]
+    fn invalid_input() {
+        let cases = [
+            // Server without public key
+            (
+                WireguardParsed::new("wg0"),
+                CLIENT_PRIVATE,
+                CLIENT_PUBLIC,
+                "10.0.0.3/24",
+                ErrorKind::InvalidConfig,
+            ),
+            (
+                server(),
+                "not a key",
+                CLIENT_PUBLIC,
+                "10.0.0.3/24",
+                ErrorKind::InvalidKey,
+            ),
+            (
+                server(),
+                CLIENT_PRIVATE,
+                &CLIENT_PUBLIC[..40],
+                "10.0.0.3/24",
+                ErrorKind::InvalidKey,
+            ),
+            // Public key or address used by existing peer
+            (
+                server(),
+                CLIENT_PRIVATE,
+                PEER_KEY,
+                "10.0.0.3/24",
+                ErrorKind::InvalidConfig,
+            ),
+            (
+                server(),
+                CLIENT_PRIVATE,
+                CLIENT_PUBLIC,
+                "10.0.0.2/24",
+                ErrorKind::InvalidConfig,
+            ),
+        ];
+        for (server, private_key, public_key, address, kind) in cases {
+            let e = provision(&server, private_key, public_key, address)
+                .unwrap_err();
+            assert_eq!(e.kind, kind, "{e}");
+        }
+    }
+}
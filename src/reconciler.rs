@@ -0,0 +1,243 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+#[cfg(any(feature = "tokio_socket", feature = "smol_socket"))]
+use crate::runtime::{sleep, with_jitter};
+use crate::{
+    diff::correction, key_fingerprint, ErrorKind, WireguardError,
+    WireguardHandle, WireguardParsed, WireguardPeerParsed,
+};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+const DEFAULT_FAILOVER_WINDOW: Duration =
+    WireguardPeerParsed::REJECT_AFTER_TIME;
+
+type DesiredSource = Box<dyn FnMut() -> WireguardParsed + Send>;
+type DriftCallback = Box<dyn FnMut(&WireguardParsed, &WireguardParsed) + Send>;
+
+/// Keep the kernel wireguard configuration matching the desired state.
+///
+/// Properties set to `None` in desired [WireguardParsed] are not managed.
+/// When `peers` is set, peers not listed will be removed from kernel.
+/// Only the changed peers are sent to kernel, hence established sessions of
+/// other peers are not interrupted.
+pub struct WireguardReconciler {
+    handle: WireguardHandle,
+    source: DesiredSource,
+    interval: Duration,
+    retry_delay: Duration,
+    max_retry_delay: Duration,
+    on_drift: Option<DriftCallback>,
+    failovers: HashMap<String, EndpointFailover>,
+    failover_window: Duration,
+}
+
+#[derive(Debug)]
+struct EndpointFailover {
+    candidates: Vec<SocketAddr>,
+    index: usize,
+    // When current candidate was chosen
+    since: Option<Instant>,
+}
+
+impl std::fmt::Debug for WireguardReconciler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WireguardReconciler")
+            .field("handle", &self.handle)
+            .field("interval", &self.interval)
+            .field("retry_delay", &self.retry_delay)
+            .field("max_retry_delay", &self.max_retry_delay)
+            .field("failovers", &self.failovers)
+            .field("failover_window", &self.failover_window)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WireguardReconciler {
+    /// Reconcile against fixed desired state. The `iface_name` of desired
+    /// state is mandatory.
+    pub fn new(handle: WireguardHandle, desired: WireguardParsed) -> Self {
+        Self::with_source(handle, move || desired.clone())
+    }
+
+    /// Reconcile against the desired state returned by `source` which is
+    /// invoked before every reconcile.
+    pub fn with_source<F>(handle: WireguardHandle, source: F) -> Self
+    where
+        F: FnMut() -> WireguardParsed + Send + 'static,
+    {
+        Self {
+            handle,
+            source: Box::new(source),
+            interval: DEFAULT_INTERVAL,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            max_retry_delay: DEFAULT_MAX_RETRY_DELAY,
+            on_drift: None,
+            failovers: HashMap::new(),
+            failover_window: DEFAULT_FAILOVER_WINDOW,
+        }
+    }
+
+    /// Interval between two successful reconciles, default is 10 seconds.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Delay before first retry on failure, doubled on every continuous
+    /// failure up to `max_retry_delay`. Default is 1 second and 60 seconds.
+    pub fn retry_delay(
+        mut self,
+        retry_delay: Duration,
+        max_retry_delay: Duration,
+    ) -> Self {
+        self.retry_delay = retry_delay;
+        self.max_retry_delay = max_retry_delay;
+        self
+    }
+
+    /// Invoke `callback` with current kernel state and the correction to
+    /// apply whenever drift is detected.
+    pub fn on_drift<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&WireguardParsed, &WireguardParsed) + Send + 'static,
+    {
+        self.on_drift = Some(Box::new(callback));
+        self
+    }
+
+    /// Use the first endpoint in `candidates` for peer of specified public
+    /// key, and switch to the next one (wrapping around) when no handshake
+    /// succeeded within the failover window. The endpoint of this peer in
+    /// desired state is overridden.
+    ///
+    /// Handshake only happens when there is traffic, hence the peer should
+    /// have `persistent_keepalive` set.
+    pub fn endpoint_failover(
+        mut self,
+        public_key: &str,
+        candidates: Vec<SocketAddr>,
+    ) -> Self {
+        if candidates.is_empty() {
+            self.failovers.remove(public_key);
+        } else {
+            self.failovers.insert(
+                public_key.to_string(),
+                EndpointFailover {
+                    candidates,
+                    index: 0,
+                    since: None,
+                },
+            );
+        }
+        self
+    }
+
+    /// Duration without successful handshake before switching to next
+    /// endpoint candidate, default is 180 seconds.
+    pub fn failover_window(mut self, window: Duration) -> Self {
+        self.failover_window = window;
+        self
+    }
+
+    /// Reconcile once. Return `true` if drift was found and corrected.
+    pub async fn reconcile(&mut self) -> Result<bool, WireguardError> {
+        let mut desired = (self.source)();
+        let iface_name = desired.iface_name.clone().ok_or_else(|| {
+            WireguardError::new(
+                ErrorKind::InvalidConfig,
+                "Desired state of WireguardReconciler has no iface_name"
+                    .to_string(),
+                None,
+            )
+        })?;
+        let current = self.handle.get_by_name(&iface_name).await?;
+        self.apply_failover(&current, &mut desired);
+        if let Some(fix) = correction(&current, &desired) {
+            if let Some(callback) = self.on_drift.as_mut() {
+                callback(&current, &fix);
+            }
+            log::debug!("Correcting drift of {iface_name}: {fix:?}");
+            self.handle.set(fix).await?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn apply_failover(
+        &mut self,
+        current: &WireguardParsed,
+        desired: &mut WireguardParsed,
+    ) {
+        for (public_key, failover) in self.failovers.iter_mut() {
+            let Some(desired_peer) = desired.peers.as_mut().and_then(|peers| {
+                peers
+                    .iter_mut()
+                    .find(|p| p.public_key.as_ref() == Some(public_key))
+            }) else {
+                continue;
+            };
+            let handshake_ok = current
+                .peers
+                .as_deref()
+                .unwrap_or_default()
+                .iter()
+                .find(|p| p.public_key.as_ref() == Some(public_key))
+                .map(|p| p.is_connected(self.failover_window))
+                .unwrap_or_default();
+            match failover.since {
+                None => failover.since = Some(Instant::now()),
+                Some(since)
+                    if !handshake_ok
+                        && since.elapsed() >= self.failover_window =>
+                {
+                    failover.index =
+                        (failover.index + 1) % failover.candidates.len();
+                    failover.since = Some(Instant::now());
+                    log::info!(
+                        "No handshake with peer {} in {:?}, switching \
+                         endpoint to {}",
+                        key_fingerprint(public_key),
+                        self.failover_window,
+                        failover.candidates[failover.index]
+                    );
+                }
+                _ => (),
+            }
+            desired_peer.endpoint =
+                failover.candidates.get(failover.index).copied();
+        }
+    }
+
+    /// Reconcile periodically, never return.
+    #[cfg(any(feature = "tokio_socket", feature = "smol_socket"))]
+    pub async fn run(&mut self) {
+        let mut failures: u32 = 0;
+        loop {
+            let delay = match self.reconcile().await {
+                Ok(_) => {
+                    failures = 0;
+                    self.interval
+                }
+                Err(e) => {
+                    log::warn!("Failed to reconcile wireguard config: {e}");
+                    let delay = self
+                        .retry_delay
+                        .saturating_mul(2u32.saturating_pow(failures))
+                        .min(self.max_retry_delay);
+                    failures = failures.saturating_add(1);
+                    delay
+                }
+            };
+            sleep(with_jitter(delay)).await;
+        }
+    }
+}
@@ -0,0 +1,231 @@
+// SPDX-License-Identifier: MIT
+
+use futures_util::StreamExt;
+
+use crate::{
+    diff::correction, DynWireguardApi, ErrorKind, WireguardError,
+    WireguardParsed,
+};
+
+const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Apply desired configurations to many targets (e.g. interfaces in
+/// different network namespaces or hosts) with bounded concurrency.
+///
+/// Each target is a pre-built [crate::WireguardHandle], for network
+/// namespace, create the connection socket inside that namespace. Like
+/// [crate::WireguardReconciler], only the difference to current kernel
+/// state is sent.
+pub struct WireguardRollout {
+    targets: Vec<(String, Box<dyn DynWireguardApi>, WireguardParsed)>,
+    concurrency: usize,
+}
+
+impl std::fmt::Debug for WireguardRollout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<&str> =
+            self.targets.iter().map(|(n, _, _)| n.as_str()).collect();
+        f.debug_struct("WireguardRollout")
+            .field("targets", &names)
+            .field("concurrency", &self.concurrency)
+            .finish()
+    }
+}
+
+/// Outcome of one target of [WireguardRollout::run()].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct WireguardRolloutResult {
+    /// Name of target given to [WireguardRollout::target()]
+    pub name: String,
+    /// `Ok(true)` if configuration changed, `Ok(false)` if already
+    /// matching desired state.
+    pub result: Result<bool, WireguardError>,
+}
+
+impl Default for WireguardRollout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WireguardRollout {
+    pub fn new() -> Self {
+        Self {
+            targets: Vec::new(),
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    /// Maximum number of targets being applied at the same time,
+    /// default is 8. Zero is treated as 1.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Add target identified by `name` in result. The `iface_name` of
+    /// `desired` is mandatory. The `api` is usually a
+    /// [crate::WireguardHandle], but any [DynWireguardApi] works.
+    pub fn target<A>(
+        mut self,
+        name: &str,
+        api: A,
+        desired: WireguardParsed,
+    ) -> Self
+    where
+        A: DynWireguardApi + 'static,
+    {
+        self.targets
+            .push((name.to_string(), Box::new(api), desired));
+        self
+    }
+
+    /// Apply all targets, failure of one target does not stop others.
+    /// Results are in the order of targets added.
+    pub async fn run(self) -> Vec<WireguardRolloutResult> {
+        futures_util::stream::iter(self.targets)
+            .map(|(name, api, desired)| async move {
+                let result = apply(api, desired).await;
+                match &result {
+                    Ok(true) => log::info!("Rollout to {name} applied"),
+                    Ok(false) => log::debug!("Rollout to {name} unchanged"),
+                    Err(e) => log::warn!("Rollout to {name} failed: {e}"),
+                }
+                WireguardRolloutResult { name, result }
+            })
+            .buffered(self.concurrency)
+            .collect()
+            .await
+    }
+}
+
+async fn apply(
+    mut api: Box<dyn DynWireguardApi>,
+    desired: WireguardParsed,
+) -> Result<bool, WireguardError> {
+    let iface_name = desired.iface_name.as_deref().ok_or_else(|| {
+        WireguardError::new(
+            ErrorKind::InvalidConfig,
+            "Desired state of rollout target has no iface_name".to_string(),
+            None,
+        )
+    })?;
+    let current = api.get_by_name(iface_name).await?;
+    if let Some(fix) = correction(&current, &desired) {
+        api.set(fix).await?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{WireguardBoxFuture, WireguardPeerParsed};
+
+    const KEY1: &str = "uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM=";
+    const KEY2: &str = "WEAuaVuhdyscyTCXVfBDJR6nf9zxD75jmJzrfhkyE3Y=";
+
+    // Interfaces of one target, recording every set()
+    #[derive(Clone, Default)]
+    struct MockApi {
+        current: Vec<WireguardParsed>,
+        applied: Arc<Mutex<Vec<WireguardParsed>>>,
+    }
+
+    impl DynWireguardApi for MockApi {
+        fn get_by_name<'a>(
+            &'a mut self,
+            iface_name: &'a str,
+        ) -> WireguardBoxFuture<'a, WireguardParsed> {
+            let ret = self
+                .current
+                .iter()
+                .find(|c| c.iface_name.as_deref() == Some(iface_name))
+                .cloned()
+                .ok_or_else(|| {
+                    WireguardError::new(
+                        ErrorKind::NoSuchInterface,
+                        format!("Interface {iface_name} not found"),
+                        None,
+                    )
+                });
+            Box::pin(async move { ret })
+        }
+
+        fn set(
+            &mut self,
+            config: WireguardParsed,
+        ) -> WireguardBoxFuture<'_, ()> {
+            self.applied.lock().unwrap().push(config);
+            Box::pin(async { Ok(()) })
+        }
+
+        fn list_all(&mut self) -> WireguardBoxFuture<'_, Vec<WireguardParsed>> {
+            let ret = self.current.clone();
+            Box::pin(async move { Ok(ret) })
+        }
+    }
+
+    fn config(peer_keys: &[&str]) -> WireguardParsed {
+        WireguardParsed {
+            listen_port: Some(51820),
+            ..WireguardParsed::new("wg0")
+        }
+        .with_peers(peer_keys.iter().map(|k| WireguardPeerParsed::new(k)))
+    }
+
+    fn target(current: WireguardParsed) -> MockApi {
+        MockApi {
+            current: vec![current],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn run() {
+        let changed = target(config(&[KEY1]));
+        let unchanged = target(config(&[KEY1, KEY2]));
+        let desired = config(&[KEY1, KEY2]);
+        let results = WireguardRollout::new()
+            .concurrency(0)
+            .target("changed", changed.clone(), desired.clone())
+            .target("missing", MockApi::default(), desired.clone())
+            .target("unchanged", unchanged.clone(), desired.clone())
+            .target("remove_peers", unchanged.clone(), config(&[]))
+            .target(
+                "no_iface_name",
+                unchanged.clone(),
+                WireguardParsed::default(),
+            )
+            .run()
+            .await;
+
+        let summary: Vec<String> = results
+            .iter()
+            .map(|r| match &r.result {
+                Ok(changed) => format!("{}: {changed}", r.name),
+                Err(e) => format!("{}: {}", r.name, e.kind),
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                "changed: true",
+                "missing: no_such_interface",
+                "unchanged: false",
+                "remove_peers: true",
+                "no_iface_name: invalid_config",
+            ]
+        );
+        assert_eq!(
+            *changed.applied.lock().unwrap(),
+            [WireguardParsed::new("wg0")
+                .with_peers([WireguardPeerParsed::new(KEY2)])]
+        );
+    }
+}
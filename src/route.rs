@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: MIT
+
+use std::str::FromStr;
+
+#[cfg(feature = "exec")]
+use crate::cmd::run_cmd;
+use crate::{
+    ErrorKind, WireguardError, WireguardIpAddress, WireguardQuickConfig,
+};
+
+/// The `Table` option of wg-quick
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[non_exhaustive]
+pub enum WireguardQuickTable {
+    /// Install routes to main table, default routes are handled by policy
+    /// routing instead.
+    #[default]
+    Auto,
+    /// Do not install routes
+    Off,
+    /// Install routes to specified routing table ID
+    Id(u32),
+    /// Install routes to routing table defined in
+    /// `/etc/iproute2/rt_tables`
+    Name(String),
+}
+
+impl FromStr for WireguardQuickTable {
+    type Err = WireguardError;
+
+    fn from_str(s: &str) -> Result<Self, WireguardError> {
+        let s = s.trim();
+        if s.is_empty() {
+            Err(WireguardError::new(
+                ErrorKind::InvalidConfig,
+                "Empty routing table".to_string(),
+                None,
+            ))
+        } else if s == "auto" {
+            Ok(Self::Auto)
+        } else if s == "off" {
+            Ok(Self::Off)
+        } else if let Ok(id) = u32::from_str(s) {
+            Ok(Self::Id(id))
+        } else {
+            Ok(Self::Name(s.to_string()))
+        }
+    }
+}
+
+impl std::fmt::Display for WireguardQuickTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Off => write!(f, "off"),
+            Self::Id(id) => write!(f, "{id}"),
+            Self::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+impl WireguardQuickConfig {
+    /// The allowed IPs of all peers to route via this interface, sorted by
+    /// prefix length in descending order like wg-quick does.
+    /// Empty if `table` is [WireguardQuickTable::Off].
+    pub fn routes(&self) -> Vec<WireguardIpAddress> {
+        if self.table == Some(WireguardQuickTable::Off) {
+            return Vec::new();
+        }
+        let mut ret: Vec<WireguardIpAddress> = Vec::new();
+        for (_, ip) in self.wireguard.iter_allowed_ips() {
+            if !ret.iter().any(|i| {
+                i.ip_addr == ip.ip_addr && i.prefix_length == ip.prefix_length
+            }) {
+                ret.push(WireguardIpAddress {
+                    flags: None,
+                    ..ip.clone()
+                });
+            }
+        }
+        ret.sort_by_key(|i| std::cmp::Reverse(i.prefix_length));
+        ret
+    }
+
+    /// Install [WireguardQuickConfig::routes()] via `ip route replace`.
+    /// With [WireguardQuickTable::Auto], default routes are skipped as they
+    /// require policy routing.
+    #[cfg(feature = "exec")]
+    pub fn add_routes(&self) -> Result<(), WireguardError> {
+        let iface_name =
+            self.wireguard.iface_name.as_deref().ok_or_else(|| {
+                WireguardError::new(
+                    ErrorKind::InvalidConfig,
+                    "Cannot add routes without iface_name".to_string(),
+                    None,
+                )
+            })?;
+        let table = self.table.clone().unwrap_or_default();
+        let table_str = match &table {
+            WireguardQuickTable::Off => return Ok(()),
+            WireguardQuickTable::Auto => "main".to_string(),
+            t => t.to_string(),
+        };
+        for route in self.routes() {
+            if table == WireguardQuickTable::Auto && route.is_default_route() {
+                log::debug!(
+                    "Skipping default route {route} which requires policy \
+                     routing"
+                );
+                continue;
+            }
+            let dst = route.to_string();
+            run_cmd(
+                "ip",
+                &[
+                    if route.ip_addr.is_ipv4() { "-4" } else { "-6" },
+                    "route",
+                    "replace",
+                    dst.as_str(),
+                    "dev",
+                    iface_name,
+                    "table",
+                    table_str.as_str(),
+                ],
+                None,
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+// SPDX-License-Identifier: MIT
+
+#[cfg(any(feature = "tokio_socket", feature = "smol_socket"))]
+use std::time::Duration;
+
+// Add up to 10% random delay to prevent many instances hitting kernel at the
+// same time.
+#[cfg(any(feature = "tokio_socket", feature = "smol_socket"))]
+pub(crate) fn with_jitter(delay: Duration) -> Duration {
+    let seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    delay + delay.mul_f64(f64::from(seed % 1000) / 10000.0)
+}
+
+#[cfg(feature = "tokio_socket")]
+pub(crate) async fn sleep(delay: Duration) {
+    tokio::time::sleep(delay).await
+}
+
+#[cfg(all(feature = "smol_socket", not(feature = "tokio_socket")))]
+pub(crate) async fn sleep(delay: Duration) {
+    async_std::task::sleep(delay).await
+}
+
+// Run blocking work like DNS lookup without stalling the async runtime.
+#[cfg(feature = "tokio_socket")]
+pub(crate) async fn unblock<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(v) => v,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+#[cfg(all(feature = "smol_socket", not(feature = "tokio_socket")))]
+pub(crate) async fn unblock<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    async_std::task::spawn_blocking(f).await
+}
+
+// Without a known runtime there is no thread pool to offload to
+#[cfg(not(any(feature = "tokio_socket", feature = "smol_socket")))]
+pub(crate) async fn unblock<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    f()
+}
@@ -0,0 +1,370 @@
+// SPDX-License-Identifier: MIT
+
+use std::fmt::Write;
+
+use crate::{
+    ErrorKind, WireguardError, WireguardPolicyRouting, WireguardQuickConfig,
+    WireguardQuickTable,
+};
+
+impl WireguardQuickConfig {
+    /// Render the provisioning of this configuration as POSIX shell script
+    /// of `ip` and `wg` commands equivalent to `wg-quick up`, for reviewing
+    /// or applying on hosts without this crate. Secrets are passed to `wg`
+    /// via here-documents instead of command line arguments.
+    pub fn to_shell_script(&self) -> Result<String, WireguardError> {
+        let wg = &self.wireguard;
+        let iface_name = wg.iface_name.as_deref().ok_or_else(|| {
+            WireguardError::new(
+                ErrorKind::InvalidConfig,
+                "Cannot generate shell script without iface_name".to_string(),
+                None,
+            )
+        })?;
+        let iface = quote(iface_name);
+        let policy_routing = WireguardPolicyRouting::new(self)?;
+        let mut ret = String::new();
+        ret.push_str("#!/bin/sh\nset -e\n\n");
+
+        for cmd in self.pre_up.as_deref().unwrap_or_default() {
+            writeln!(ret, "{}", cmd.replace("%i", iface_name)).ok();
+        }
+        writeln!(ret, "ip link add dev {iface} type wireguard").ok();
+
+        if let Some(key) = wg.private_key.as_deref() {
+            write_secret(&mut ret, &format!("wg set {iface} private-key"), key);
+        }
+        let mut dev_args = String::new();
+        if let Some(port) = wg.listen_port {
+            write!(dev_args, " listen-port {port}").ok();
+        }
+        let fwmark = policy_routing
+            .as_ref()
+            .map(|p| p.fwmark())
+            .or(wg.fwmark.filter(|m| *m != 0));
+        if let Some(fwmark) = fwmark {
+            write!(dev_args, " fwmark {fwmark:#x}").ok();
+        }
+        if !dev_args.is_empty() {
+            writeln!(ret, "wg set {iface}{dev_args}").ok();
+        }
+
+        for peer in wg.peers_iter() {
+            let Some(public_key) = peer.public_key.as_deref() else {
+                continue;
+            };
+            let peer_cmd = format!("wg set {iface} peer {}", quote(public_key));
+            let mut peer_args = String::new();
+            if let Some(ips) = peer.allowed_ips.as_ref() {
+                let ips: Vec<String> =
+                    ips.iter().map(|i| i.to_string()).collect();
+                write!(peer_args, " allowed-ips {}", ips.join(",")).ok();
+            }
+            if let Some(endpoint) = self.peer_endpoint(peer) {
+                write!(peer_args, " endpoint {}", quote(&endpoint.to_string()))
+                    .ok();
+            }
+            if let Some(v) = peer.persistent_keepalive.filter(|v| *v != 0) {
+                write!(peer_args, " persistent-keepalive {v}").ok();
+            }
+            writeln!(ret, "{peer_cmd}{peer_args}").ok();
+            if let Some(key) = peer.preshared_key.as_deref() {
+                write_secret(
+                    &mut ret,
+                    &format!("{peer_cmd} preshared-key"),
+                    key,
+                );
+            }
+        }
+
+        for addr in self.addresses.as_deref().unwrap_or_default() {
+            writeln!(ret, "ip address add {addr} dev {iface}").ok();
+        }
+        if let Some(mtu) = self.mtu {
+            writeln!(ret, "ip link set mtu {mtu} up dev {iface}").ok();
+        } else {
+            writeln!(ret, "ip link set up dev {iface}").ok();
+        }
+
+        // Lines are passed as arguments of printf, never as its format
+        let dns: Vec<String> = self
+            .dns
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .map(|i| quote(&format!("nameserver {i}")))
+            .chain(
+                self.dns_search
+                    .as_ref()
+                    .filter(|s| !s.is_empty())
+                    .map(|s| quote(&format!("search {}", s.join(" ")))),
+            )
+            .collect();
+        if !dns.is_empty() {
+            writeln!(
+                ret,
+                "printf '%s\\n' {} | resolvconf -a {} -m 0 -x",
+                dns.join(" "),
+                quote(&format!("tun.{iface_name}"))
+            )
+            .ok();
+        }
+
+        let table = self.table.clone().unwrap_or_default();
+        let table_str = match &table {
+            WireguardQuickTable::Auto => "main".to_string(),
+            t => t.to_string(),
+        };
+        if table != WireguardQuickTable::Off {
+            for route in self.routes() {
+                if table == WireguardQuickTable::Auto
+                    && route.is_default_route()
+                {
+                    continue;
+                }
+                writeln!(
+                    ret,
+                    "ip {} route add {route} dev {iface} table {}",
+                    if route.ip_addr.is_ipv4() { "-4" } else { "-6" },
+                    quote(&table_str)
+                )
+                .ok();
+            }
+        }
+        if let Some(policy) = policy_routing.as_ref() {
+            for cmd in policy.rule_delete_cmds() {
+                writeln!(
+                    ret,
+                    "while {} 2>/dev/null; do :; done",
+                    shell_cmd(&cmd)
+                )
+                .ok();
+            }
+            for cmd in policy.setup_cmds() {
+                writeln!(ret, "{}", shell_cmd(&cmd)).ok();
+            }
+        }
+
+        for cmd in self.post_up.as_deref().unwrap_or_default() {
+            writeln!(ret, "{}", cmd.replace("%i", iface_name)).ok();
+        }
+        Ok(ret)
+    }
+}
+
+fn write_secret(out: &mut String, cmd: &str, secret: &str) {
+    writeln!(out, "{cmd} /dev/stdin <<'EOF'\n{secret}\nEOF").ok();
+}
+
+fn shell_cmd(args: &[String]) -> String {
+    args.iter().map(|a| quote(a)).collect::<Vec<_>>().join(" ")
+}
+
+// Single quote for shell unless only safe characters are used
+fn quote(s: &str) -> String {
+    if !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+-./:=@_,".contains(c))
+    {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    const PRIVATE_KEY: &str = "6EtabScXwQA6E7QxVwNT26ypFGzxUMX4V1aA/rpSAno=";
+    const PEER_KEY: &str = "uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM=";
+    const PSK: &str = "GIUVCT6VL18i6GXO8wEucvi18LWYrAMJ1drM47cPz1I=";
+
+    fn config(lines: &[&str]) -> WireguardQuickConfig {
+        let content = lines
+            .join("\n")
+            .replace("<PRIVATE_KEY>", PRIVATE_KEY)
+            .replace("<PEER_KEY>", PEER_KEY)
+            .replace("<PSK>", PSK);
+        let mut ret = WireguardQuickConfig::from_str(&content).unwrap();
+        ret.wireguard.iface_name = Some("wg0".to_string());
+        ret
+    }
+
+    fn script(lines: &[&str]) -> Vec<String> {
+        config(lines)
+            .to_shell_script()
+            .unwrap()
+            .lines()
+            .map(|l| {
+                l.replace(PRIVATE_KEY, "<PRIVATE_KEY>")
+                    .replace(PEER_KEY, "<PEER_KEY>")
+                    .replace(PSK, "<PSK>")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_tunnel() {
+        assert_eq!(
+            script(&[
+                "[Interface]",
+                "Address = 10.0.0.1/24",
+                "MTU = 1420",
+                "DNS = 10.0.0.53, example.com",
+                "ListenPort = 51820",
+                "PrivateKey = <PRIVATE_KEY>",
+                "PreUp = echo %i pre-up",
+                "PostUp = echo %i post-up",
+                "[Peer]",
+                "PublicKey = <PEER_KEY>",
+                "PresharedKey = <PSK>",
+                "AllowedIPs = 10.0.0.2/32, 192.168.0.0/16",
+                "Endpoint = [2001:db8::1]:51820",
+                "PersistentKeepalive = 25",
+            ]),
+            [
+                "#!/bin/sh",
+                "set -e",
+                "",
+                "echo wg0 pre-up",
+                "ip link add dev wg0 type wireguard",
+                "wg set wg0 private-key /dev/stdin <<'EOF'",
+                "<PRIVATE_KEY>",
+                "EOF",
+                "wg set wg0 listen-port 51820",
+                "wg set wg0 peer <PEER_KEY> allowed-ips \
+                 10.0.0.2/32,192.168.0.0/16 endpoint '[2001:db8::1]:51820' \
+                 persistent-keepalive 25",
+                "wg set wg0 peer <PEER_KEY> preshared-key /dev/stdin <<'EOF'",
+                "<PSK>",
+                "EOF",
+                "ip address add 10.0.0.1/24 dev wg0",
+                "ip link set mtu 1420 up dev wg0",
+                "printf '%s\\n' 'nameserver 10.0.0.53' 'search example.com' | \
+                 resolvconf -a tun.wg0 -m 0 -x",
+                "ip -4 route add 10.0.0.2/32 dev wg0 table main",
+                "ip -4 route add 192.168.0.0/16 dev wg0 table main",
+                "echo wg0 post-up",
+            ]
+        );
+    }
+
+    #[test]
+    fn full_tunnel() {
+        assert_eq!(
+            script(&[
+                "[Interface]",
+                "[Peer]",
+                "PublicKey = <PEER_KEY>",
+                "AllowedIPs = 0.0.0.0/0, ::/0",
+            ]),
+            [
+                "#!/bin/sh",
+                "set -e",
+                "",
+                "ip link add dev wg0 type wireguard",
+                "wg set wg0 fwmark 0xca6c",
+                "wg set wg0 peer <PEER_KEY> allowed-ips 0.0.0.0/0,::/0",
+                "ip link set up dev wg0",
+                "while ip -4 rule delete table 51820 2>/dev/null; do :; done",
+                "while ip -4 rule delete table main suppress_prefixlength 0 \
+                 2>/dev/null; do :; done",
+                "while ip -6 rule delete table 51820 2>/dev/null; do :; done",
+                "while ip -6 rule delete table main suppress_prefixlength 0 \
+                 2>/dev/null; do :; done",
+                "ip -4 route replace 0.0.0.0/0 dev wg0 table 51820",
+                "ip -4 rule add not fwmark 51820 table 51820",
+                "ip -4 rule add table main suppress_prefixlength 0",
+                "sysctl -q net.ipv4.conf.all.src_valid_mark=1",
+                "ip -6 route replace ::/0 dev wg0 table 51820",
+                "ip -6 rule add not fwmark 51820 table 51820",
+                "ip -6 rule add table main suppress_prefixlength 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_table() {
+        let lines = script(&[
+            "[Interface]",
+            "Table = my table",
+            "FwMark = 0x1234",
+            "[Peer]",
+            "PublicKey = <PEER_KEY>",
+            "AllowedIPs = 0.0.0.0/0, fd00::/64",
+        ]);
+        assert!(lines.contains(&"wg set wg0 fwmark 0x1234".to_string()));
+        assert!(lines.contains(
+            &"ip -4 route add 0.0.0.0/0 dev wg0 table 'my table'".to_string()
+        ));
+        assert!(lines.contains(
+            &"ip -6 route add fd00::/64 dev wg0 table 'my table'".to_string()
+        ));
+        assert!(!lines.iter().any(|l| l.contains(" rule ")));
+
+        let lines = script(&[
+            "[Interface]",
+            "Table = off",
+            "[Peer]",
+            "PublicKey = <PEER_KEY>",
+            "AllowedIPs = 0.0.0.0/0",
+        ]);
+        assert!(!lines.iter().any(|l| l.contains(" route ")));
+    }
+
+    #[test]
+    fn dns_is_not_shell_injectable() {
+        let mut config = config(&["[Interface]", "DNS = 10.0.0.53"]);
+        config.dns_search = Some(vec![
+            "it's.example.com".to_string(),
+            "100%s.example.com".to_string(),
+            "$(reboot)".to_string(),
+        ]);
+        let script = config.to_shell_script().unwrap();
+        let printf = script
+            .lines()
+            .find(|l| l.starts_with("printf "))
+            .unwrap()
+            .split(" | resolvconf ")
+            .next()
+            .unwrap();
+        let output = std::process::Command::new("sh")
+            .args(["-c", printf])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "nameserver 10.0.0.53\nsearch it's.example.com 100%s.example.com \
+             $(reboot)\n"
+        );
+    }
+
+    #[test]
+    fn quote_special_chars() {
+        let cases = [
+            ("wg0", "wg0"),
+            ("10.0.0.1:51820", "10.0.0.1:51820"),
+            ("", "''"),
+            ("a b", "'a b'"),
+            ("it's", "'it'\\''s'"),
+            ("$HOME", "'$HOME'"),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(quote(s), expected, "{s}");
+        }
+    }
+
+    #[test]
+    fn missing_iface_name() {
+        let mut config = config(&["[Interface]"]);
+        config.wireguard.iface_name = None;
+        assert_eq!(
+            config.to_shell_script().unwrap_err().kind,
+            ErrorKind::InvalidConfig
+        );
+    }
+}
@@ -0,0 +1,209 @@
+// SPDX-License-Identifier: MIT
+
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "exec")]
+use crate::cmd::run_cmd;
+use crate::{
+    diff::correction,
+    handle::{wireguard_iface_names, SYSFS_NET_DIR},
+    ErrorKind, WireguardError, WireguardHandle, WireguardParsed,
+    WireguardParsedDeviceFlags, WireguardQuickConfig,
+};
+
+impl WireguardHandle {
+    /// Write the configuration of every wireguard interface found by
+    /// [WireguardHandle::list_all()] to `<dir>/<iface_name>.conf` in
+    /// wg-quick format with permission `0600`, return the paths written.
+    ///
+    /// With `redact`, private and preshared keys are omitted. Interface
+    /// properties not managed via wireguard netlink (e.g. IP addresses and
+    /// MTU) are not included.
+    pub async fn snapshot_all(
+        &mut self,
+        dir: &Path,
+        redact: bool,
+    ) -> Result<Vec<PathBuf>, WireguardError> {
+        std::fs::create_dir_all(dir).map_err(|e| {
+            WireguardError::new(
+                ErrorKind::IoError,
+                format!("Failed to create dir {}: {e}", dir.display()),
+                None,
+            )
+        })?;
+        let mut ret = Vec::new();
+        for parsed in self.list_all().await? {
+            let Some(iface_name) = parsed.iface_name.clone() else {
+                continue;
+            };
+            let mut wireguard = parsed.without_runtime_fields();
+            if redact {
+                wireguard.private_key = None;
+                for peer in wireguard.peers.iter_mut().flatten() {
+                    peer.preshared_key = None;
+                }
+            }
+            let path = dir.join(format!("{iface_name}.conf"));
+            WireguardQuickConfig::from(wireguard).write(&path)?;
+            log::info!("Saved {iface_name} to {}", path.display());
+            ret.push(path);
+        }
+        Ok(ret)
+    }
+
+    /// Apply every `<iface_name>.conf` in `dir` written by
+    /// [WireguardHandle::snapshot_all()], return the interface name with
+    /// whether it was changed, sorted by interface name.
+    ///
+    /// Missing interfaces are created via `ip link` command with the `exec`
+    /// feature, otherwise they fail with [ErrorKind::NoSuchInterface]. Peers
+    /// not in
+    /// the file are removed, while properties absent in file (e.g. private
+    /// key of redacted snapshot) are left untouched. On failure, a newly
+    /// created interface is deleted and an existing one is reverted to its
+    /// previous configuration.
+    pub async fn restore_all(
+        &mut self,
+        dir: &Path,
+    ) -> Result<Vec<(String, Result<bool, WireguardError>)>, WireguardError>
+    {
+        let entries = std::fs::read_dir(dir).map_err(|e| {
+            WireguardError::new(
+                ErrorKind::IoError,
+                format!("Failed to read dir {}: {e}", dir.display()),
+                None,
+            )
+        })?;
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == "conf"))
+            .collect();
+        paths.sort_unstable();
+        let existing = wireguard_iface_names()?;
+
+        let mut ret = Vec::new();
+        for path in paths {
+            let iface_name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let config = match WireguardQuickConfig::read(&path) {
+                Ok(mut c) => c.resolve_endpoints_async().await.map(|_| c),
+                Err(e) => Err(e),
+            };
+            let result = match config {
+                Ok(config) => {
+                    self.restore(
+                        &iface_name,
+                        config.wireguard,
+                        existing.contains(&iface_name),
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result.as_ref() {
+                log::warn!(
+                    "Failed to restore {iface_name} from {}: {e}",
+                    path.display()
+                );
+            }
+            ret.push((iface_name, result));
+        }
+        Ok(ret)
+    }
+
+    async fn restore(
+        &mut self,
+        iface_name: &str,
+        desired: WireguardParsed,
+        exists: bool,
+    ) -> Result<bool, WireguardError> {
+        if !exists {
+            if Path::new(SYSFS_NET_DIR).join(iface_name).exists() {
+                return Err(WireguardError::new(
+                    ErrorKind::NotWireguard,
+                    format!(
+                        "Interface {iface_name} exists but is not a wireguard \
+                         interface"
+                    ),
+                    None,
+                ));
+            }
+            create_iface(iface_name)?;
+        }
+        let current = match self.get_by_name(iface_name).await {
+            Ok(c) => c,
+            Err(e) => {
+                self.rollback(iface_name, None, exists).await;
+                return Err(e);
+            }
+        };
+        let Some(fix) = correction(&current, &desired) else {
+            return Ok(false);
+        };
+        match self.set(fix).await {
+            Ok(()) => Ok(true),
+            Err(e) => {
+                self.rollback(iface_name, Some(current), exists).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn rollback(
+        &mut self,
+        iface_name: &str,
+        previous: Option<WireguardParsed>,
+        existed: bool,
+    ) {
+        let result = if !existed {
+            delete_iface(iface_name)
+        } else if let Some(previous) = previous {
+            let mut previous = previous.without_runtime_fields();
+            previous.peers.get_or_insert_with(Vec::new);
+            previous.flags =
+                Some(vec![WireguardParsedDeviceFlags::ReplacePeers]);
+            self.set(previous).await
+        } else {
+            Ok(())
+        };
+        if let Err(e) = result {
+            log::error!("Failed to roll back interface {iface_name}: {e}");
+        }
+    }
+}
+
+#[cfg(feature = "exec")]
+fn create_iface(iface_name: &str) -> Result<(), WireguardError> {
+    run_cmd(
+        "ip",
+        &["link", "add", "dev", iface_name, "type", "wireguard"],
+        None,
+    )?;
+    log::info!("Created interface {iface_name}");
+    Ok(())
+}
+
+#[cfg(not(feature = "exec"))]
+fn create_iface(iface_name: &str) -> Result<(), WireguardError> {
+    Err(WireguardError::new(
+        ErrorKind::NoSuchInterface,
+        format!(
+            "Interface {iface_name} does not exist, creating it requires the \
+             exec feature"
+        ),
+        None,
+    ))
+}
+
+#[cfg(feature = "exec")]
+fn delete_iface(iface_name: &str) -> Result<(), WireguardError> {
+    run_cmd("ip", &["link", "del", "dev", iface_name], None)
+}
+
+// Nothing to delete as interface is never created without `exec` feature
+#[cfg(not(feature = "exec"))]
+fn delete_iface(_iface_name: &str) -> Result<(), WireguardError> {
+    Ok(())
+}
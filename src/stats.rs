@@ -0,0 +1,319 @@
+// SPDX-License-Identifier: MIT
+
+use std::time::Duration;
+
+#[cfg(feature = "connection")]
+use base64::{prelude::BASE64_STANDARD, Engine};
+#[cfg(feature = "connection")]
+use netlink_packet_wireguard::{
+    WireguardAttribute, WireguardMessage, WireguardPeerAttribute,
+};
+
+#[cfg(feature = "connection")]
+use crate::peer_parsed::parse_last_handshake;
+use crate::{key_fingerprint, WireguardParsed};
+
+/// Traffic statistics of wireguard interface, only holding the information
+/// required for monitoring.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[non_exhaustive]
+pub struct WireguardDeviceStats {
+    pub iface_name: Option<String>,
+    pub iface_index: Option<u32>,
+    pub peers: Vec<WireguardPeerStats>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[non_exhaustive]
+pub struct WireguardPeerStats {
+    /// Base64 encoded public key
+    pub public_key: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Last handshake time since UNIX_EPOCH, `None` if never handshaked
+    pub last_handshake: Option<Duration>,
+}
+
+/// Traffic of peer between two polls of [WireguardPeerStats].
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[non_exhaustive]
+pub struct WireguardPeerStatsDelta {
+    /// Base64 encoded public key
+    pub public_key: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// The counters restarted from zero (e.g. interface recreated or
+    /// kernel module reloaded), the deltas are the traffic since then.
+    pub counter_reset: bool,
+}
+
+impl WireguardPeerStats {
+    /// Traffic since `previous` poll of the same peer.
+    pub fn delta(&self, previous: &Self) -> WireguardPeerStatsDelta {
+        let counter_reset = self.rx_bytes < previous.rx_bytes
+            || self.tx_bytes < previous.tx_bytes;
+        if counter_reset {
+            log::debug!(
+                "Traffic counters of peer {} were reset",
+                key_fingerprint(&self.public_key)
+            );
+            WireguardPeerStatsDelta {
+                public_key: self.public_key.clone(),
+                rx_bytes: self.rx_bytes,
+                tx_bytes: self.tx_bytes,
+                counter_reset,
+            }
+        } else {
+            WireguardPeerStatsDelta {
+                public_key: self.public_key.clone(),
+                rx_bytes: self.rx_bytes - previous.rx_bytes,
+                tx_bytes: self.tx_bytes - previous.tx_bytes,
+                counter_reset,
+            }
+        }
+    }
+}
+
+impl From<&WireguardParsed> for WireguardDeviceStats {
+    fn from(parsed: &WireguardParsed) -> Self {
+        Self {
+            iface_name: parsed.iface_name.clone(),
+            iface_index: parsed.iface_index,
+            peers: parsed
+                .peers_iter()
+                .map(|peer| WireguardPeerStats {
+                    public_key: peer.public_key.clone().unwrap_or_default(),
+                    rx_bytes: peer.rx_bytes.unwrap_or_default(),
+                    tx_bytes: peer.tx_bytes.unwrap_or_default(),
+                    last_handshake: peer.last_handshake,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl WireguardDeviceStats {
+    /// Traffic of each current peer since `previous` poll of the same
+    /// interface.
+    /// All counters are treated as reset when interface index changed,
+    /// which means the interface was recreated. Peers not found in
+    /// `previous` are counted from zero.
+    pub fn delta(&self, previous: &Self) -> Vec<WireguardPeerStatsDelta> {
+        self.peers
+            .iter()
+            .map(|peer| self.peer_delta(previous, peer))
+            .collect()
+    }
+
+    /// Up to `n` peers with most received bytes, in descending order.
+    pub fn top_peers_by_rx(&self, n: usize) -> Vec<&WireguardPeerStats> {
+        self.top_peers_by(n, |p| p.rx_bytes)
+    }
+
+    /// Up to `n` peers with most transmitted bytes, in descending order.
+    pub fn top_peers_by_tx(&self, n: usize) -> Vec<&WireguardPeerStats> {
+        self.top_peers_by(n, |p| p.tx_bytes)
+    }
+
+    fn top_peers_by<F>(&self, n: usize, key: F) -> Vec<&WireguardPeerStats>
+    where
+        F: Fn(&WireguardPeerStats) -> u64,
+    {
+        let mut ret: Vec<&WireguardPeerStats> = self.peers.iter().collect();
+        ret.sort_by_key(|p| std::cmp::Reverse(key(p)));
+        ret.truncate(n);
+        ret
+    }
+
+    pub(crate) fn peer_delta(
+        &self,
+        previous: &Self,
+        peer: &WireguardPeerStats,
+    ) -> WireguardPeerStatsDelta {
+        let recreated = self.iface_index.is_some()
+            && previous.iface_index.is_some()
+            && self.iface_index != previous.iface_index;
+        match previous
+            .peers
+            .iter()
+            .find(|p| p.public_key == peer.public_key)
+        {
+            Some(prev) if !recreated => peer.delta(prev),
+            _ => WireguardPeerStatsDelta {
+                public_key: peer.public_key.clone(),
+                rx_bytes: peer.rx_bytes,
+                tx_bytes: peer.tx_bytes,
+                counter_reset: recreated,
+            },
+        }
+    }
+
+    /// Append the statistics found in a reply of
+    /// [netlink_packet_wireguard::WireguardCmd::GetDevice] dump. Other
+    /// attributes are ignored.
+    #[cfg(feature = "connection")]
+    pub(crate) fn append(&mut self, msg: WireguardMessage) {
+        for attr in msg.attributes {
+            match attr {
+                WireguardAttribute::IfName(v) => self.iface_name = Some(v),
+                WireguardAttribute::IfIndex(v) => self.iface_index = Some(v),
+                WireguardAttribute::Peers(peers) => {
+                    self.peers.reserve(peers.len());
+                    for peer in peers {
+                        let mut stats = WireguardPeerStats::default();
+                        for peer_attr in peer.0 {
+                            match peer_attr {
+                                WireguardPeerAttribute::PublicKey(v) => {
+                                    stats.public_key = BASE64_STANDARD.encode(v)
+                                }
+                                WireguardPeerAttribute::RxBytes(v) => {
+                                    stats.rx_bytes = v
+                                }
+                                WireguardPeerAttribute::TxBytes(v) => {
+                                    stats.tx_bytes = v
+                                }
+                                WireguardPeerAttribute::LastHandshake(v) => {
+                                    stats.last_handshake =
+                                        parse_last_handshake(&v)
+                                }
+                                _ => (),
+                            }
+                        }
+                        // Kernel repeats the public key of peer when its
+                        // allowed IPs continue in next dump message.
+                        if self.peers.last().map(|p| &p.public_key)
+                            != Some(&stats.public_key)
+                        {
+                            self.peers.push(stats);
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(
+        public_key: &str,
+        rx_bytes: u64,
+        tx_bytes: u64,
+    ) -> WireguardPeerStats {
+        WireguardPeerStats {
+            public_key: public_key.to_string(),
+            rx_bytes,
+            tx_bytes,
+            last_handshake: None,
+        }
+    }
+
+    fn device(
+        iface_index: Option<u32>,
+        peers: Vec<WireguardPeerStats>,
+    ) -> WireguardDeviceStats {
+        WireguardDeviceStats {
+            iface_name: Some("wg0".to_string()),
+            iface_index,
+            peers,
+        }
+    }
+
+    fn delta(
+        public_key: &str,
+        rx_bytes: u64,
+        tx_bytes: u64,
+        counter_reset: bool,
+    ) -> WireguardPeerStatsDelta {
+        WireguardPeerStatsDelta {
+            public_key: public_key.to_string(),
+            rx_bytes,
+            tx_bytes,
+            counter_reset,
+        }
+    }
+
+    #[test]
+    fn peer_delta() {
+        let cases = [
+            ((100, 200), (100, 200), delta("a", 0, 0, false)),
+            ((100, 200), (150, 260), delta("a", 50, 60, false)),
+            ((100, 200), (0, 0), delta("a", 0, 0, true)),
+            ((100, 200), (30, 300), delta("a", 30, 300, true)),
+            ((100, 200), (300, 30), delta("a", 300, 30, true)),
+            (
+                (0, 0),
+                (u64::MAX, u64::MAX),
+                delta("a", u64::MAX, u64::MAX, false),
+            ),
+        ];
+        for ((prev_rx, prev_tx), (rx, tx), expected) in cases {
+            assert_eq!(
+                peer("a", rx, tx).delta(&peer("a", prev_rx, prev_tx)),
+                expected,
+                "({prev_rx}, {prev_tx}) -> ({rx}, {tx})"
+            );
+        }
+    }
+
+    #[test]
+    fn device_delta() {
+        let previous =
+            device(Some(5), vec![peer("a", 100, 100), peer("b", 50, 50)]);
+        let current = device(
+            Some(5),
+            vec![peer("a", 150, 120), peer("b", 10, 60), peer("c", 7, 8)],
+        );
+        assert_eq!(
+            current.delta(&previous),
+            [
+                delta("a", 50, 20, false),
+                delta("b", 10, 60, true),
+                // New peer counted from zero
+                delta("c", 7, 8, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn device_delta_iface_recreated() {
+        let previous = device(Some(5), vec![peer("a", 100, 100)]);
+        let current =
+            device(Some(6), vec![peer("a", 150, 120), peer("c", 7, 8)]);
+        assert_eq!(
+            current.delta(&previous),
+            [delta("a", 150, 120, true), delta("c", 7, 8, true)]
+        );
+        assert_eq!(
+            current.peer_delta(&previous, &current.peers[0]),
+            delta("a", 150, 120, true)
+        );
+    }
+
+    #[test]
+    fn device_delta_unknown_iface_index() {
+        let previous = device(None, vec![peer("a", 100, 100)]);
+        let current = device(Some(6), vec![peer("a", 150, 120)]);
+        assert_eq!(current.delta(&previous), [delta("a", 50, 20, false)]);
+    }
+
+    #[test]
+    fn top_peers() {
+        let stats = device(
+            None,
+            vec![peer("a", 1, 30), peer("b", 3, 10), peer("c", 2, 20)],
+        );
+        let keys = |peers: Vec<&WireguardPeerStats>| {
+            peers
+                .iter()
+                .map(|p| p.public_key.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(stats.top_peers_by_rx(2)), ["b", "c"]);
+        assert_eq!(keys(stats.top_peers_by_tx(5)), ["a", "c", "b"]);
+        assert!(stats.top_peers_by_rx(0).is_empty());
+    }
+}
@@ -0,0 +1,354 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::WireguardDeviceStats;
+
+/// Traffic rate of peer
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+#[non_exhaustive]
+pub struct WireguardPeerRate {
+    pub rx_bytes_per_sec: f64,
+    pub tx_bytes_per_sec: f64,
+}
+
+/// Fixed-size history of timestamped [WireguardDeviceStats] of a single
+/// interface, the oldest sample is dropped when full.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WireguardStatsHistory {
+    capacity: usize,
+    samples: VecDeque<(Instant, WireguardDeviceStats)>,
+}
+
+impl WireguardStatsHistory {
+    /// Keep at most `capacity` samples (at least 2).
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(2);
+        Self {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Record sample taken now.
+    pub fn push(&mut self, stats: WireguardDeviceStats) {
+        self.push_at(Instant::now(), stats)
+    }
+
+    pub(crate) fn push_at(
+        &mut self,
+        time: Instant,
+        stats: WireguardDeviceStats,
+    ) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((time, stats));
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Samples from oldest to newest
+    pub fn samples(
+        &self,
+    ) -> impl Iterator<Item = (Instant, &WireguardDeviceStats)> {
+        self.samples.iter().map(|(t, s)| (*t, s))
+    }
+
+    pub fn latest(&self) -> Option<&WireguardDeviceStats> {
+        self.samples.back().map(|(_, s)| s)
+    }
+
+    /// Average traffic rate of peer over samples taken within `window`
+    /// before the latest one. Counter resets are accounted as restarting
+    /// from zero.
+    /// Return `None` if less than two samples within the window or peer
+    /// not found in latest sample.
+    pub fn rate_over(
+        &self,
+        public_key: &str,
+        window: Duration,
+    ) -> Option<WireguardPeerRate> {
+        let (latest_time, _) = self.samples.back()?;
+        let start = self
+            .samples
+            .iter()
+            .position(|(t, _)| latest_time.duration_since(*t) <= window)?;
+        let samples: Vec<_> = self.samples.range(start..).collect();
+        let (first_time, _) = samples.first()?;
+        let elapsed = latest_time.duration_since(*first_time).as_secs_f64();
+        if samples.len() < 2 || elapsed <= 0.0 {
+            return None;
+        }
+        let mut rx_bytes = 0u64;
+        let mut tx_bytes = 0u64;
+        for pair in samples.windows(2) {
+            let (_, prev) = pair[0];
+            let (_, cur) = pair[1];
+            if let Some(peer) =
+                cur.peers.iter().find(|p| p.public_key == public_key)
+            {
+                let delta = cur.peer_delta(prev, peer);
+                rx_bytes = rx_bytes.saturating_add(delta.rx_bytes);
+                tx_bytes = tx_bytes.saturating_add(delta.tx_bytes);
+            }
+        }
+        self.latest()?
+            .peers
+            .iter()
+            .any(|p| p.public_key == public_key)
+            .then_some(WireguardPeerRate {
+                rx_bytes_per_sec: rx_bytes as f64 / elapsed,
+                tx_bytes_per_sec: tx_bytes as f64 / elapsed,
+            })
+    }
+
+    /// Exponentially weighted moving average of traffic rate of peer over
+    /// all samples, the weight of a sample decays by `1/e` after
+    /// `time_constant`. This is smoother than rate between two adjacent
+    /// samples when polling interval is short.
+    /// Return `None` if less than two samples or peer not found in latest
+    /// sample.
+    pub fn ewma_rate(
+        &self,
+        public_key: &str,
+        time_constant: Duration,
+    ) -> Option<WireguardPeerRate> {
+        if !self
+            .latest()?
+            .peers
+            .iter()
+            .any(|p| p.public_key == public_key)
+        {
+            return None;
+        }
+        let tau = time_constant.as_secs_f64();
+        let mut ret: Option<WireguardPeerRate> = None;
+        for i in 1..self.samples.len() {
+            let (prev_time, prev) = &self.samples[i - 1];
+            let (cur_time, cur) = &self.samples[i];
+            let elapsed = cur_time.duration_since(*prev_time).as_secs_f64();
+            if elapsed <= 0.0 {
+                continue;
+            }
+            let Some(peer) =
+                cur.peers.iter().find(|p| p.public_key == public_key)
+            else {
+                continue;
+            };
+            let delta = cur.peer_delta(prev, peer);
+            let rx = delta.rx_bytes as f64 / elapsed;
+            let tx = delta.tx_bytes as f64 / elapsed;
+            ret = Some(match ret {
+                None => WireguardPeerRate {
+                    rx_bytes_per_sec: rx,
+                    tx_bytes_per_sec: tx,
+                },
+                Some(avg) => {
+                    let alpha = if tau > 0.0 {
+                        1.0 - (-elapsed / tau).exp()
+                    } else {
+                        1.0
+                    };
+                    WireguardPeerRate {
+                        rx_bytes_per_sec: avg.rx_bytes_per_sec
+                            + alpha * (rx - avg.rx_bytes_per_sec),
+                        tx_bytes_per_sec: avg.tx_bytes_per_sec
+                            + alpha * (tx - avg.tx_bytes_per_sec),
+                    }
+                }
+            });
+        }
+        ret
+    }
+
+    /// Up to `n` peers with highest receive rate over `window` (see
+    /// [WireguardStatsHistory::rate_over()]), in descending order.
+    pub fn top_peers_by_rx_rate(
+        &self,
+        window: Duration,
+        n: usize,
+    ) -> Vec<(String, WireguardPeerRate)> {
+        self.top_peers_by_rate(window, n, |r| r.rx_bytes_per_sec)
+    }
+
+    /// Up to `n` peers with highest transmit rate over `window` (see
+    /// [WireguardStatsHistory::rate_over()]), in descending order.
+    pub fn top_peers_by_tx_rate(
+        &self,
+        window: Duration,
+        n: usize,
+    ) -> Vec<(String, WireguardPeerRate)> {
+        self.top_peers_by_rate(window, n, |r| r.tx_bytes_per_sec)
+    }
+
+    fn top_peers_by_rate<F>(
+        &self,
+        window: Duration,
+        n: usize,
+        key: F,
+    ) -> Vec<(String, WireguardPeerRate)>
+    where
+        F: Fn(&WireguardPeerRate) -> f64,
+    {
+        let Some(latest) = self.latest() else {
+            return Vec::new();
+        };
+        let mut ret: Vec<(String, WireguardPeerRate)> = latest
+            .peers
+            .iter()
+            .filter_map(|p| {
+                self.rate_over(&p.public_key, window)
+                    .map(|r| (p.public_key.clone(), r))
+            })
+            .collect();
+        ret.sort_by(|(_, a), (_, b)| key(b).total_cmp(&key(a)));
+        ret.truncate(n);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WireguardPeerStats;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn stats(
+        iface_index: u32,
+        peers: &[(&str, u64, u64)],
+    ) -> WireguardDeviceStats {
+        WireguardDeviceStats {
+            iface_name: Some("wg0".to_string()),
+            iface_index: Some(iface_index),
+            peers: peers
+                .iter()
+                .map(|(key, rx, tx)| WireguardPeerStats {
+                    public_key: key.to_string(),
+                    rx_bytes: *rx,
+                    tx_bytes: *tx,
+                    last_handshake: None,
+                })
+                .collect(),
+        }
+    }
+
+    // Samples taken at given seconds since start
+    fn history(
+        samples: &[(u64, WireguardDeviceStats)],
+    ) -> WireguardStatsHistory {
+        let start = Instant::now();
+        let mut history = WireguardStatsHistory::new(samples.len());
+        for (secs, stats) in samples {
+            history.push_at(start + SECOND * (*secs as u32), stats.clone());
+        }
+        history
+    }
+
+    fn rate(rx: f64, tx: f64) -> Option<WireguardPeerRate> {
+        Some(WireguardPeerRate {
+            rx_bytes_per_sec: rx,
+            tx_bytes_per_sec: tx,
+        })
+    }
+
+    #[test]
+    fn capacity() {
+        let mut history = WireguardStatsHistory::new(0);
+        assert!(history.is_empty());
+        for i in 0..3 {
+            history.push(stats(i, &[]));
+        }
+        assert_eq!(history.len(), 2);
+        assert_eq!(
+            history
+                .samples()
+                .map(|(_, s)| s.iface_index)
+                .collect::<Vec<_>>(),
+            [Some(1), Some(2)]
+        );
+        assert_eq!(history.latest().unwrap().iface_index, Some(2));
+    }
+
+    #[test]
+    fn rate_over_window() {
+        let history = history(&[
+            (0, stats(1, &[("a", 0, 0)])),
+            (10, stats(1, &[("a", 1000, 100), ("b", 0, 0)])),
+            (20, stats(1, &[("a", 3000, 200), ("b", 500, 50)])),
+            (30, stats(1, &[("a", 6000, 300), ("b", 1500, 450)])),
+        ]);
+        assert_eq!(history.rate_over("a", SECOND * 30), rate(200.0, 10.0));
+        // Window only covers last two samples
+        assert_eq!(history.rate_over("a", SECOND * 15), rate(300.0, 10.0));
+        // Peer absent in oldest sample is counted once it shows up
+        assert_eq!(history.rate_over("b", SECOND * 30), rate(50.0, 15.0));
+        assert_eq!(history.rate_over("a", SECOND * 5), None);
+        assert_eq!(history.rate_over("c", SECOND * 30), None);
+        assert_eq!(
+            history.top_peers_by_rx_rate(SECOND * 30, 1),
+            [("a".to_string(), rate(200.0, 10.0).unwrap())]
+        );
+        assert_eq!(
+            history
+                .top_peers_by_tx_rate(SECOND * 15, 5)
+                .into_iter()
+                .map(|(k, _)| k)
+                .collect::<Vec<_>>(),
+            ["b", "a"]
+        );
+    }
+
+    #[test]
+    fn rate_over_counter_reset() {
+        let history = history(&[
+            (0, stats(1, &[("a", 5000, 5000)])),
+            (10, stats(1, &[("a", 6000, 6000)])),
+            // Counters restarted from zero
+            (20, stats(1, &[("a", 500, 500)])),
+            // Interface recreated, counters happen to be larger
+            (30, stats(2, &[("a", 1500, 1500)])),
+        ]);
+        // 1000 + 500 + 1500 over 30 seconds
+        assert_eq!(history.rate_over("a", SECOND * 30), rate(100.0, 100.0));
+    }
+
+    #[test]
+    fn rate_over_peer_removed() {
+        let history =
+            history(&[(0, stats(1, &[("a", 0, 0)])), (10, stats(1, &[]))]);
+        assert_eq!(history.rate_over("a", SECOND * 10), None);
+        assert_eq!(history.ewma_rate("a", SECOND * 10), None);
+    }
+
+    #[test]
+    fn ewma_rate() {
+        let samples = [
+            (0, stats(1, &[("a", 0, 0)])),
+            (10, stats(1, &[("a", 1000, 0)])),
+            (20, stats(1, &[("a", 3000, 0)])),
+        ];
+        let history = history(&samples);
+        // First rate is 100/s then 200/s weighted by 1 - e^-1
+        let alpha = 1.0 - (-1.0f64).exp();
+        assert_eq!(
+            history.ewma_rate("a", SECOND * 10),
+            rate(100.0 + alpha * 100.0, 0.0)
+        );
+        // Zero time constant means the latest rate only
+        assert_eq!(history.ewma_rate("a", Duration::ZERO), rate(200.0, 0.0));
+
+        let history = super::tests::history(&samples[..1]);
+        assert_eq!(history.ewma_rate("a", SECOND * 10), None);
+    }
+}
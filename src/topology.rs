@@ -0,0 +1,388 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    net::{IpAddr, SocketAddr},
+};
+
+use crate::{
+    ErrorKind, WireguardError, WireguardIpAddress, WireguardIpPool,
+    WireguardParsed, WireguardPeerParsed,
+};
+
+// Keep NAT mapping of spokes alive, the value recommended by wg(8)
+const SPOKE_KEEPALIVE: u16 = 25;
+
+/// Node of wireguard network used by topology generators
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct WireguardTopologyNode {
+    /// Unique name of node
+    pub name: String,
+    /// Base64 encoded public key
+    pub public_key: String,
+    /// Endpoint other nodes connect to, `None` if behind NAT
+    pub endpoint: Option<SocketAddr>,
+    /// Tunnel address of node
+    pub address: Option<IpAddr>,
+}
+
+impl WireguardTopologyNode {
+    pub fn new(name: &str, public_key: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            public_key: public_key.to_string(),
+            endpoint: None,
+            address: None,
+        }
+    }
+
+    pub(crate) fn to_peer(
+        &self,
+        allowed_ips: Vec<WireguardIpAddress>,
+    ) -> WireguardPeerParsed {
+        WireguardPeerParsed {
+            public_key: Some(self.public_key.clone()),
+            endpoint: self.endpoint,
+            allowed_ips: Some(allowed_ips),
+            ..Default::default()
+        }
+    }
+}
+
+/// Generate the full-mesh configuration of every node indexed by node name,
+/// each node has all other nodes as peers with the host route of their
+/// tunnel address as allowed IP.
+/// Private keys are not included.
+pub fn full_mesh(
+    nodes: &[WireguardTopologyNode],
+) -> Result<BTreeMap<String, WireguardParsed>, WireguardError> {
+    validate_nodes(nodes)?;
+    let mut routes = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let address = node.address.ok_or_else(|| {
+            WireguardError::new(
+                ErrorKind::InvalidConfig,
+                format!("Node {} has no tunnel address", node.name),
+                None,
+            )
+        })?;
+        routes.push(WireguardIpAddress::host(address));
+    }
+    let mut ret = BTreeMap::new();
+    for node in nodes {
+        let peers = nodes
+            .iter()
+            .zip(routes.iter())
+            .filter(|(n, _)| n.name != node.name)
+            .map(|(n, route)| n.to_peer(vec![route.clone()]))
+            .collect();
+        ret.insert(
+            node.name.clone(),
+            WireguardParsed {
+                public_key: Some(node.public_key.clone()),
+                listen_port: node.endpoint.map(|e| e.port()),
+                peers: Some(peers),
+                ..Default::default()
+            },
+        );
+    }
+    Ok(ret)
+}
+
+/// Generate hub-and-spoke configuration of every node indexed by node name:
+///  * Hub has one peer per spoke with the host route of spoke tunnel address as
+///    allowed IP.
+///  * Spokes have the hub as only peer, routing the whole subnet of `pool` to
+///    it with persistent keepalive of 25 seconds.
+///
+/// Nodes without tunnel address get one allocated from `pool` which is
+/// stored back into `hub` and `spokes`, existing addresses are reserved in
+/// `pool` hence must be in its subnet. Hub endpoint is mandatory.
+/// Private keys are not included.
+pub fn hub_and_spoke(
+    hub: &mut WireguardTopologyNode,
+    spokes: &mut [WireguardTopologyNode],
+    pool: &mut WireguardIpPool,
+) -> Result<BTreeMap<String, WireguardParsed>, WireguardError> {
+    let hub_endpoint = hub.endpoint.ok_or_else(|| {
+        WireguardError::new(
+            ErrorKind::InvalidConfig,
+            format!("Hub {} has no endpoint", hub.name),
+            None,
+        )
+    })?;
+    let mut all_nodes = vec![hub.clone()];
+    all_nodes.extend_from_slice(spokes);
+    validate_nodes(&all_nodes)?;
+
+    // Reserve all existing addresses before allocating new ones
+    for addr in all_nodes.iter().filter_map(|n| n.address) {
+        pool.reserve(addr)?;
+    }
+    for node in std::iter::once(&mut *hub).chain(spokes.iter_mut()) {
+        if node.address.is_none() {
+            node.address = Some(pool.allocate()?.ip_addr);
+        }
+    }
+
+    let subnet = WireguardIpAddress {
+        flags: None,
+        ip_addr: pool.subnet().ip_addr,
+        prefix_length: pool.subnet().prefix_length,
+    };
+    let mut ret = BTreeMap::new();
+    let mut hub_peers = Vec::with_capacity(spokes.len());
+    for spoke in spokes.iter() {
+        if let Some(addr) = spoke.address {
+            hub_peers.push(spoke.to_peer(vec![WireguardIpAddress::host(addr)]));
+        }
+        ret.insert(
+            spoke.name.clone(),
+            WireguardParsed {
+                public_key: Some(spoke.public_key.clone()),
+                listen_port: spoke.endpoint.map(|e| e.port()),
+                peers: Some(vec![WireguardPeerParsed {
+                    persistent_keepalive: Some(SPOKE_KEEPALIVE),
+                    ..hub.to_peer(vec![subnet.clone()])
+                }]),
+                ..Default::default()
+            },
+        );
+    }
+    ret.insert(
+        hub.name.clone(),
+        WireguardParsed {
+            public_key: Some(hub.public_key.clone()),
+            listen_port: Some(hub_endpoint.port()),
+            peers: Some(hub_peers),
+            ..Default::default()
+        },
+    );
+    Ok(ret)
+}
+
+pub(crate) fn validate_nodes(
+    nodes: &[WireguardTopologyNode],
+) -> Result<(), WireguardError> {
+    let mut names = HashSet::new();
+    let mut keys = HashSet::new();
+    let mut addrs = HashSet::new();
+    for node in nodes {
+        let duplicate = if !names.insert(node.name.as_str()) {
+            Some("name")
+        } else if !keys.insert(node.public_key.as_str()) {
+            Some("public key")
+        } else if node.address.map(|a| addrs.insert(a)) == Some(false) {
+            Some("tunnel address")
+        } else {
+            None
+        };
+        if let Some(prop) = duplicate {
+            return Err(WireguardError::new(
+                ErrorKind::InvalidConfig,
+                format!("Node {} has duplicate {prop}", node.name),
+                None,
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, address: Option<&str>) -> WireguardTopologyNode {
+        WireguardTopologyNode {
+            address: address.map(|a| a.parse().unwrap()),
+            ..WireguardTopologyNode::new(name, &format!("{name}-key"))
+        }
+    }
+
+    fn allowed_ips(peer: &WireguardPeerParsed) -> Vec<String> {
+        peer.allowed_ips
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .map(|i| i.to_string())
+            .collect()
+    }
+
+    fn peer_keys(config: &WireguardParsed) -> Vec<&str> {
+        config
+            .peers
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|p| p.public_key.as_deref())
+            .collect()
+    }
+
+    fn assert_duplicate(
+        result: Result<BTreeMap<String, WireguardParsed>, WireguardError>,
+        expected: &str,
+    ) {
+        let e = result.unwrap_err();
+        assert_eq!(e.kind, ErrorKind::InvalidConfig);
+        assert_eq!(e.msg, expected);
+    }
+
+    #[test]
+    fn full_mesh_nodes() {
+        let mut a = node("a", Some("10.0.0.1"));
+        a.endpoint = Some("192.0.2.1:51820".parse().unwrap());
+        let b = node("b", Some("fd00::2"));
+        let c = node("c", Some("10.0.0.3"));
+        let configs = full_mesh(&[a, b, c]).unwrap();
+
+        assert_eq!(configs.keys().collect::<Vec<_>>(), ["a", "b", "c"]);
+        let a = &configs["a"];
+        assert_eq!(a.public_key.as_deref(), Some("a-key"));
+        assert_eq!(a.listen_port, Some(51820));
+        assert_eq!(a.private_key, None);
+        assert_eq!(peer_keys(a), ["b-key", "c-key"]);
+        let peers = a.peers.as_deref().unwrap();
+        assert_eq!(allowed_ips(&peers[0]), ["fd00::2/128"]);
+        assert_eq!(allowed_ips(&peers[1]), ["10.0.0.3/32"]);
+        assert_eq!(peers[0].endpoint, None);
+        assert_eq!(peers[0].persistent_keepalive, None);
+
+        let b = &configs["b"];
+        assert_eq!(b.listen_port, None);
+        assert_eq!(peer_keys(b), ["a-key", "c-key"]);
+        let peers = b.peers.as_deref().unwrap();
+        assert_eq!(peers[0].endpoint, Some("192.0.2.1:51820".parse().unwrap()));
+        assert_eq!(allowed_ips(&peers[0]), ["10.0.0.1/32"]);
+        assert_eq!(peer_keys(&configs["c"]), ["a-key", "b-key"]);
+    }
+
+    #[test]
+    fn full_mesh_errors() {
+        assert_duplicate(
+            full_mesh(&[
+                node("a", Some("10.0.0.1")),
+                node("a", Some("10.0.0.2")),
+            ]),
+            "Node a has duplicate name",
+        );
+        let mut b = node("b", Some("10.0.0.2"));
+        b.public_key = "a-key".to_string();
+        assert_duplicate(
+            full_mesh(&[node("a", Some("10.0.0.1")), b]),
+            "Node b has duplicate public key",
+        );
+        assert_duplicate(
+            full_mesh(&[
+                node("a", Some("10.0.0.1")),
+                node("b", Some("10.0.0.1")),
+            ]),
+            "Node b has duplicate tunnel address",
+        );
+        assert_duplicate(
+            full_mesh(&[node("a", Some("10.0.0.1")), node("b", None)]),
+            "Node b has no tunnel address",
+        );
+    }
+
+    #[test]
+    fn hub_and_spoke_allocates_from_pool() {
+        let mut hub = node("hub", None);
+        hub.endpoint = Some("192.0.2.1:51820".parse().unwrap());
+        let mut spokes = [
+            node("s1", None),
+            node("s2", Some("10.0.0.2")),
+            node("s3", None),
+        ];
+        let mut pool = WireguardIpPool::new("10.0.0.0/29".parse().unwrap());
+        let configs = hub_and_spoke(&mut hub, &mut spokes, &mut pool).unwrap();
+
+        // Existing address is reserved before others are allocated
+        assert_eq!(hub.address, Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(spokes[0].address, Some("10.0.0.3".parse().unwrap()));
+        assert_eq!(spokes[1].address, Some("10.0.0.2".parse().unwrap()));
+        assert_eq!(spokes[2].address, Some("10.0.0.4".parse().unwrap()));
+        assert!(pool.is_allocated("10.0.0.4".parse().unwrap()));
+
+        assert_eq!(configs.len(), 4);
+        let hub_config = &configs["hub"];
+        assert_eq!(hub_config.listen_port, Some(51820));
+        assert_eq!(peer_keys(hub_config), ["s1-key", "s2-key", "s3-key"]);
+        let peers = hub_config.peers.as_deref().unwrap();
+        assert_eq!(allowed_ips(&peers[0]), ["10.0.0.3/32"]);
+        assert_eq!(allowed_ips(&peers[1]), ["10.0.0.2/32"]);
+        assert_eq!(allowed_ips(&peers[2]), ["10.0.0.4/32"]);
+        assert!(peers.iter().all(|p| p.persistent_keepalive.is_none()));
+    }
+
+    #[test]
+    fn hub_and_spoke_spoke_routes_subnet_to_hub() {
+        let mut hub = node("hub", Some("fd00::1"));
+        hub.endpoint = Some("[2001:db8::1]:51820".parse().unwrap());
+        let mut spokes = [node("s1", None), node("s2", None)];
+        let mut pool = WireguardIpPool::new("fd00::/120".parse().unwrap());
+        let configs = hub_and_spoke(&mut hub, &mut spokes, &mut pool).unwrap();
+
+        for spoke in ["s1", "s2"] {
+            let config = &configs[spoke];
+            assert_eq!(config.listen_port, None);
+            assert_eq!(peer_keys(config), ["hub-key"]);
+            let peer = &config.peers.as_deref().unwrap()[0];
+            assert_eq!(allowed_ips(peer), ["fd00::/120"]);
+            assert_eq!(peer.endpoint, hub.endpoint);
+            assert_eq!(peer.persistent_keepalive, Some(SPOKE_KEEPALIVE));
+        }
+    }
+
+    #[test]
+    fn hub_and_spoke_errors() {
+        let mut pool = WireguardIpPool::new("10.0.0.0/24".parse().unwrap());
+        let mut hub = node("hub", None);
+        let e = hub_and_spoke(&mut hub, &mut [], &mut pool).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::InvalidConfig);
+        assert_eq!(e.msg, "Hub hub has no endpoint");
+
+        hub.endpoint = Some("192.0.2.1:51820".parse().unwrap());
+        assert_duplicate(
+            hub_and_spoke(&mut hub, &mut [node("hub", None)], &mut pool),
+            "Node hub has duplicate name",
+        );
+        let mut spoke = node("s1", None);
+        spoke.public_key = "hub-key".to_string();
+        assert_duplicate(
+            hub_and_spoke(&mut hub, &mut [spoke], &mut pool),
+            "Node s1 has duplicate public key",
+        );
+        assert_duplicate(
+            hub_and_spoke(
+                &mut hub,
+                &mut [
+                    node("s1", Some("10.0.0.9")),
+                    node("s2", Some("10.0.0.9")),
+                ],
+                &mut pool,
+            ),
+            "Node s2 has duplicate tunnel address",
+        );
+        // Nothing allocated on failure
+        assert_eq!(hub.address, None);
+
+        let mut pool = WireguardIpPool::new("10.0.0.0/30".parse().unwrap());
+        let e = hub_and_spoke(
+            &mut hub,
+            &mut [node("s1", None), node("s2", None)],
+            &mut pool,
+        )
+        .unwrap_err();
+        assert_eq!(e.kind, ErrorKind::IpPoolExhausted);
+
+        let mut pool = WireguardIpPool::new("10.0.0.0/24".parse().unwrap());
+        let e = hub_and_spoke(
+            &mut hub,
+            &mut [node("s1", Some("10.1.0.1"))],
+            &mut pool,
+        )
+        .unwrap_err();
+        assert_eq!(e.kind, ErrorKind::InvalidConfig);
+    }
+}
@@ -0,0 +1,548 @@
+// SPDX-License-Identifier: MIT
+
+use std::{fmt::Write, net::SocketAddr, str::FromStr, time::Duration};
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+
+use crate::{
+    parsed::decode_key, peer_parsed::normalize_endpoint, ErrorKind,
+    WireguardError, WireguardIpAddress, WireguardParsed,
+    WireguardParsedAllowedIpFlags, WireguardParsedDeviceFlags,
+    WireguardParsedPeerFlags, WireguardPeerParsed,
+};
+
+impl WireguardParsed {
+    /// Generate the `set=1` request of the cross-platform userspace API
+    /// (UAPI) used by wireguard-go and boringtun, including the trailing
+    /// empty line. Keys are converted to hex and `iface_name` is ignored
+    /// as the UAPI socket is per interface.
+    pub fn to_uapi_set_string(&self) -> Result<String, WireguardError> {
+        let mut ret = String::from("set=1\n");
+        if let Some(key) = self.private_key.as_deref() {
+            writeln!(
+                ret,
+                "private_key={}",
+                to_hex(&decode_key("private_key", key)?)
+            )
+            .ok();
+        }
+        if let Some(port) = self.listen_port {
+            writeln!(ret, "listen_port={port}").ok();
+        }
+        if let Some(fwmark) = self.fwmark {
+            writeln!(ret, "fwmark={fwmark}").ok();
+        }
+        for flag in self.flags.as_deref().unwrap_or_default() {
+            match flag {
+                WireguardParsedDeviceFlags::ReplacePeers => {
+                    ret.push_str("replace_peers=true\n")
+                }
+                WireguardParsedDeviceFlags::Other(v) => {
+                    return Err(unsupported_flag("device", *v));
+                }
+            }
+        }
+        for peer in self.peers_iter() {
+            let key = peer.public_key.as_deref().ok_or_else(|| {
+                WireguardError::new(
+                    ErrorKind::InvalidConfig,
+                    "UAPI requires public key of every peer".to_string(),
+                    None,
+                )
+            })?;
+            writeln!(
+                ret,
+                "public_key={}",
+                to_hex(&decode_key("public_key", key)?)
+            )
+            .ok();
+            for flag in peer.flags.as_deref().unwrap_or_default() {
+                match flag {
+                    WireguardParsedPeerFlags::RemoveMe => {
+                        ret.push_str("remove=true\n")
+                    }
+                    WireguardParsedPeerFlags::UpdateOnly => {
+                        ret.push_str("update_only=true\n")
+                    }
+                    WireguardParsedPeerFlags::ReplaceAllowedIps => {
+                        ret.push_str("replace_allowed_ips=true\n")
+                    }
+                    WireguardParsedPeerFlags::Other(v) => {
+                        return Err(unsupported_flag("peer", *v));
+                    }
+                }
+            }
+            if let Some(key) = peer.preshared_key.as_deref() {
+                writeln!(
+                    ret,
+                    "preshared_key={}",
+                    to_hex(&decode_key("preshared_key", key)?)
+                )
+                .ok();
+            }
+            if let Some(endpoint) = peer.endpoint {
+                writeln!(ret, "endpoint={endpoint}").ok();
+            }
+            if let Some(v) = peer.persistent_keepalive {
+                writeln!(ret, "persistent_keepalive_interval={v}").ok();
+            }
+            for ip in peer.allowed_ips.as_deref().unwrap_or_default() {
+                let remove = ip
+                    .flags
+                    .as_deref()
+                    .unwrap_or_default()
+                    .contains(&WireguardParsedAllowedIpFlags::RemoveMe);
+                if remove {
+                    writeln!(ret, "remove_allowed_ip={ip}").ok();
+                } else {
+                    writeln!(ret, "allowed_ip={ip}").ok();
+                }
+            }
+            if let Some(v) = peer.protocol_version {
+                writeln!(ret, "protocol_version={v}").ok();
+            }
+        }
+        ret.push('\n');
+        Ok(ret)
+    }
+}
+
+impl WireguardParsed {
+    /// Parse the reply of `get=1` request of the cross-platform userspace
+    /// API (UAPI), parsing stops at the first empty line.
+    /// Non-zero `errno` is reported as [ErrorKind::CommandFailed] and
+    /// unknown keys are ignored.
+    pub fn from_uapi_get_string(reply: &str) -> Result<Self, WireguardError> {
+        let mut ret = Self::default();
+        let mut peers: Vec<WireguardPeerParsed> = Vec::new();
+        let mut handshake_sec = 0u64;
+        for line in reply.lines() {
+            if line.is_empty() {
+                break;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                WireguardError::new(
+                    ErrorKind::InvalidConfig,
+                    format!("Invalid UAPI line {line}: expecting key=value"),
+                    None,
+                )
+            })?;
+            if key == "public_key" {
+                handshake_sec = 0;
+                peers.push(WireguardPeerParsed {
+                    public_key: Some(hex_to_base64(key, value)?),
+                    ..Default::default()
+                });
+                continue;
+            }
+            let Some(peer) = peers.last_mut() else {
+                match key {
+                    "private_key" => {
+                        ret.private_key = Some(hex_to_base64(key, value)?)
+                    }
+                    "listen_port" => {
+                        ret.listen_port = Some(parse_num(key, value)?)
+                    }
+                    "fwmark" => ret.fwmark = Some(parse_num(key, value)?),
+                    "errno" => check_errno(value)?,
+                    _ => log::debug!("Ignoring unknown UAPI key {key}"),
+                }
+                continue;
+            };
+            match key {
+                "preshared_key" => {
+                    if value.bytes().any(|b| b != b'0') {
+                        peer.preshared_key = Some(hex_to_base64(key, value)?);
+                    }
+                }
+                "endpoint" => {
+                    peer.endpoint = Some(normalize_endpoint(
+                        SocketAddr::from_str(value)
+                            .map_err(|e| invalid_value(key, value, e))?,
+                    ))
+                }
+                "persistent_keepalive_interval" => {
+                    peer.persistent_keepalive = Some(parse_num(key, value)?)
+                }
+                "allowed_ip" => peer
+                    .allowed_ips
+                    .get_or_insert_with(Vec::new)
+                    .push(WireguardIpAddress::from_str(value)?),
+                "rx_bytes" => peer.rx_bytes = Some(parse_num(key, value)?),
+                "tx_bytes" => peer.tx_bytes = Some(parse_num(key, value)?),
+                "last_handshake_time_sec" => {
+                    handshake_sec = parse_num(key, value)?;
+                }
+                "last_handshake_time_nsec" => {
+                    let nsec: u32 = parse_num(key, value)?;
+                    if handshake_sec != 0 || nsec != 0 {
+                        peer.last_handshake =
+                            Some(Duration::new(handshake_sec, nsec));
+                    }
+                    handshake_sec = 0;
+                }
+                "protocol_version" => {
+                    peer.protocol_version = Some(parse_num(key, value)?)
+                }
+                "errno" => check_errno(value)?,
+                _ => log::debug!("Ignoring unknown UAPI key {key}"),
+            }
+        }
+        if !peers.is_empty() {
+            ret.peers = Some(peers);
+        }
+        Ok(ret)
+    }
+}
+
+fn check_errno(value: &str) -> Result<(), WireguardError> {
+    let errno: i32 = parse_num("errno", value)?;
+    if errno == 0 {
+        Ok(())
+    } else {
+        Err(WireguardError::new(
+            ErrorKind::CommandFailed,
+            format!("UAPI request failed with errno {errno}"),
+            None,
+        ))
+    }
+}
+
+fn hex_to_base64(key: &str, value: &str) -> Result<String, WireguardError> {
+    let invalid = || {
+        // Do not include the value as it might be private key
+        WireguardError::new(
+            ErrorKind::InvalidKey,
+            format!("Invalid {key}: expecting 64 hex characters"),
+            None,
+        )
+    };
+    if value.len() != 64 || !value.is_ascii() {
+        return Err(invalid());
+    }
+    let mut data = [0u8; 32];
+    for (i, b) in data.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16)
+            .map_err(|_| invalid())?;
+    }
+    Ok(BASE64_STANDARD.encode(data))
+}
+
+fn parse_num<T>(key: &str, value: &str) -> Result<T, WireguardError>
+where
+    T: FromStr,
+    <T as FromStr>::Err: std::fmt::Display,
+{
+    T::from_str(value).map_err(|e| invalid_value(key, value, e))
+}
+
+fn invalid_value(
+    key: &str,
+    value: &str,
+    e: impl std::fmt::Display,
+) -> WireguardError {
+    WireguardError::new(
+        ErrorKind::InvalidConfig,
+        format!("Invalid UAPI value {value} of {key}: {e}"),
+        None,
+    )
+}
+
+fn to_hex(data: &[u8]) -> String {
+    let mut ret = String::with_capacity(data.len() * 2);
+    for b in data {
+        write!(ret, "{b:02x}").ok();
+    }
+    ret
+}
+
+fn unsupported_flag(kind: &str, value: u32) -> WireguardError {
+    WireguardError::new(
+        ErrorKind::InvalidConfig,
+        format!("Unknown {kind} flag {value:#x} is not supported by UAPI"),
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // From the cross-platform userspace API documentation of wireguard
+    const PRIVATE_KEY_HEX: &str =
+        "e84b5a6d2717c1003a13b431570353dbaca9146cf150c5f8575680feba52027a";
+    const PRIVATE_KEY: &str = "6EtabScXwQA6E7QxVwNT26ypFGzxUMX4V1aA/rpSAno=";
+    const PEER1_KEY_HEX: &str =
+        "b85996fecc9c7f1fc6d2572a76eda11d59bcd20be8e543b15ce4bd85a8e75a33";
+    const PEER1_KEY: &str = "uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM=";
+    const PSK_HEX: &str =
+        "188515093e952f5f22e865cef3012e72f8b5f0b598ac0309d5dacce3b70fcf52";
+    const PSK: &str = "GIUVCT6VL18i6GXO8wEucvi18LWYrAMJ1drM47cPz1I=";
+    const PEER2_KEY_HEX: &str =
+        "58402e695ba1772b1cc9309755f043251ea77fdcf10fbe63989ceb7e19321376";
+    const PEER2_KEY: &str = "WEAuaVuhdyscyTCXVfBDJR6nf9zxD75jmJzrfhkyE3Y=";
+
+    #[test]
+    fn set_string() {
+        let config = WireguardParsed {
+            private_key: Some(PRIVATE_KEY.to_string()),
+            listen_port: Some(12912),
+            fwmark: Some(0),
+            flags: Some(vec![WireguardParsedDeviceFlags::ReplacePeers]),
+            ..WireguardParsed::new("wg0")
+        }
+        .with_peers([
+            WireguardPeerParsed {
+                public_key: Some(PEER1_KEY.to_string()),
+                preshared_key: Some(PSK.to_string()),
+                endpoint: Some("[abcd:23::33]:51820".parse().unwrap()),
+                allowed_ips: Some(vec!["192.168.4.4/32".parse().unwrap()]),
+                flags: Some(vec![WireguardParsedPeerFlags::ReplaceAllowedIps]),
+                ..Default::default()
+            },
+            WireguardPeerParsed {
+                public_key: Some(PEER2_KEY.to_string()),
+                persistent_keepalive: Some(111),
+                allowed_ips: Some(vec![WireguardIpAddress {
+                    flags: Some(vec![WireguardParsedAllowedIpFlags::RemoveMe]),
+                    ..WireguardIpAddress::from_str("192.168.4.6/32").unwrap()
+                }]),
+                protocol_version: Some(1),
+                // Runtime properties are not part of set request
+                rx_bytes: Some(2224),
+                tx_bytes: Some(38333),
+                last_handshake: Some(Duration::from_secs(1)),
+                ..Default::default()
+            },
+            WireguardPeerParsed {
+                public_key: Some(PEER1_KEY.to_string()),
+                flags: Some(vec![
+                    WireguardParsedPeerFlags::UpdateOnly,
+                    WireguardParsedPeerFlags::RemoveMe,
+                ]),
+                ..Default::default()
+            },
+        ]);
+        let expected = [
+            "set=1".to_string(),
+            format!("private_key={PRIVATE_KEY_HEX}"),
+            "listen_port=12912".to_string(),
+            "fwmark=0".to_string(),
+            "replace_peers=true".to_string(),
+            format!("public_key={PEER1_KEY_HEX}"),
+            "replace_allowed_ips=true".to_string(),
+            format!("preshared_key={PSK_HEX}"),
+            "endpoint=[abcd:23::33]:51820".to_string(),
+            "allowed_ip=192.168.4.4/32".to_string(),
+            format!("public_key={PEER2_KEY_HEX}"),
+            "persistent_keepalive_interval=111".to_string(),
+            "remove_allowed_ip=192.168.4.6/32".to_string(),
+            "protocol_version=1".to_string(),
+            format!("public_key={PEER1_KEY_HEX}"),
+            "update_only=true".to_string(),
+            "remove=true".to_string(),
+            String::new(),
+            String::new(),
+        ];
+        assert_eq!(config.to_uapi_set_string().unwrap(), expected.join("\n"));
+    }
+
+    #[test]
+    fn set_string_empty() {
+        assert_eq!(
+            WireguardParsed::new("wg0").to_uapi_set_string().unwrap(),
+            "set=1\n\n"
+        );
+    }
+
+    #[test]
+    fn set_string_errors() {
+        let no_key = WireguardParsed::default()
+            .with_peers([WireguardPeerParsed::default()]);
+        assert_eq!(
+            no_key.to_uapi_set_string().unwrap_err().kind,
+            ErrorKind::InvalidConfig
+        );
+
+        let bad_key = WireguardParsed {
+            private_key: Some("not a key".to_string()),
+            ..Default::default()
+        };
+        let e = bad_key.to_uapi_set_string().unwrap_err();
+        assert_eq!(e.kind, ErrorKind::InvalidKey);
+        assert!(!e.msg.contains("not a key"), "{e}");
+
+        let unknown_flag = WireguardParsed {
+            flags: Some(vec![WireguardParsedDeviceFlags::Other(0x80)]),
+            ..Default::default()
+        };
+        assert_eq!(
+            unknown_flag.to_uapi_set_string().unwrap_err().kind,
+            ErrorKind::InvalidConfig
+        );
+    }
+
+    // Example reply in the cross-platform userspace API documentation
+    fn xplatform_reply() -> String {
+        [
+            format!("private_key={PRIVATE_KEY_HEX}"),
+            "listen_port=12912".to_string(),
+            format!("public_key={PEER1_KEY_HEX}"),
+            format!("preshared_key={PSK_HEX}"),
+            "allowed_ip=192.168.4.4/32".to_string(),
+            "endpoint=[abcd:23::33%2]:51820".to_string(),
+            format!("public_key={PEER2_KEY_HEX}"),
+            "tx_bytes=38333".to_string(),
+            "rx_bytes=2224".to_string(),
+            "allowed_ip=192.168.4.6/32".to_string(),
+            "persistent_keepalive_interval=111".to_string(),
+            "endpoint=182.122.22.19:3233".to_string(),
+            "errno=0".to_string(),
+            String::new(),
+            String::new(),
+        ]
+        .join("\n")
+    }
+
+    #[test]
+    fn parse_xplatform_reply() {
+        let config =
+            WireguardParsed::from_uapi_get_string(&xplatform_reply()).unwrap();
+        assert_eq!(config.private_key.as_deref(), Some(PRIVATE_KEY));
+        assert_eq!(config.listen_port, Some(12912));
+        assert_eq!(config.fwmark, None);
+        let peers = config.peers.unwrap();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].public_key.as_deref(), Some(PEER1_KEY));
+        assert_eq!(peers[0].preshared_key.as_deref(), Some(PSK));
+        assert_eq!(
+            peers[0].endpoint,
+            Some("[abcd:23::33%2]:51820".parse().unwrap())
+        );
+        assert_eq!(
+            peers[0].allowed_ips,
+            Some(vec!["192.168.4.4/32".parse().unwrap()])
+        );
+        assert_eq!(peers[1].public_key.as_deref(), Some(PEER2_KEY));
+        assert_eq!(peers[1].preshared_key, None);
+        assert_eq!(peers[1].tx_bytes, Some(38333));
+        assert_eq!(peers[1].rx_bytes, Some(2224));
+        assert_eq!(peers[1].persistent_keepalive, Some(111));
+        assert_eq!(
+            peers[1].endpoint,
+            Some("182.122.22.19:3233".parse().unwrap())
+        );
+    }
+
+    // Layout of wireguard-go which always reports preshared key and
+    // handshake time of every peer.
+    #[test]
+    fn parse_wireguard_go_reply() {
+        let reply = [
+            format!("private_key={PRIVATE_KEY_HEX}"),
+            "listen_port=51820".to_string(),
+            "fwmark=4660".to_string(),
+            format!("public_key={PEER1_KEY_HEX}"),
+            format!("preshared_key={}", "0".repeat(64)),
+            "protocol_version=1".to_string(),
+            "endpoint=[::ffff:192.0.2.1]:51820".to_string(),
+            "last_handshake_time_sec=1700000000".to_string(),
+            "last_handshake_time_nsec=123456789".to_string(),
+            "tx_bytes=100".to_string(),
+            "rx_bytes=200".to_string(),
+            "persistent_keepalive_interval=0".to_string(),
+            "allowed_ip=10.0.0.2/32".to_string(),
+            "allowed_ip=fd00::2/128".to_string(),
+            format!("public_key={PEER2_KEY_HEX}"),
+            format!("preshared_key={PSK_HEX}"),
+            "protocol_version=1".to_string(),
+            "last_handshake_time_sec=0".to_string(),
+            "last_handshake_time_nsec=0".to_string(),
+            "tx_bytes=0".to_string(),
+            "rx_bytes=0".to_string(),
+            "persistent_keepalive_interval=25".to_string(),
+            "errno=0".to_string(),
+            String::new(),
+            // Content after empty line is not parsed
+            "listen_port=1".to_string(),
+        ]
+        .join("\n");
+        let config = WireguardParsed::from_uapi_get_string(&reply).unwrap();
+        assert_eq!(config.listen_port, Some(51820));
+        assert_eq!(config.fwmark, Some(0x1234));
+        let peers = config.peers.unwrap();
+        // All zero preshared key means unset
+        assert_eq!(peers[0].preshared_key, None);
+        assert_eq!(peers[0].protocol_version, Some(1));
+        // IPv4-mapped endpoint is normalized
+        assert_eq!(peers[0].endpoint, Some("192.0.2.1:51820".parse().unwrap()));
+        assert_eq!(
+            peers[0].last_handshake,
+            Some(Duration::new(1_700_000_000, 123_456_789))
+        );
+        assert_eq!(peers[0].persistent_keepalive, Some(0));
+        assert_eq!(peers[0].allowed_ips.as_ref().map(Vec::len), Some(2));
+        assert_eq!(peers[1].preshared_key.as_deref(), Some(PSK));
+        // Zero handshake time means never
+        assert_eq!(peers[1].last_handshake, None);
+        assert_eq!(peers[1].endpoint, None);
+        assert_eq!(peers[1].allowed_ips, None);
+    }
+
+    #[test]
+    fn parse_handshake_sec_not_carried_to_next_peer() {
+        let reply = [
+            format!("public_key={PEER1_KEY_HEX}"),
+            "last_handshake_time_sec=1700000000".to_string(),
+            format!("public_key={PEER2_KEY_HEX}"),
+            "last_handshake_time_nsec=5".to_string(),
+        ]
+        .join("\n");
+        let peers = WireguardParsed::from_uapi_get_string(&reply)
+            .unwrap()
+            .peers
+            .unwrap();
+        assert_eq!(peers[0].last_handshake, None);
+        assert_eq!(peers[1].last_handshake, Some(Duration::new(0, 5)));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = [
+            ("errno=22\n", ErrorKind::CommandFailed),
+            ("listen_port=51820\nerrno=-1\n", ErrorKind::CommandFailed),
+            ("listen_port\n", ErrorKind::InvalidConfig),
+            ("listen_port=65536\n", ErrorKind::InvalidConfig),
+            ("private_key=abcd\n", ErrorKind::InvalidKey),
+            (
+                "public_key=zz5996fecc9c7f1fc6d2572a76eda11d59bcd20be8e543b15ce4bd85a8e75a33\n",
+                ErrorKind::InvalidKey,
+            ),
+        ];
+        for (reply, kind) in cases {
+            assert_eq!(
+                WireguardParsed::from_uapi_get_string(reply)
+                    .unwrap_err()
+                    .kind,
+                kind,
+                "{reply}"
+            );
+        }
+        // Unknown keys are ignored
+        assert_eq!(
+            WireguardParsed::from_uapi_get_string("foo=bar\nerrno=0\n\n")
+                .unwrap(),
+            WireguardParsed::default()
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let config =
+            WireguardParsed::from_uapi_get_string(&xplatform_reply()).unwrap();
+        let set = config.to_uapi_set_string().unwrap();
+        let reply = set.strip_prefix("set=1\n").unwrap();
+        let parsed = WireguardParsed::from_uapi_get_string(reply).unwrap();
+        assert_eq!(parsed, config.without_runtime_fields());
+    }
+}
@@ -0,0 +1,293 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use futures_util::{Stream, StreamExt};
+
+use crate::{
+    runtime::sleep, ErrorKind, WireguardDeviceStats, WireguardError,
+    WireguardEvent, WireguardHandle, WireguardHandshakeAlert,
+    WireguardHandshakeMonitor, WireguardParsed, WireguardStatsHistory,
+};
+
+/// Poll statistics of a dynamic set of wireguard interfaces.
+///
+/// Interfaces can be added or removed at any time, even when the stream
+/// returned by [WireguardWatcher::stream()] is being consumed.
+/// The cloned watchers share the same set of interfaces.
+#[derive(Clone, Debug)]
+pub struct WireguardWatcher {
+    handle: WireguardHandle,
+    ifaces: Arc<Mutex<BTreeSet<String>>>,
+    interval: Duration,
+    history_capacity: usize,
+    histories: Arc<Mutex<HashMap<String, WireguardStatsHistory>>>,
+}
+
+impl WireguardWatcher {
+    /// Poll every interface once per `interval`.
+    pub fn new(handle: WireguardHandle, interval: Duration) -> Self {
+        Self {
+            handle,
+            ifaces: Arc::new(Mutex::new(BTreeSet::new())),
+            interval,
+            history_capacity: 0,
+            histories: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Keep last `capacity` statistics of each watched interface polled by
+    /// [WireguardWatcher::stream()], query them via
+    /// [WireguardWatcher::history()].
+    pub fn with_history(mut self, capacity: usize) -> Self {
+        self.history_capacity = capacity;
+        self
+    }
+
+    /// Copy of statistics history of specified interface, `None` if history
+    /// disabled or no statistics polled yet.
+    pub fn history(
+        &self,
+        iface_name: &str,
+    ) -> Result<Option<WireguardStatsHistory>, WireguardError> {
+        Ok(self.lock_histories()?.get(iface_name).cloned())
+    }
+
+    /// Start watching specified interface, return `false` if already
+    /// watched.
+    pub fn add(&self, iface_name: &str) -> Result<bool, WireguardError> {
+        Ok(self.lock()?.insert(iface_name.to_string()))
+    }
+
+    /// Stop watching specified interface, return `false` if not watched.
+    pub fn remove(&self, iface_name: &str) -> Result<bool, WireguardError> {
+        self.lock_histories()?.remove(iface_name);
+        Ok(self.lock()?.remove(iface_name))
+    }
+
+    /// Names of watched interfaces
+    pub fn interfaces(&self) -> Result<Vec<String>, WireguardError> {
+        Ok(self.lock()?.iter().cloned().collect())
+    }
+
+    /// Stream of interface name and its statistics (or the error of
+    /// querying it). Never ends.
+    pub fn stream(
+        &self,
+    ) -> impl Stream<Item = (String, Result<WireguardDeviceStats, WireguardError>)>
+    {
+        let watcher = self.clone();
+        let pending: VecDeque<String> = VecDeque::new();
+        futures_util::stream::unfold(
+            (watcher, pending, true),
+            |(mut watcher, mut pending, mut first_round)| async move {
+                loop {
+                    if pending.is_empty() {
+                        if first_round {
+                            first_round = false;
+                        } else {
+                            sleep(watcher.interval).await;
+                        }
+                        match watcher.interfaces() {
+                            Ok(ifaces) => pending.extend(ifaces),
+                            Err(e) => {
+                                log::error!("{e}");
+                            }
+                        }
+                        continue;
+                    }
+                    let next = match watcher.lock() {
+                        Ok(ifaces) => next_watched(&ifaces, &mut pending),
+                        Err(e) => {
+                            log::error!("{e}");
+                            pending.clear();
+                            None
+                        }
+                    };
+                    let Some(iface_name) = next else {
+                        continue;
+                    };
+                    let result = watcher.handle.get_stats(&iface_name).await;
+                    if let Ok(stats) = result.as_ref() {
+                        watcher.record(&iface_name, stats);
+                    }
+                    return Some((
+                        (iface_name, result),
+                        (watcher, pending, first_round),
+                    ));
+                }
+            },
+        )
+    }
+
+    /// Stream of handshake alerts generated by `monitor` from
+    /// [WireguardWatcher::stream()]. Query failures are logged and ignored.
+    pub fn handshake_alerts(
+        &self,
+        mut monitor: WireguardHandshakeMonitor,
+    ) -> impl Stream<Item = WireguardHandshakeAlert> {
+        self.stream()
+            .map(move |(iface_name, result)| match result {
+                Ok(stats) => monitor.update(&stats),
+                Err(e) => {
+                    log::warn!("Failed to query stats of {iface_name}: {e}");
+                    Vec::new()
+                }
+            })
+            .flat_map(futures_util::stream::iter)
+    }
+
+    /// Stream of [WireguardEvent] detected by comparing the full
+    /// configuration of each watched interface between polls. Query
+    /// failure is treated as interface removed. Never ends.
+    pub fn events(&self) -> impl Stream<Item = WireguardEvent> {
+        let watcher = self.clone();
+        let previous: HashMap<String, WireguardParsed> = HashMap::new();
+        futures_util::stream::unfold(
+            (watcher, previous, true),
+            |(mut watcher, mut previous, mut first_round)| async move {
+                if first_round {
+                    first_round = false;
+                } else {
+                    sleep(watcher.interval).await;
+                }
+                let ifaces = watcher.interfaces().unwrap_or_else(|e| {
+                    log::error!("{e}");
+                    Vec::new()
+                });
+                // Interfaces no longer watched are forgotten silently
+                previous.retain(|iface_name, _| ifaces.contains(iface_name));
+                let mut events = Vec::new();
+                for iface_name in ifaces {
+                    let current =
+                        match watcher.handle.get_by_name(&iface_name).await {
+                            Ok(c) => Some(c),
+                            Err(e) => {
+                                log::debug!(
+                                    "Failed to query {iface_name}: {e}"
+                                );
+                                None
+                            }
+                        };
+                    events.extend(WireguardEvent::diff(
+                        &iface_name,
+                        previous.get(&iface_name),
+                        current.as_ref(),
+                    ));
+                    match current {
+                        Some(c) => previous.insert(iface_name, c),
+                        None => previous.remove(&iface_name),
+                    };
+                }
+                Some((
+                    futures_util::stream::iter(events),
+                    (watcher, previous, first_round),
+                ))
+            },
+        )
+        .flatten()
+    }
+
+    fn record(&self, iface_name: &str, stats: &WireguardDeviceStats) {
+        if self.history_capacity == 0 {
+            return;
+        }
+        match self.lock_histories() {
+            Ok(mut histories) => histories
+                .entry(iface_name.to_string())
+                .or_insert_with(|| {
+                    WireguardStatsHistory::new(self.history_capacity)
+                })
+                .push(stats.clone()),
+            Err(e) => log::error!("{e}"),
+        }
+    }
+
+    fn lock_histories(
+        &self,
+    ) -> Result<
+        MutexGuard<'_, HashMap<String, WireguardStatsHistory>>,
+        WireguardError,
+    > {
+        self.histories.lock().map_err(|e| {
+            WireguardError::new(
+                ErrorKind::Bug,
+                format!("Statistics history of WireguardWatcher poisoned: {e}"),
+                None,
+            )
+        })
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, BTreeSet<String>>, WireguardError> {
+        self.ifaces.lock().map_err(|e| {
+            WireguardError::new(
+                ErrorKind::Bug,
+                format!("Interface list of WireguardWatcher poisoned: {e}"),
+                None,
+            )
+        })
+    }
+}
+
+// Pop the next interface of this round, skipping the ones removed from
+// `watched` since the round started. Interfaces added during the round are
+// not in `pending`, hence polled from next round.
+fn next_watched(
+    watched: &BTreeSet<String>,
+    pending: &mut VecDeque<String>,
+) -> Option<String> {
+    while let Some(iface_name) = pending.pop_front() {
+        if watched.contains(&iface_name) {
+            return Some(iface_name);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn add_and_remove_during_round() {
+        let mut watched: BTreeSet<String> =
+            names(&["wg0", "wg1", "wg2"]).into_iter().collect();
+        let mut pending: VecDeque<String> = watched.iter().cloned().collect();
+
+        assert_eq!(
+            next_watched(&watched, &mut pending).as_deref(),
+            Some("wg0")
+        );
+        watched.remove("wg1");
+        watched.insert("wg3".to_string());
+        assert_eq!(
+            next_watched(&watched, &mut pending).as_deref(),
+            Some("wg2")
+        );
+        assert_eq!(next_watched(&watched, &mut pending), None);
+        assert!(pending.is_empty());
+
+        // Next round polls the added interface
+        pending.extend(watched.iter().cloned());
+        let mut polled = Vec::new();
+        while let Some(iface_name) = next_watched(&watched, &mut pending) {
+            polled.push(iface_name);
+        }
+        assert_eq!(polled, names(&["wg0", "wg2", "wg3"]));
+    }
+
+    #[test]
+    fn all_removed_during_round() {
+        let mut pending: VecDeque<String> = names(&["wg0", "wg1"]).into();
+        assert_eq!(next_watched(&BTreeSet::new(), &mut pending), None);
+        assert!(pending.is_empty());
+    }
+}
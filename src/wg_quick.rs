@@ -0,0 +1,1205 @@
+// SPDX-License-Identifier: MIT
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    io::Write as _,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[cfg(feature = "connection")]
+use crate::runtime::unblock;
+use crate::{
+    ErrorKind, WireguardEndpoint, WireguardError, WireguardIpAddress,
+    WireguardParsed, WireguardParsedPeerFlags, WireguardPeerParsed,
+    WireguardQuickTable,
+};
+
+/// Configuration file used by `wg-quick`, e.g. `/etc/wireguard/wg0.conf`.
+///
+/// Besides the properties applied via netlink, `wg-quick` also manages
+/// interface properties like IP addresses and MTU.
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+#[non_exhaustive]
+pub struct WireguardQuickConfig {
+    /// Wireguard configuration applied via netlink
+    pub wireguard: WireguardParsed,
+    /// IP addresses assigned to interface, `Address` in `[Interface]`
+    /// section
+    pub addresses: Option<Vec<WireguardIpAddress>>,
+    /// `MTU` in `[Interface]` section
+    pub mtu: Option<u32>,
+    /// DNS servers, the IP addresses in `DNS` of `[Interface]` section
+    pub dns: Option<Vec<IpAddr>>,
+    /// DNS search domains, the non-IP entries in `DNS` of `[Interface]`
+    /// section
+    pub dns_search: Option<Vec<String>>,
+    /// `Table` in `[Interface]` section, `None` means
+    /// [WireguardQuickTable::Auto]
+    pub table: Option<WireguardQuickTable>,
+    /// `PreUp` commands, executed only by
+    /// [WireguardQuickConfig::run_hooks()]
+    pub pre_up: Option<Vec<String>>,
+    /// `PostUp` commands
+    pub post_up: Option<Vec<String>>,
+    /// `PreDown` commands
+    pub pre_down: Option<Vec<String>>,
+    /// `PostDown` commands
+    pub post_down: Option<Vec<String>>,
+    /// Arbitrary labels (e.g. owner, device name) of peers indexed by
+    /// base64 encoded public key. Stored as `#! key = value` comment lines
+    /// in `[Peer]` section which are ignored by `wg-quick`. Label keys
+    /// should not contain `=` and neither keys nor values should contain
+    /// line breaks.
+    pub peer_labels: Option<BTreeMap<String, BTreeMap<String, String>>>,
+    /// `Endpoint` of peers given as host name, indexed by base64 encoded
+    /// public key. Host names are not resolved when parsing, use
+    /// [WireguardQuickConfig::resolve_endpoints()] before applying
+    /// [WireguardQuickConfig::wireguard]. Endpoints given as IP address are
+    /// stored in [WireguardPeerParsed::endpoint] directly.
+    pub peer_endpoints: Option<BTreeMap<String, WireguardEndpoint>>,
+}
+
+const LABEL_PREFIX: &str = "#!";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Section {
+    None,
+    Interface,
+    Peer,
+}
+
+impl FromStr for WireguardQuickConfig {
+    type Err = WireguardError;
+
+    fn from_str(content: &str) -> Result<Self, WireguardError> {
+        let mut ret = Self::default();
+        let mut peers: Vec<WireguardPeerParsed> = Vec::new();
+        let mut labels: Vec<BTreeMap<String, String>> = Vec::new();
+        let mut endpoints: Vec<Option<WireguardEndpoint>> = Vec::new();
+        let mut section = Section::None;
+
+        for (line_no, line) in content.lines().enumerate() {
+            if let Some(label) = line.trim().strip_prefix(LABEL_PREFIX) {
+                if section == Section::Peer {
+                    if let (Some((key, value)), Some(peer_labels)) =
+                        (label.split_once('='), labels.last_mut())
+                    {
+                        peer_labels.insert(
+                            key.trim().to_string(),
+                            value.trim().to_string(),
+                        );
+                    }
+                }
+                continue;
+            }
+            let line = match line.split_once('#') {
+                Some((l, _)) => l,
+                None => line,
+            }
+            .trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.eq_ignore_ascii_case("[Interface]") {
+                section = Section::Interface;
+                continue;
+            } else if line.eq_ignore_ascii_case("[Peer]") {
+                section = Section::Peer;
+                peers.push(WireguardPeerParsed::default());
+                labels.push(BTreeMap::new());
+                endpoints.push(None);
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| {
+                    invalid_line(line_no, line, "expecting `key = value`")
+                })?;
+            match section {
+                Section::Interface => {
+                    ret.parse_interface_line(key, value).map_err(|e| {
+                        invalid_line(line_no, line, e.msg.as_str())
+                    })?;
+                }
+                Section::Peer => {
+                    if let (Some(peer), Some(endpoint)) =
+                        (peers.last_mut(), endpoints.last_mut())
+                    {
+                        parse_peer_line(peer, endpoint, key, value).map_err(
+                            |e| invalid_line(line_no, line, e.msg.as_str()),
+                        )?;
+                    }
+                }
+                Section::None => {
+                    return Err(invalid_line(
+                        line_no,
+                        line,
+                        "not in [Interface] or [Peer] section",
+                    ));
+                }
+            }
+        }
+        let peer_labels: BTreeMap<String, BTreeMap<String, String>> = peers
+            .iter()
+            .zip(labels)
+            .filter(|(_, l)| !l.is_empty())
+            .filter_map(|(p, l)| p.public_key.clone().map(|k| (k, l)))
+            .collect();
+        if !peer_labels.is_empty() {
+            ret.peer_labels = Some(peer_labels);
+        }
+        let peer_endpoints: BTreeMap<String, WireguardEndpoint> = peers
+            .iter()
+            .zip(endpoints)
+            .filter_map(|(p, e)| Some((p.public_key.clone()?, e?)))
+            .collect();
+        if !peer_endpoints.is_empty() {
+            ret.peer_endpoints = Some(peer_endpoints);
+        }
+        if !peers.is_empty() {
+            ret.wireguard.peers = Some(peers);
+        }
+        Ok(ret)
+    }
+}
+
+impl From<WireguardParsed> for WireguardQuickConfig {
+    fn from(wireguard: WireguardParsed) -> Self {
+        Self {
+            wireguard,
+            ..Default::default()
+        }
+    }
+}
+
+impl WireguardQuickConfig {
+    /// Generate the content of configuration file. Runtime properties like
+    /// traffic statistics are not included.
+    ///
+    /// To save the runtime state like `wg-quick save`, replace
+    /// [WireguardQuickConfig::wireguard] with the reply of
+    /// [crate::WireguardHandle::get_by_name()].
+    pub fn to_conf_string(&self) -> String {
+        let mut ret = String::new();
+        let wg = &self.wireguard;
+        ret.push_str("[Interface]\n");
+        if let Some(addrs) = self.addresses.as_ref() {
+            writeln!(ret, "Address = {}", join(addrs)).ok();
+        }
+        if let Some(mtu) = self.mtu {
+            writeln!(ret, "MTU = {mtu}").ok();
+        }
+        let dns: Vec<String> = self
+            .dns
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .map(|i| i.to_string())
+            .chain(self.dns_search.as_deref().unwrap_or_default().to_vec())
+            .collect();
+        if !dns.is_empty() {
+            writeln!(ret, "DNS = {}", dns.join(", ")).ok();
+        }
+        if let Some(table) = self.table.as_ref() {
+            writeln!(ret, "Table = {table}").ok();
+        }
+        for (key, cmds) in [
+            ("PreUp", &self.pre_up),
+            ("PostUp", &self.post_up),
+            ("PreDown", &self.pre_down),
+            ("PostDown", &self.post_down),
+        ] {
+            for cmd in cmds.as_deref().unwrap_or_default() {
+                writeln!(ret, "{key} = {cmd}").ok();
+            }
+        }
+        if let Some(port) = wg.listen_port {
+            writeln!(ret, "ListenPort = {port}").ok();
+        }
+        if let Some(fwmark) = wg.fwmark.filter(|m| *m != 0) {
+            writeln!(ret, "FwMark = {fwmark:#x}").ok();
+        }
+        if let Some(key) = wg.private_key.as_deref() {
+            writeln!(ret, "PrivateKey = {key}").ok();
+        }
+        for peer in wg.peers.as_deref().unwrap_or_default() {
+            ret.push_str("\n[Peer]\n");
+            if let Some(key) = peer.public_key.as_deref() {
+                writeln!(ret, "PublicKey = {key}").ok();
+                for (name, value) in self.labels(key).into_iter().flatten() {
+                    writeln!(ret, "{LABEL_PREFIX} {name} = {value}").ok();
+                }
+            }
+            if let Some(key) = peer.preshared_key.as_deref() {
+                writeln!(ret, "PresharedKey = {key}").ok();
+            }
+            if let Some(ips) = peer.allowed_ips.as_ref() {
+                writeln!(ret, "AllowedIPs = {}", join(ips)).ok();
+            }
+            if let Some(endpoint) = self.peer_endpoint(peer) {
+                writeln!(ret, "Endpoint = {endpoint}").ok();
+            }
+            if let Some(v) = peer.persistent_keepalive.filter(|v| *v != 0) {
+                writeln!(ret, "PersistentKeepalive = {v}").ok();
+            }
+        }
+        ret
+    }
+
+    /// Resolve host names in [WireguardQuickConfig::peer_endpoints] via
+    /// system resolver and store the first address into
+    /// [WireguardPeerParsed::endpoint] of each peer.
+    ///
+    /// This blocks on DNS lookup, use
+    /// [WireguardQuickConfig::resolve_endpoints_async()] in async code.
+    pub fn resolve_endpoints(&mut self) -> Result<(), WireguardError> {
+        let resolved = resolve_all(self.peer_endpoints.clone())?;
+        self.set_resolved_endpoints(resolved);
+        Ok(())
+    }
+
+    /// Like [WireguardQuickConfig::resolve_endpoints()], but DNS lookup is
+    /// done in a thread for blocking work so the async runtime is not
+    /// stalled.
+    #[cfg(feature = "connection")]
+    pub async fn resolve_endpoints_async(
+        &mut self,
+    ) -> Result<(), WireguardError> {
+        if self.peer_endpoints.is_none() {
+            return Ok(());
+        }
+        let endpoints = self.peer_endpoints.clone();
+        let resolved = unblock(move || resolve_all(endpoints)).await?;
+        self.set_resolved_endpoints(resolved);
+        Ok(())
+    }
+
+    fn set_resolved_endpoints(&mut self, resolved: Vec<(String, SocketAddr)>) {
+        for (public_key, addr) in resolved {
+            if let Some(peer) = self
+                .wireguard
+                .peers
+                .iter_mut()
+                .flatten()
+                .find(|p| p.public_key.as_ref() == Some(&public_key))
+            {
+                peer.endpoint = Some(addr);
+            }
+        }
+    }
+
+    // Host name endpoint is preferred so the resolved address is not
+    // persisted
+    pub(crate) fn peer_endpoint(
+        &self,
+        peer: &WireguardPeerParsed,
+    ) -> Option<WireguardEndpoint> {
+        peer.public_key
+            .as_ref()
+            .and_then(|k| self.peer_endpoints.as_ref()?.get(k).cloned())
+            .or_else(|| peer.endpoint.map(WireguardEndpoint::from))
+    }
+
+    /// Labels of peer with specified base64 encoded public key
+    pub fn labels(
+        &self,
+        public_key: &str,
+    ) -> Option<&BTreeMap<String, String>> {
+        self.peer_labels.as_ref()?.get(public_key)
+    }
+
+    /// Base64 encoded public keys of peers having label `name`, regardless
+    /// of label value.
+    pub fn peers_with_label<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        self.peer_labels
+            .iter()
+            .flatten()
+            .filter(move |(_, labels)| labels.contains_key(name))
+            .map(|(public_key, _)| public_key.as_str())
+    }
+
+    /// Minimal configuration for [crate::WireguardHandle::set()] removing
+    /// peers having label `name` from the interface, other peers are
+    /// untouched. This configuration itself is not modified.
+    pub fn remove_peers_with_label(&self, name: &str) -> WireguardParsed {
+        self.peers_change_for_label(name, |peer| {
+            peer.flags = Some(vec![WireguardParsedPeerFlags::RemoveMe]);
+        })
+    }
+
+    /// Minimal configuration for [crate::WireguardHandle::set()] changing
+    /// persistent keepalive of peers having label `name` to `interval`
+    /// seconds (0 for disabling), other peers are untouched. Peers not
+    /// existing in interface are not created.
+    pub fn set_keepalive_for_label(
+        &self,
+        name: &str,
+        interval: u16,
+    ) -> WireguardParsed {
+        self.peers_change_for_label(name, |peer| {
+            peer.persistent_keepalive = Some(interval);
+            peer.flags = Some(vec![WireguardParsedPeerFlags::UpdateOnly]);
+        })
+    }
+
+    fn peers_change_for_label<F>(
+        &self,
+        name: &str,
+        change: F,
+    ) -> WireguardParsed
+    where
+        F: Fn(&mut WireguardPeerParsed),
+    {
+        let peers = self.peers_with_label(name).map(|public_key| {
+            let mut peer = WireguardPeerParsed::new(public_key);
+            change(&mut peer);
+            peer
+        });
+        WireguardParsed {
+            iface_name: self.wireguard.iface_name.clone(),
+            ..Default::default()
+        }
+        .with_peers(peers)
+    }
+
+    /// Generate the configuration for WireGuard client applications, e.g.
+    /// the mobile apps. Unlike [WireguardQuickConfig::to_conf_string()],
+    /// fail if private key, IP address or public key of any peer is
+    /// missing, as those applications refuse to import such configuration.
+    pub fn client_config_string(&self) -> Result<String, WireguardError> {
+        if self.wireguard.private_key.is_none() {
+            return Err(WireguardError::new(
+                ErrorKind::InvalidConfig,
+                "Client configuration requires private key".to_string(),
+                None,
+            ));
+        }
+        if self.addresses.as_ref().map(Vec::is_empty).unwrap_or(true) {
+            return Err(WireguardError::new(
+                ErrorKind::InvalidConfig,
+                "Client configuration requires IP address".to_string(),
+                None,
+            ));
+        }
+        if self
+            .wireguard
+            .peers
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .any(|p| p.public_key.is_none())
+        {
+            return Err(WireguardError::new(
+                ErrorKind::InvalidConfig,
+                "Client configuration requires public key of every peer"
+                    .to_string(),
+                None,
+            ));
+        }
+        Ok(self.to_conf_string())
+    }
+
+    /// Render [WireguardQuickConfig::client_config_string()] as QR code in
+    /// SVG format for scanning by WireGuard mobile apps.
+    #[cfg(feature = "qrcode")]
+    pub fn client_config_qr_svg(&self) -> Result<String, WireguardError> {
+        let code =
+            qrcode::QrCode::new(self.client_config_string()?).map_err(|e| {
+                WireguardError::new(
+                    ErrorKind::InvalidConfig,
+                    format!("Failed to generate QR code: {e}"),
+                    None,
+                )
+            })?;
+        Ok(code.render::<qrcode::render::svg::Color>().build())
+    }
+
+    /// Write configuration file with permission `0600` as it contains
+    /// private key. The file is replaced atomically, so readers never see
+    /// partially written content.
+    pub fn write(&self, path: &Path) -> Result<(), WireguardError> {
+        write_file(path, self.to_conf_string().as_bytes())
+    }
+
+    /// Like [WireguardQuickConfig::write()], but the file content is the
+    /// output of `encrypt` taking the configuration in plain text, so
+    /// private keys are never stored in plaintext on disk. Read it back
+    /// via [WireguardQuickConfig::read_encrypted()].
+    pub fn write_encrypted<F>(
+        &self,
+        path: &Path,
+        encrypt: F,
+    ) -> Result<(), WireguardError>
+    where
+        F: FnOnce(&[u8]) -> Result<Vec<u8>, WireguardError>,
+    {
+        write_file(path, &encrypt(self.to_conf_string().as_bytes())?)
+    }
+
+    /// Read and parse specified configuration file. The interface name is
+    /// set from file name, e.g. `wg0` for `/etc/wireguard/wg0.conf`.
+    pub fn read(path: &Path) -> Result<Self, WireguardError> {
+        let mut ret = Self::from_str(&read_file(path)?)?;
+        ret.set_iface_name_from_path(path);
+        Ok(ret)
+    }
+
+    /// Read file written by [WireguardQuickConfig::write_encrypted()],
+    /// `decrypt` should return the configuration in plain text.
+    pub fn read_encrypted<F>(
+        path: &Path,
+        decrypt: F,
+    ) -> Result<Self, WireguardError>
+    where
+        F: FnOnce(&[u8]) -> Result<Vec<u8>, WireguardError>,
+    {
+        let content = std::fs::read(path).map_err(|e| {
+            WireguardError::new(
+                ErrorKind::IoError,
+                format!("Failed to read {}: {e}", path.display()),
+                None,
+            )
+        })?;
+        let content = String::from_utf8(decrypt(&content)?).map_err(|_| {
+            WireguardError::new(
+                ErrorKind::InvalidConfig,
+                format!("Decrypted {} is not valid UTF-8", path.display()),
+                None,
+            )
+        })?;
+        let mut ret = Self::from_str(&content)?;
+        ret.set_iface_name_from_path(path);
+        Ok(ret)
+    }
+
+    /// Parse configuration after replacing every `${NAME}` in `template`
+    /// with the value of `NAME` in `vars`, or environment variable `NAME`
+    /// if not found in `vars`. This allows one template to provision many
+    /// similar nodes, e.g. with `Endpoint = ${HUB}:${PORT}` or
+    /// `PostUp = wg set %i private-key /etc/wireguard/${NODE}.key`.
+    ///
+    /// `NAME` should only contain ASCII alphanumeric characters and
+    /// underscore, other `${...}` (e.g. `${VAR:-default}` of shell) are
+    /// kept untouched. Undefined variable or value containing line break
+    /// is treated as error.
+    pub fn from_template(
+        template: &str,
+        vars: &HashMap<String, String>,
+    ) -> Result<Self, WireguardError> {
+        Self::from_str(&substitute(template, vars)?)
+    }
+
+    /// Read specified template file and parse it by
+    /// [WireguardQuickConfig::from_template()]. The interface name is set
+    /// from file name like [WireguardQuickConfig::read()].
+    pub fn read_template(
+        path: &Path,
+        vars: &HashMap<String, String>,
+    ) -> Result<Self, WireguardError> {
+        let mut ret = Self::from_template(&read_file(path)?, vars)?;
+        ret.set_iface_name_from_path(path);
+        Ok(ret)
+    }
+
+    fn set_iface_name_from_path(&mut self, path: &Path) {
+        self.wireguard.iface_name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_string());
+    }
+
+    fn parse_interface_line(
+        &mut self,
+        key: &str,
+        value: &str,
+    ) -> Result<(), WireguardError> {
+        match key.to_ascii_lowercase().as_str() {
+            "privatekey" => {
+                self.wireguard.private_key = Some(value.to_string())
+            }
+            "listenport" => {
+                self.wireguard.listen_port = Some(parse_num(value)?)
+            }
+            "fwmark" => {
+                self.wireguard.fwmark = Some(if value == "off" {
+                    0
+                } else if let Some(hex) = value.strip_prefix("0x") {
+                    u32::from_str_radix(hex, 16)
+                        .map_err(|e| invalid_value(value, e))?
+                } else {
+                    parse_num(value)?
+                })
+            }
+            "address" => {
+                let addresses = self.addresses.get_or_insert_with(Vec::new);
+                for addr in value.split(',') {
+                    addresses.push(WireguardIpAddress::from_str(addr)?);
+                }
+            }
+            "mtu" => self.mtu = Some(parse_num(value)?),
+            "dns" => {
+                for entry in value.split(',').map(str::trim) {
+                    if let Ok(ip) = IpAddr::from_str(entry) {
+                        self.dns.get_or_insert_with(Vec::new).push(ip);
+                    } else if !entry.is_empty() {
+                        self.dns_search
+                            .get_or_insert_with(Vec::new)
+                            .push(entry.to_string());
+                    }
+                }
+            }
+            "preup" => push_cmd(&mut self.pre_up, value),
+            "postup" => push_cmd(&mut self.post_up, value),
+            "predown" => push_cmd(&mut self.pre_down, value),
+            "postdown" => push_cmd(&mut self.post_down, value),
+            "table" => self.table = Some(WireguardQuickTable::from_str(value)?),
+            "saveconfig" => {
+                log::debug!("Ignoring unsupported wg-quick option {key}");
+            }
+            _ => {
+                return Err(WireguardError::new(
+                    ErrorKind::InvalidConfig,
+                    format!("Unknown key {key}"),
+                    None,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, WireguardError> {
+    std::fs::read_to_string(path).map_err(|e| {
+        WireguardError::new(
+            ErrorKind::IoError,
+            format!("Failed to read {}: {e}", path.display()),
+            None,
+        )
+    })
+}
+
+// Write to temporary file of permission `0600` (as the content might contain
+// private key) in the same directory, then rename it to `path`.
+fn write_file(path: &Path, content: &[u8]) -> Result<(), WireguardError> {
+    let tmp_path = tmp_path_of(path);
+    // Left behind by crashed process of the same PID
+    std::fs::remove_file(&tmp_path).ok();
+    let result = write_new_file(&tmp_path, content)
+        .and_then(|()| std::fs::rename(&tmp_path, path));
+    if result.is_err() {
+        std::fs::remove_file(&tmp_path).ok();
+    }
+    result.map_err(|e| {
+        WireguardError::new(
+            ErrorKind::IoError,
+            format!("Failed to write {}: {e}", path.display()),
+            None,
+        )
+    })
+}
+
+fn tmp_path_of(path: &Path) -> PathBuf {
+    let mut file_name = std::ffi::OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(file_name)
+}
+
+fn write_new_file(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut fd = options.open(path)?;
+    fd.write_all(content)?;
+    fd.sync_all()
+}
+
+fn substitute(
+    template: &str,
+    vars: &HashMap<String, String>,
+) -> Result<String, WireguardError> {
+    let mut ret = String::new();
+    let mut rest = template;
+    while let Some(pos) = rest.find("${") {
+        ret.push_str(&rest[..pos]);
+        let after = &rest[pos + 2..];
+        let name = after.split_once('}').map(|(n, _)| n).filter(|n| {
+            !n.is_empty()
+                && n.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        let Some(name) = name else {
+            ret.push_str("${");
+            rest = after;
+            continue;
+        };
+        let value = match vars.get(name) {
+            Some(v) => v.clone(),
+            None => std::env::var(name).map_err(|_| {
+                WireguardError::new(
+                    ErrorKind::InvalidConfig,
+                    format!("Undefined template variable {name}"),
+                    None,
+                )
+            })?,
+        };
+        if value.contains(['\n', '\r']) {
+            return Err(WireguardError::new(
+                ErrorKind::InvalidConfig,
+                format!("Template variable {name} contains line break"),
+                None,
+            ));
+        }
+        ret.push_str(&value);
+        rest = &after[name.len() + 1..];
+    }
+    ret.push_str(rest);
+    Ok(ret)
+}
+
+fn push_cmd(cmds: &mut Option<Vec<String>>, value: &str) {
+    cmds.get_or_insert_with(Vec::new).push(value.to_string())
+}
+
+fn join(ips: &[WireguardIpAddress]) -> String {
+    ips.iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn parse_peer_line(
+    peer: &mut WireguardPeerParsed,
+    host_endpoint: &mut Option<WireguardEndpoint>,
+    key: &str,
+    value: &str,
+) -> Result<(), WireguardError> {
+    match key.to_ascii_lowercase().as_str() {
+        "publickey" => peer.public_key = Some(value.to_string()),
+        "presharedkey" => peer.preshared_key = Some(value.to_string()),
+        "allowedips" => {
+            let ips = peer.allowed_ips.get_or_insert_with(Vec::new);
+            for ip in value.split(',').filter(|i| !i.trim().is_empty()) {
+                ips.push(WireguardIpAddress::from_str(ip)?);
+            }
+        }
+        // Host name is resolved later by resolve_endpoints()
+        "endpoint" => match WireguardEndpoint::from_str(value)? {
+            WireguardEndpoint::Addr(addr) => {
+                peer.endpoint = Some(addr);
+                *host_endpoint = None;
+            }
+            host => {
+                peer.endpoint = None;
+                *host_endpoint = Some(host);
+            }
+        },
+        "persistentkeepalive" => {
+            peer.persistent_keepalive =
+                Some(if value == "off" { 0 } else { parse_num(value)? })
+        }
+        _ => {
+            return Err(WireguardError::new(
+                ErrorKind::InvalidConfig,
+                format!("Unknown key {key}"),
+                None,
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Host name is resolved to its first address like `wg` does.
+fn resolve_all(
+    endpoints: Option<BTreeMap<String, WireguardEndpoint>>,
+) -> Result<Vec<(String, SocketAddr)>, WireguardError> {
+    endpoints
+        .into_iter()
+        .flatten()
+        .map(|(public_key, endpoint)| Ok((public_key, endpoint.resolve()?)))
+        .collect()
+}
+
+fn parse_num<T>(value: &str) -> Result<T, WireguardError>
+where
+    T: FromStr,
+    <T as FromStr>::Err: std::fmt::Display,
+{
+    T::from_str(value).map_err(|e| invalid_value(value, e))
+}
+
+fn invalid_value(value: &str, e: impl std::fmt::Display) -> WireguardError {
+    WireguardError::new(
+        ErrorKind::InvalidConfig,
+        format!("Invalid value {value}: {e}"),
+        None,
+    )
+}
+
+fn invalid_line(line_no: usize, line: &str, reason: &str) -> WireguardError {
+    // Do not include the line content as it might contain keys
+    let key = line.split('=').next().unwrap_or_default().trim();
+    WireguardError::new(
+        ErrorKind::InvalidConfig,
+        format!("Invalid line {} ({key}): {reason}", line_no + 1),
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIVATE_KEY: &str = "6EtabScXwQA6E7QxVwNT26ypFGzxUMX4V1aA/rpSAno=";
+    const PEER1_KEY: &str = "uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM=";
+    const PEER2_KEY: &str = "WEAuaVuhdyscyTCXVfBDJR6nf9zxD75jmJzrfhkyE3Y=";
+    const PSK: &str = "GIUVCT6VL18i6GXO8wEucvi18LWYrAMJ1drM47cPz1I=";
+
+    // Join lines with trailing line break, replacing `<NAME>` of keys above
+    fn conf(lines: &[&str]) -> String {
+        let mut ret = lines.join("\n");
+        ret.push('\n');
+        ret.replace("<PRIVATE_KEY>", PRIVATE_KEY)
+            .replace("<PEER1_KEY>", PEER1_KEY)
+            .replace("<PEER2_KEY>", PEER2_KEY)
+            .replace("<PSK>", PSK)
+    }
+
+    fn parse(content: &str) -> WireguardQuickConfig {
+        WireguardQuickConfig::from_str(content).unwrap()
+    }
+
+    fn parse_err(content: &str) -> WireguardError {
+        WireguardQuickConfig::from_str(content).unwrap_err()
+    }
+
+    fn ips(ips: &[&str]) -> Vec<WireguardIpAddress> {
+        ips.iter().map(|i| i.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn parse_full() {
+        let config = parse(&conf(&[
+            "# Managed by hand",
+            "[Interface]",
+            "Address = 10.0.0.1/24, fd00::1/64",
+            "Address = 10.1.0.1/24",
+            "ListenPort = 51820",
+            "PrivateKey = <PRIVATE_KEY>",
+            "MTU = 1420",
+            "DNS = 10.0.0.53, fd00::53, example.com",
+            "Table = 1234",
+            "PreUp = echo pre-up",
+            "PostUp = iptables -A FORWARD -i %i -j ACCEPT",
+            "PostUp = echo second",
+            "PostDown = echo post-down",
+            "SaveConfig = true",
+            "",
+            "[Peer]",
+            "PublicKey = <PEER1_KEY> # laptop",
+            "PresharedKey = <PSK>",
+            "AllowedIPs = 10.0.0.2/32,fd00::2/128",
+            "AllowedIPs = 192.168.1.0/24",
+            "Endpoint = 192.0.2.1:51820",
+            "PersistentKeepalive = 25",
+            "",
+            "[peer]",
+            "publickey = <PEER2_KEY>",
+            "Endpoint = [2001:db8::1]:51821",
+            "AllowedIPs = ",
+        ]));
+        assert_eq!(
+            config.addresses,
+            Some(ips(&["10.0.0.1/24", "fd00::1/64", "10.1.0.1/24"]))
+        );
+        assert_eq!(config.mtu, Some(1420));
+        assert_eq!(
+            config.dns,
+            Some(vec![
+                "10.0.0.53".parse().unwrap(),
+                "fd00::53".parse().unwrap()
+            ])
+        );
+        assert_eq!(config.dns_search, Some(vec!["example.com".to_string()]));
+        assert_eq!(config.table, Some(WireguardQuickTable::Id(1234)));
+        assert_eq!(config.pre_up, Some(vec!["echo pre-up".to_string()]));
+        assert_eq!(
+            config.post_up,
+            Some(vec![
+                "iptables -A FORWARD -i %i -j ACCEPT".to_string(),
+                "echo second".to_string(),
+            ])
+        );
+        assert_eq!(config.pre_down, None);
+        assert_eq!(config.post_down, Some(vec!["echo post-down".to_string()]));
+
+        let wg = &config.wireguard;
+        assert_eq!(wg.iface_name, None);
+        assert_eq!(wg.listen_port, Some(51820));
+        assert_eq!(wg.private_key.as_deref(), Some(PRIVATE_KEY));
+        assert_eq!(wg.fwmark, None);
+        let peers = wg.peers.as_deref().unwrap();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].public_key.as_deref(), Some(PEER1_KEY));
+        assert_eq!(peers[0].preshared_key.as_deref(), Some(PSK));
+        assert_eq!(
+            peers[0].allowed_ips,
+            Some(ips(&["10.0.0.2/32", "fd00::2/128", "192.168.1.0/24"]))
+        );
+        assert_eq!(peers[0].endpoint, Some("192.0.2.1:51820".parse().unwrap()));
+        assert_eq!(peers[0].persistent_keepalive, Some(25));
+        assert_eq!(peers[1].public_key.as_deref(), Some(PEER2_KEY));
+        assert_eq!(
+            peers[1].endpoint,
+            Some("[2001:db8::1]:51821".parse().unwrap())
+        );
+        // Empty AllowedIPs means no allowed IP rather than unmanaged
+        assert_eq!(peers[1].allowed_ips, Some(Vec::new()));
+        assert_eq!(config.peer_labels, None);
+    }
+
+    #[test]
+    fn parse_fwmark() {
+        let cases = [
+            ("off", 0),
+            ("0", 0),
+            ("51820", 51820),
+            ("0xca6c", 0xca6c),
+            ("0x0", 0),
+        ];
+        for (value, expected) in cases {
+            let config = parse(&format!("[Interface]\nFwMark = {value}\n"));
+            assert_eq!(config.wireguard.fwmark, Some(expected), "{value}");
+        }
+        for value in ["0xzz", "-1", "4294967296", "on"] {
+            let e = parse_err(&format!("[Interface]\nFwMark = {value}\n"));
+            assert_eq!(e.kind, ErrorKind::InvalidConfig, "{value}");
+        }
+    }
+
+    #[test]
+    fn parse_persistent_keepalive_off() {
+        let config = parse(&conf(&[
+            "[Peer]",
+            "PublicKey = <PEER1_KEY>",
+            "PersistentKeepalive = off",
+        ]));
+        assert_eq!(
+            config.wireguard.peers.unwrap()[0].persistent_keepalive,
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn parse_labels() {
+        let config = parse(&conf(&[
+            "[Interface]",
+            "#! ignored = outside of peer",
+            "[Peer]",
+            "#! owner = alice",
+            "PublicKey = <PEER1_KEY>",
+            "#!device=laptop = work",
+            "#! no value",
+            "[Peer]",
+            "PublicKey = <PEER2_KEY>",
+            "[Peer]",
+            "#! owner = nobody, peer has no public key",
+        ]));
+        let mut expected = BTreeMap::new();
+        expected.insert("owner".to_string(), "alice".to_string());
+        expected.insert("device".to_string(), "laptop = work".to_string());
+        assert_eq!(config.labels(PEER1_KEY), Some(&expected));
+        assert_eq!(config.labels(PEER2_KEY), None);
+        assert_eq!(config.peer_labels.as_ref().map(BTreeMap::len), Some(1));
+        assert_eq!(
+            config.peers_with_label("owner").collect::<Vec<_>>(),
+            [PEER1_KEY]
+        );
+        assert_eq!(config.peers_with_label("missing").count(), 0);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = [
+            "ListenPort = 51820\n",
+            "[Interface]\nListenPort\n",
+            "[Interface]\nListenPort = 65536\n",
+            "[Interface]\nUnknown = 1\n",
+            "[Interface]\nAddress = 10.0.0.1/33\n",
+            "[Peer]\nAllowedIPs = 10.0.0.1/32, nonsense\n",
+            "[Peer]\nPersistentKeepalive = 1s\n",
+        ];
+        for content in cases {
+            let e = parse_err(content);
+            assert_eq!(e.kind, ErrorKind::InvalidConfig, "{content}");
+        }
+        // Key material never shows up in error
+        let e = parse_err(&conf(&["[Interface]", "PrivateKey <PRIVATE_KEY>"]));
+        assert!(!e.msg.contains(PRIVATE_KEY), "{e}");
+        assert!(e.msg.starts_with("Invalid line 2 "), "{e}");
+    }
+
+    #[test]
+    fn template() {
+        let mut vars = HashMap::new();
+        vars.insert("NODE".to_string(), "node1".to_string());
+        vars.insert("PORT".to_string(), "51820".to_string());
+        let template = conf(&[
+            "[Interface]",
+            "ListenPort = ${PORT}",
+            "PostUp = wg set %i private-key /etc/wireguard/${NODE}.key",
+            "PostUp = echo ${PATH}",
+            "PostDown = echo ${VAR:-default} ${} $${NODE}",
+        ]);
+        let config =
+            WireguardQuickConfig::from_template(&template, &vars).unwrap();
+        assert_eq!(config.wireguard.listen_port, Some(51820));
+        assert_eq!(
+            config.post_up,
+            Some(vec![
+                "wg set %i private-key /etc/wireguard/node1.key".to_string(),
+                format!("echo {}", std::env::var("PATH").unwrap()),
+            ])
+        );
+        assert_eq!(
+            config.post_down,
+            Some(vec!["echo ${VAR:-default} ${} $node1".to_string()])
+        );
+    }
+
+    #[test]
+    fn template_errors() {
+        let mut vars = HashMap::new();
+        vars.insert("INJECT".to_string(), "1\nPostUp = reboot".to_string());
+        let cases = [
+            "[Interface]\nListenPort = ${NL_WIREGUARD_UNDEFINED_VAR}\n",
+            "[Interface]\nListenPort = ${INJECT}\n",
+        ];
+        for template in cases {
+            let e = WireguardQuickConfig::from_template(template, &vars)
+                .unwrap_err();
+            assert_eq!(e.kind, ErrorKind::InvalidConfig, "{template}");
+        }
+    }
+
+    #[test]
+    fn to_conf_string() {
+        let mut config = WireguardQuickConfig::from(
+            WireguardParsed {
+                listen_port: Some(51820),
+                fwmark: Some(0x1234),
+                private_key: Some(PRIVATE_KEY.to_string()),
+                ..WireguardParsed::new("wg0")
+            }
+            .with_peers([WireguardPeerParsed {
+                public_key: Some(PEER1_KEY.to_string()),
+                preshared_key: Some(PSK.to_string()),
+                allowed_ips: Some(ips(&["10.0.0.2/32", "fd00::2/128"])),
+                endpoint: Some("192.0.2.1:51820".parse().unwrap()),
+                persistent_keepalive: Some(25),
+                // Runtime properties are not written
+                rx_bytes: Some(1),
+                ..Default::default()
+            }]),
+        );
+        config.addresses = Some(ips(&["10.0.0.1/24", "fd00::1/64"]));
+        config.mtu = Some(1420);
+        config.dns = Some(vec!["10.0.0.53".parse().unwrap()]);
+        config.dns_search = Some(vec!["example.com".to_string()]);
+        config.table = Some(WireguardQuickTable::Off);
+        config.post_up = Some(vec!["echo up".to_string()]);
+        let mut labels = BTreeMap::new();
+        labels.insert("owner".to_string(), "alice".to_string());
+        config.peer_labels =
+            Some([(PEER1_KEY.to_string(), labels)].into_iter().collect());
+
+        assert_eq!(
+            config.to_conf_string(),
+            conf(&[
+                "[Interface]",
+                "Address = 10.0.0.1/24, fd00::1/64",
+                "MTU = 1420",
+                "DNS = 10.0.0.53, example.com",
+                "Table = off",
+                "PostUp = echo up",
+                "ListenPort = 51820",
+                "FwMark = 0x1234",
+                "PrivateKey = <PRIVATE_KEY>",
+                "",
+                "[Peer]",
+                "PublicKey = <PEER1_KEY>",
+                "#! owner = alice",
+                "PresharedKey = <PSK>",
+                "AllowedIPs = 10.0.0.2/32, fd00::2/128",
+                "Endpoint = 192.0.2.1:51820",
+                "PersistentKeepalive = 25",
+            ])
+        );
+    }
+
+    #[test]
+    fn to_conf_string_omits_zero() {
+        let config = WireguardQuickConfig::from(
+            WireguardParsed {
+                fwmark: Some(0),
+                ..Default::default()
+            }
+            .with_peers([WireguardPeerParsed {
+                persistent_keepalive: Some(0),
+                ..WireguardPeerParsed::new(PEER1_KEY)
+            }]),
+        );
+        assert_eq!(
+            config.to_conf_string(),
+            conf(&["[Interface]", "", "[Peer]", "PublicKey = <PEER1_KEY>"])
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let lines = [
+            "[Interface]",
+            "Address = 10.0.0.1/24, fd00::1/64, 10.1.0.1/24",
+            "MTU = 1420",
+            "DNS = 10.0.0.53, example.com",
+            "Table = main",
+            "PreUp = echo 1",
+            "PostUp = echo 2",
+            "PreDown = echo 3",
+            "PostDown = echo 4",
+            "ListenPort = 51820",
+            "FwMark = 0xca6c",
+            "PrivateKey = <PRIVATE_KEY>",
+            "",
+            "[Peer]",
+            "PublicKey = <PEER1_KEY>",
+            "#! device = laptop",
+            "#! owner = alice",
+            "PresharedKey = <PSK>",
+            "AllowedIPs = 10.0.0.2/32, fd00::2/128",
+            "Endpoint = [2001:db8::1]:51820",
+            "PersistentKeepalive = 25",
+            "",
+            "[Peer]",
+            "PublicKey = <PEER2_KEY>",
+            "AllowedIPs = 0.0.0.0/0",
+        ];
+        let config = parse(&conf(&lines));
+        assert_eq!(config.to_conf_string(), conf(&lines));
+
+        // Multiple Address lines are merged into one
+        let mut split = lines.to_vec();
+        split.splice(
+            1..2,
+            ["Address = 10.0.0.1/24, fd00::1/64", "Address = 10.1.0.1/24"],
+        );
+        assert_eq!(parse(&conf(&split)), config);
+    }
+
+    #[test]
+    fn host_endpoint_kept_unresolved() {
+        let lines = [
+            "[Interface]",
+            "",
+            "[Peer]",
+            "PublicKey = <PEER1_KEY>",
+            "Endpoint = localhost:51820",
+            "",
+            "[Peer]",
+            "PublicKey = <PEER2_KEY>",
+            "Endpoint = 192.0.2.1:51820",
+        ];
+        let mut config = parse(&conf(&lines));
+        let peers = config.wireguard.peers.as_deref().unwrap();
+        assert_eq!(peers[0].endpoint, None);
+        assert_eq!(peers[1].endpoint, Some("192.0.2.1:51820".parse().unwrap()));
+        assert_eq!(
+            config.peer_endpoints,
+            Some(BTreeMap::from([(
+                PEER1_KEY.to_string(),
+                WireguardEndpoint::Host {
+                    host: "localhost".to_string(),
+                    port: 51820,
+                }
+            )]))
+        );
+        assert_eq!(config.to_conf_string(), conf(&lines));
+
+        config.resolve_endpoints().unwrap();
+        let endpoint = config.wireguard.peers.as_deref().unwrap()[0]
+            .endpoint
+            .unwrap();
+        assert!(endpoint.ip().is_loopback());
+        assert_eq!(endpoint.port(), 51820);
+        // Host name is still written instead of the resolved address
+        assert_eq!(config.to_conf_string(), conf(&lines));
+    }
+
+    #[cfg(feature = "tokio_socket")]
+    #[tokio::test]
+    async fn resolve_endpoints_async() {
+        let mut config = parse(&conf(&[
+            "[Peer]",
+            "PublicKey = <PEER1_KEY>",
+            "Endpoint = localhost:51820",
+        ]));
+        config.resolve_endpoints_async().await.unwrap();
+        let peers = config.wireguard.peers.as_deref().unwrap();
+        assert!(peers[0].endpoint.unwrap().ip().is_loopback());
+
+        let mut config = parse(&conf(&[
+            "[Peer]",
+            "PublicKey = <PEER1_KEY>",
+            "Endpoint = host.invalid:51820",
+        ]));
+        let e = config.resolve_endpoints_async().await.unwrap_err();
+        assert_eq!(e.kind, ErrorKind::InvalidConfig);
+    }
+
+    #[test]
+    fn write_replaces_file_with_private_permission() {
+        let dir = std::env::temp_dir()
+            .join(format!("nl-wireguard-wg-quick-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("wg0.conf");
+        std::fs::write(&path, "stale content which is much longer\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(
+                &path,
+                std::fs::Permissions::from_mode(0o644),
+            )
+            .unwrap();
+        }
+
+        let config =
+            parse(&conf(&["[Interface]", "PrivateKey = <PRIVATE_KEY>"]));
+        config.write(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            config.to_conf_string()
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // No temporary file left
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        let e = config
+            .write(&dir.join("missing").join("wg0.conf"))
+            .unwrap_err();
+        assert_eq!(e.kind, ErrorKind::IoError);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}